@@ -15,3 +15,7 @@ func wasmimport_NewResponseOutparam(result *cm.Tuple[ResponseOutparam, FutureInc
 //go:wasmimport wasiext:http/ext@0.1.0 new-incoming-request
 //go:noescape
 func wasmimport_NewIncomingRequest(req0 uint32) (result0 uint32)
+
+//go:wasmimport wasiext:http/ext@0.1.0 new-incoming-response
+//go:noescape
+func wasmimport_NewIncomingResponse(resp0 uint32) (result0 uint32)
@@ -28,6 +28,11 @@ type IncomingRequest = types.IncomingRequest
 // See [types.OutgoingRequest] for more information.
 type OutgoingRequest = types.OutgoingRequest
 
+// OutgoingResponse represents the imported type alias "wasiext:http/ext@0.1.0#outgoing-response".
+//
+// See [types.OutgoingResponse] for more information.
+type OutgoingResponse = types.OutgoingResponse
+
 // NewResponseOutparam represents the imported function "new-response-outparam".
 //
 //	new-response-outparam: func() -> tuple<response-outparam, future-incoming-response>
@@ -49,3 +54,15 @@ func NewIncomingRequest(req OutgoingRequest) (result IncomingRequest) {
 	result = cm.Reinterpret[IncomingRequest]((uint32)(result0))
 	return
 }
+
+// NewIncomingResponse represents the imported function "new-incoming-response".
+//
+//	new-incoming-response: func(resp: outgoing-response) -> future-incoming-response
+//
+//go:nosplit
+func NewIncomingResponse(resp OutgoingResponse) (result FutureIncomingResponse) {
+	resp0 := cm.Reinterpret[uint32](resp)
+	result0 := wasmimport_NewIncomingResponse((uint32)(resp0))
+	result = cm.Reinterpret[FutureIncomingResponse]((uint32)(result0))
+	return
+}
@@ -17,6 +17,28 @@ fn main() -> anyhow::Result<()> {
         crates.join("wadge-sys"),
         cbindgen::Config {
             language: cbindgen::Language::C,
+            enumeration: cbindgen::EnumConfig {
+                prefix_with_name: true,
+                ..Default::default()
+            },
+            // Enums cross the FFI boundary as `u32` and are not reachable from function signatures
+            export: cbindgen::ExportConfig {
+                include: [
+                    "CassetteMode",
+                    "Clock",
+                    "HttpAction",
+                    "HttpErrorCode",
+                    "HttpPolicy",
+                    "InheritEnv",
+                    "Input",
+                    "KeyValueBackend",
+                    "NetworkPolicy",
+                    "Output",
+                ]
+                .map(Into::into)
+                .into(),
+                ..Default::default()
+            },
             ..Default::default()
         },
     )
@@ -0,0 +1,236 @@
+use wasi_passthrough::bindings::exports::wasi::io::streams::{InputStream, OutputStream};
+
+mod bindings {
+    wit_bindgen::generate!({
+        inline: "
+            package wadge:passthrough-blobstore;
+
+            world imports {
+                import wasi:blobstore/blobstore@0.2.0-draft;
+            }
+        ",
+        path: "../../wit",
+        world: "wadge:passthrough-blobstore/imports",
+        with: {
+            "wasi:blobstore/blobstore@0.2.0-draft": generate,
+            "wasi:blobstore/container@0.2.0-draft": generate,
+            "wasi:blobstore/types@0.2.0-draft": generate,
+            "wasi:io/error@0.2.1": wasi_passthrough::bindings::wasi::io::error,
+            "wasi:io/poll@0.2.1": wasi_passthrough::bindings::wasi::io::poll,
+            "wasi:io/streams@0.2.1": wasi_passthrough::bindings::wasi::io::streams,
+        },
+        type_section_suffix: "wadge-passthrough-blobstore-imports",
+    });
+
+    pub mod exports {
+        // Interfaces exported by `wasi-passthrough`, which `blobstore` depends on, are listed
+        // explicitly for world merging to succeed regardless of custom section order
+        wit_bindgen::generate!({
+            inline: "
+                package wadge:passthrough-blobstore;
+
+                world exports {
+                    export wasi:io/error@0.2.1;
+                    export wasi:io/poll@0.2.1;
+                    export wasi:io/streams@0.2.1;
+                    export wasi:blobstore/types@0.2.0-draft;
+                    export wasi:blobstore/container@0.2.0-draft;
+                    export wasi:blobstore/blobstore@0.2.0-draft;
+                }
+            ",
+            path: "../../wit",
+            world: "wadge:passthrough-blobstore/exports",
+            with: {
+                "wasi:blobstore/blobstore@0.2.0-draft": generate,
+                "wasi:blobstore/container@0.2.0-draft": generate,
+                "wasi:blobstore/types@0.2.0-draft": generate,
+                "wasi:io/error@0.2.1": wasi_passthrough::bindings::exports::wasi::io::error,
+                "wasi:io/poll@0.2.1": wasi_passthrough::bindings::exports::wasi::io::poll,
+                "wasi:io/streams@0.2.1": wasi_passthrough::bindings::exports::wasi::io::streams,
+            },
+            type_section_suffix: "wadge-passthrough-blobstore-exports",
+        });
+
+        #[cfg(not(target_os = "linux"))]
+        type Component = ();
+
+        #[cfg(not(target_os = "linux"))]
+        export!(Component with_types_in self);
+    }
+}
+
+use bindings::exports::exports::wasi::blobstore as exports;
+use bindings::wasi::blobstore::{blobstore, container, types};
+
+impl From<types::ContainerMetadata> for exports::types::ContainerMetadata {
+    fn from(types::ContainerMetadata { name, created_at }: types::ContainerMetadata) -> Self {
+        Self { name, created_at }
+    }
+}
+
+impl From<types::ObjectMetadata> for exports::types::ObjectMetadata {
+    fn from(
+        types::ObjectMetadata {
+            name,
+            container,
+            created_at,
+            size,
+        }: types::ObjectMetadata,
+    ) -> Self {
+        Self {
+            name,
+            container,
+            created_at,
+            size,
+        }
+    }
+}
+
+impl From<exports::types::ObjectId> for types::ObjectId {
+    fn from(exports::types::ObjectId { container, object }: exports::types::ObjectId) -> Self {
+        Self { container, object }
+    }
+}
+
+impl exports::types::Guest for () {
+    type OutgoingValue = types::OutgoingValue;
+    type IncomingValue = types::IncomingValue;
+}
+
+impl exports::types::GuestOutgoingValue for types::OutgoingValue {
+    fn new_outgoing_value() -> exports::types::OutgoingValue {
+        exports::types::OutgoingValue::new(Self::new_outgoing_value())
+    }
+
+    fn outgoing_value_write_body(&self) -> Result<OutputStream, ()> {
+        Self::outgoing_value_write_body(self).map(OutputStream::new)
+    }
+
+    fn finish(this: exports::types::OutgoingValue) -> Result<(), exports::types::Error> {
+        Self::finish(this.into_inner())
+    }
+}
+
+impl exports::types::GuestIncomingValue for types::IncomingValue {
+    fn incoming_value_consume_sync(
+        this: exports::types::IncomingValue,
+    ) -> Result<exports::types::IncomingValueSyncBody, exports::types::Error> {
+        Self::incoming_value_consume_sync(this.into_inner())
+    }
+
+    fn incoming_value_consume_async(
+        this: exports::types::IncomingValue,
+    ) -> Result<exports::types::IncomingValueAsyncBody, exports::types::Error> {
+        Self::incoming_value_consume_async(this.into_inner()).map(InputStream::new)
+    }
+
+    fn size(&self) -> u64 {
+        Self::size(self)
+    }
+}
+
+impl exports::container::Guest for () {
+    type Container = container::Container;
+    type StreamObjectNames = container::StreamObjectNames;
+}
+
+impl exports::container::GuestContainer for container::Container {
+    fn name(&self) -> Result<String, exports::types::Error> {
+        Self::name(self)
+    }
+
+    fn info(&self) -> Result<exports::types::ContainerMetadata, exports::types::Error> {
+        Self::info(self).map(Into::into)
+    }
+
+    fn get_data(
+        &self,
+        name: String,
+        start: u64,
+        end: u64,
+    ) -> Result<exports::types::IncomingValue, exports::types::Error> {
+        Self::get_data(self, &name, start, end).map(exports::types::IncomingValue::new)
+    }
+
+    fn write_data(
+        &self,
+        name: String,
+        data: exports::types::OutgoingValueBorrow<'_>,
+    ) -> Result<(), exports::types::Error> {
+        Self::write_data(self, &name, data.get())
+    }
+
+    fn list_objects(&self) -> Result<exports::container::StreamObjectNames, exports::types::Error> {
+        Self::list_objects(self).map(exports::container::StreamObjectNames::new)
+    }
+
+    fn delete_object(&self, name: String) -> Result<(), exports::types::Error> {
+        Self::delete_object(self, &name)
+    }
+
+    fn delete_objects(&self, names: Vec<String>) -> Result<(), exports::types::Error> {
+        Self::delete_objects(self, &names)
+    }
+
+    fn has_object(&self, name: String) -> Result<bool, exports::types::Error> {
+        Self::has_object(self, &name)
+    }
+
+    fn object_info(
+        &self,
+        name: String,
+    ) -> Result<exports::types::ObjectMetadata, exports::types::Error> {
+        Self::object_info(self, &name).map(Into::into)
+    }
+
+    fn clear(&self) -> Result<(), exports::types::Error> {
+        Self::clear(self)
+    }
+}
+
+impl exports::container::GuestStreamObjectNames for container::StreamObjectNames {
+    fn read_stream_object_names(
+        &self,
+        len: u64,
+    ) -> Result<(Vec<String>, bool), exports::types::Error> {
+        Self::read_stream_object_names(self, len)
+    }
+
+    fn skip_stream_object_names(&self, num: u64) -> Result<(u64, bool), exports::types::Error> {
+        Self::skip_stream_object_names(self, num)
+    }
+}
+
+impl exports::blobstore::Guest for () {
+    fn create_container(
+        name: String,
+    ) -> Result<exports::container::Container, exports::types::Error> {
+        blobstore::create_container(&name).map(exports::container::Container::new)
+    }
+
+    fn get_container(name: String) -> Result<exports::container::Container, exports::types::Error> {
+        blobstore::get_container(&name).map(exports::container::Container::new)
+    }
+
+    fn delete_container(name: String) -> Result<(), exports::types::Error> {
+        blobstore::delete_container(&name)
+    }
+
+    fn container_exists(name: String) -> Result<bool, exports::types::Error> {
+        blobstore::container_exists(&name)
+    }
+
+    fn copy_object(
+        src: exports::types::ObjectId,
+        dest: exports::types::ObjectId,
+    ) -> Result<(), exports::types::Error> {
+        blobstore::copy_object(&src.into(), &dest.into())
+    }
+
+    fn move_object(
+        src: exports::types::ObjectId,
+        dest: exports::types::ObjectId,
+    ) -> Result<(), exports::types::Error> {
+        blobstore::move_object(&src.into(), &dest.into())
+    }
+}
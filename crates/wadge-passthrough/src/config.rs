@@ -0,0 +1,63 @@
+mod bindings {
+    wit_bindgen::generate!({
+        inline: "
+            package wadge:passthrough-config;
+
+            world imports {
+                import wasi:config/store@0.2.0-draft;
+            }
+        ",
+        path: "../../wit",
+        world: "wadge:passthrough-config/imports",
+        with: {
+            "wasi:config/store@0.2.0-draft": generate,
+        },
+        type_section_suffix: "wadge-passthrough-config-imports",
+    });
+
+    pub mod exports {
+        wit_bindgen::generate!({
+            inline: "
+                package wadge:passthrough-config;
+
+                world exports {
+                    export wasi:config/store@0.2.0-draft;
+                }
+            ",
+            path: "../../wit",
+            world: "wadge:passthrough-config/exports",
+            with: {
+                "wasi:config/store@0.2.0-draft": generate,
+            },
+            type_section_suffix: "wadge-passthrough-config-exports",
+        });
+
+        #[cfg(not(target_os = "linux"))]
+        type Component = ();
+
+        #[cfg(not(target_os = "linux"))]
+        export!(Component with_types_in self);
+    }
+}
+
+use bindings::exports::exports::wasi::config::store::{Error, Guest};
+use bindings::wasi::config::store;
+
+impl From<store::Error> for Error {
+    fn from(err: store::Error) -> Self {
+        match err {
+            store::Error::Upstream(err) => Self::Upstream(err),
+            store::Error::Io(err) => Self::Io(err),
+        }
+    }
+}
+
+impl Guest for () {
+    fn get(key: String) -> Result<Option<String>, Error> {
+        store::get(&key).map_err(Into::into)
+    }
+
+    fn get_all() -> Result<Vec<(String, String)>, Error> {
+        store::get_all().map_err(Into::into)
+    }
+}
@@ -0,0 +1,89 @@
+use wasi_passthrough::bindings::exports::wasi::http::types::{
+    FutureIncomingResponse, IncomingRequest, OutgoingRequest, OutgoingResponse, ResponseOutparam,
+};
+
+mod bindings {
+    wit_bindgen::generate!({
+        inline: "
+            package wadge:passthrough-ext;
+
+            world imports {
+                import wasiext:http/ext@0.1.0;
+            }
+        ",
+        path: "../../wit",
+        world: "wadge:passthrough-ext/imports",
+        with: {
+            "wasi:clocks/monotonic-clock@0.2.1": wasi_passthrough::bindings::wasi::clocks::monotonic_clock,
+            "wasi:http/types@0.2.1": wasi_passthrough::bindings::wasi::http::types,
+            "wasi:io/error@0.2.1": wasi_passthrough::bindings::wasi::io::error,
+            "wasi:io/poll@0.2.1": wasi_passthrough::bindings::wasi::io::poll,
+            "wasi:io/streams@0.2.1": wasi_passthrough::bindings::wasi::io::streams,
+            "wasiext:http/ext@0.1.0": generate,
+        },
+        type_section_suffix: "wadge-passthrough-ext-imports",
+    });
+
+    pub mod exports {
+        // Interfaces exported by `wasi-passthrough`, which `ext` depends on, are listed
+        // explicitly for world merging to succeed regardless of custom section order
+        wit_bindgen::generate!({
+            inline: "
+                package wadge:passthrough-ext;
+
+                world exports {
+                    export wasi:clocks/monotonic-clock@0.2.1;
+                    export wasi:filesystem/types@0.2.1;
+                    export wasi:http/types@0.2.1;
+                    export wasi:io/error@0.2.1;
+                    export wasi:io/poll@0.2.1;
+                    export wasi:io/streams@0.2.1;
+                    export wasi:sockets/tcp@0.2.1;
+                    export wasi:sockets/udp@0.2.1;
+                    export wasiext:http/ext@0.1.0;
+                }
+            ",
+            path: "../../wit",
+            world: "wadge:passthrough-ext/exports",
+            with: {
+                "wasi:clocks/monotonic-clock@0.2.1": wasi_passthrough::bindings::exports::wasi::clocks::monotonic_clock,
+                "wasi:clocks/wall-clock@0.2.1": wasi_passthrough::bindings::wasi::clocks::wall_clock,
+                "wasi:filesystem/types@0.2.1": wasi_passthrough::bindings::exports::wasi::filesystem::types,
+                "wasi:http/types@0.2.1": wasi_passthrough::bindings::exports::wasi::http::types,
+                "wasi:io/error@0.2.1": wasi_passthrough::bindings::exports::wasi::io::error,
+                "wasi:io/poll@0.2.1": wasi_passthrough::bindings::exports::wasi::io::poll,
+                "wasi:io/streams@0.2.1": wasi_passthrough::bindings::exports::wasi::io::streams,
+                "wasi:sockets/network@0.2.1": wasi_passthrough::bindings::wasi::sockets::network,
+                "wasi:sockets/tcp@0.2.1": wasi_passthrough::bindings::exports::wasi::sockets::tcp,
+                "wasi:sockets/udp@0.2.1": wasi_passthrough::bindings::exports::wasi::sockets::udp,
+                "wasiext:http/ext@0.1.0": generate,
+            },
+            type_section_suffix: "wadge-passthrough-ext-exports",
+        });
+
+        #[cfg(not(target_os = "linux"))]
+        type Component = ();
+
+        #[cfg(not(target_os = "linux"))]
+        export!(Component with_types_in self);
+    }
+}
+
+impl bindings::exports::exports::wasiext::http::ext::Guest for () {
+    fn new_response_outparam() -> (ResponseOutparam, FutureIncomingResponse) {
+        let (out, res) = bindings::wasiext::http::ext::new_response_outparam();
+        (ResponseOutparam::new(out), FutureIncomingResponse::new(res))
+    }
+
+    fn new_incoming_request(req: OutgoingRequest) -> IncomingRequest {
+        IncomingRequest::new(bindings::wasiext::http::ext::new_incoming_request(
+            req.into_inner(),
+        ))
+    }
+
+    fn new_incoming_response(resp: OutgoingResponse) -> FutureIncomingResponse {
+        FutureIncomingResponse::new(bindings::wasiext::http::ext::new_incoming_response(
+            resp.into_inner(),
+        ))
+    }
+}
@@ -1,4 +1,8 @@
 use wasi_passthrough as _;
-use wasi_passthrough_ext as _;
 use wasi_passthrough_keyvalue as _;
 use wasi_passthrough_logging as _;
+
+mod blobstore;
+mod config;
+mod ext;
+mod messaging;
@@ -0,0 +1,176 @@
+mod bindings {
+    wit_bindgen::generate!({
+        inline: "
+            package wadge:passthrough-messaging;
+
+            world imports {
+                import wasi:messaging/producer@0.2.0-draft;
+                import wasi:messaging/request-reply@0.2.0-draft;
+            }
+        ",
+        path: "../../wit",
+        world: "wadge:passthrough-messaging/imports",
+        with: {
+            "wasi:messaging/producer@0.2.0-draft": generate,
+            "wasi:messaging/request-reply@0.2.0-draft": generate,
+            "wasi:messaging/types@0.2.0-draft": generate,
+        },
+        type_section_suffix: "wadge-passthrough-messaging-imports",
+    });
+
+    pub mod exports {
+        wit_bindgen::generate!({
+            inline: "
+                package wadge:passthrough-messaging;
+
+                world exports {
+                    export wasi:messaging/types@0.2.0-draft;
+                    export wasi:messaging/producer@0.2.0-draft;
+                    export wasi:messaging/request-reply@0.2.0-draft;
+                }
+            ",
+            path: "../../wit",
+            world: "wadge:passthrough-messaging/exports",
+            with: {
+                "wasi:messaging/producer@0.2.0-draft": generate,
+                "wasi:messaging/request-reply@0.2.0-draft": generate,
+                "wasi:messaging/types@0.2.0-draft": generate,
+            },
+            type_section_suffix: "wadge-passthrough-messaging-exports",
+        });
+
+        #[cfg(not(target_os = "linux"))]
+        type Component = ();
+
+        #[cfg(not(target_os = "linux"))]
+        export!(Component with_types_in self);
+    }
+}
+
+use bindings::exports::exports::wasi::messaging as exports;
+use bindings::wasi::messaging::{producer, request_reply, types};
+
+impl From<types::Error> for exports::types::Error {
+    fn from(err: types::Error) -> Self {
+        match err {
+            types::Error::Timeout => Self::Timeout,
+            types::Error::Connection(err) => Self::Connection(err),
+            types::Error::PermissionDenied(err) => Self::PermissionDenied(err),
+            types::Error::Other(err) => Self::Other(err),
+        }
+    }
+}
+
+impl exports::types::Guest for () {
+    type Client = types::Client;
+    type Message = types::Message;
+}
+
+impl exports::types::GuestClient for types::Client {
+    fn connect(name: String) -> Result<exports::types::Client, exports::types::Error> {
+        let client = Self::connect(&name)?;
+        Ok(exports::types::Client::new(client))
+    }
+
+    fn disconnect(&self) -> Result<(), exports::types::Error> {
+        Self::disconnect(self)?;
+        Ok(())
+    }
+}
+
+impl exports::types::GuestMessage for types::Message {
+    fn new(data: Vec<u8>) -> Self {
+        Self::new(&data)
+    }
+
+    fn topic(&self) -> Option<exports::types::Topic> {
+        Self::topic(self)
+    }
+
+    fn content_type(&self) -> Option<String> {
+        Self::content_type(self)
+    }
+
+    fn set_content_type(&self, content_type: String) {
+        Self::set_content_type(self, &content_type);
+    }
+
+    fn data(&self) -> Vec<u8> {
+        Self::data(self)
+    }
+
+    fn set_data(&self, data: Vec<u8>) {
+        Self::set_data(self, &data);
+    }
+
+    fn metadata(&self) -> Option<exports::types::Metadata> {
+        Self::metadata(self)
+    }
+
+    fn add_metadata(&self, key: String, value: String) {
+        Self::add_metadata(self, &key, &value);
+    }
+
+    fn set_metadata(&self, meta: exports::types::Metadata) {
+        Self::set_metadata(self, &meta);
+    }
+
+    fn remove_metadata(&self, key: String) {
+        Self::remove_metadata(self, &key);
+    }
+}
+
+impl exports::producer::Guest for () {
+    fn send(
+        c: exports::types::ClientBorrow<'_>,
+        topic: exports::types::Topic,
+        message: exports::types::Message,
+    ) -> Result<(), exports::types::Error> {
+        producer::send(c.get(), &topic, message.into_inner())?;
+        Ok(())
+    }
+}
+
+impl exports::request_reply::Guest for () {
+    type RequestOptions = request_reply::RequestOptions;
+
+    fn request(
+        c: exports::types::ClientBorrow<'_>,
+        topic: exports::types::Topic,
+        message: exports::types::MessageBorrow<'_>,
+        options: Option<exports::request_reply::RequestOptions>,
+    ) -> Result<Vec<exports::types::Message>, exports::types::Error> {
+        let replies = request_reply::request(
+            c.get(),
+            &topic,
+            message.get(),
+            options.map(exports::request_reply::RequestOptions::into_inner),
+        )?;
+        Ok(replies
+            .into_iter()
+            .map(exports::types::Message::new)
+            .collect())
+    }
+
+    fn reply(
+        reply_to: exports::types::MessageBorrow<'_>,
+        message: exports::types::Message,
+    ) -> Result<(), exports::types::Error> {
+        request_reply::reply(reply_to.get(), message.into_inner())?;
+        Ok(())
+    }
+}
+
+impl exports::request_reply::GuestRequestOptions for request_reply::RequestOptions {
+    fn new() -> Self {
+        Self::new()
+    }
+
+    fn set_timeout_ms(&self, timeout_ms: u32) {
+        Self::set_timeout_ms(self, timeout_ms);
+    }
+
+    fn set_expected_replies(&self, expected_replies: u32) {
+        Self::set_expected_replies(self, expected_replies);
+    }
+}
@@ -0,0 +1,106 @@
+use core::net::{Ipv4Addr, SocketAddr};
+use core::num::NonZeroUsize;
+
+use std::env;
+use std::fs;
+use std::thread;
+
+use anyhow::{bail, Context as _};
+use tracing_subscriber::EnvFilter;
+
+const USAGE: &str = "usage: wadge-serve [--port <PORT>] [--concurrency <N>] [--har <PATH>] <WASM>";
+
+struct Args {
+    port: u16,
+    concurrency: NonZeroUsize,
+    har: Option<String>,
+    wasm: String,
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut port = 8080;
+    let mut concurrency = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+    let mut har = None;
+    let mut wasm = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" | "-p" => {
+                let v = args.next().context("`--port` requires a value")?;
+                port = v.parse().with_context(|| format!("invalid port `{v}`"))?;
+            }
+            "--concurrency" | "-c" => {
+                let v = args.next().context("`--concurrency` requires a value")?;
+                concurrency = v
+                    .parse()
+                    .with_context(|| format!("invalid concurrency `{v}`"))?;
+            }
+            "--har" => {
+                har = Some(args.next().context("`--har` requires a value")?);
+            }
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ if wasm.is_none() && !arg.starts_with('-') => wasm = Some(arg),
+            _ => bail!("unexpected argument `{arg}`\n{USAGE}"),
+        }
+    }
+    let wasm = wasm.with_context(|| format!("component path missing\n{USAGE}"))?;
+    Ok(Args {
+        port,
+        concurrency,
+        har,
+        wasm,
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .without_time()
+        .with_env_filter(EnvFilter::from_env("WADGE_LOG"))
+        .init();
+
+    let Args {
+        port,
+        concurrency,
+        har,
+        wasm: path,
+    } = parse_args()?;
+    let wasm = fs::read(&path).with_context(|| format!("failed to read `{path}`"))?;
+    let har = har
+        .map(|har| {
+            wadge::Har::new(&har, wadge::Har::DEFAULT_BODY_LIMIT)
+                .with_context(|| format!("failed to create HAR log `{har}`"))
+        })
+        .transpose()?;
+    let engine = wasmtime::Engine::default();
+    // the component is compiled once and instantiated `concurrency` times
+    let pre = wadge::InstancePre::new(&engine, &wasm)
+        .with_context(|| format!("failed to compile `{path}`"))?;
+    // `wasi:keyvalue` buckets are shared by all instances
+    let keyvalue = wadge::KeyValueStore::new();
+    // `wasi:blobstore` containers are shared by all instances
+    let blobstore = wadge::BlobStore::new();
+    let config = move || wadge::Config {
+        inherit_env: wadge::InheritEnv::None,
+        args: vec![path.clone()],
+        stdin: wadge::Input::Empty,
+        har: har.clone(),
+        keyvalue: keyvalue.clone(),
+        blobstore: blobstore.clone(),
+        ..wadge::Config::default()
+    };
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+    rt.block_on(async {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("failed to bind `{addr}`"))?;
+        wadge::serve(listener, pre, concurrency, config).await
+    })
+}
@@ -4,7 +4,14 @@ use core::ptr::{self, null_mut};
 use std::ffi::CString;
 use std::sync::{LazyLock, Mutex};
 
-use crate::{call, instantiate, Config, Instance};
+use crate::{
+    blobstore_containers, blobstore_delete, blobstore_objects, blobstore_read, blobstore_write,
+    call, clock_advance, clock_set, clock_set_auto_advance, flush, fs_read, fs_write, instantiate,
+    keyvalue_buckets, keyvalue_delete, keyvalue_get, keyvalue_keys, keyvalue_set, messaging_handle,
+    messaging_published, output_take, replies_push, response_append_header, response_set_body,
+    response_set_error, response_set_status, stdin_close, stdin_push, CallStatus, Config, Instance,
+    List, Message, MessageVisitor,
+};
 
 static ERROR: LazyLock<Mutex<Option<CString>>> = LazyLock::new(Mutex::default);
 
@@ -51,14 +58,138 @@ pub extern "C" fn instance_free(instance: *mut c_void) {
     unsafe { drop(Box::from_raw(instance.cast::<Instance>())) }
 }
 
+/// Calls function `name` within `instance`.
+/// If the guest exits via `wasi:cli/exit` during the call, the exit code is written to
+/// `exit_code` and `CallStatus::Exit` is returned.
 #[no_mangle]
 pub extern "C" fn instance_call(
     instance_ptr: *mut c_void,
     instance: *const c_char,
     name: *const c_char,
     args: *const *mut c_void,
-) -> bool {
+    exit_code: *mut i32,
+) -> CallStatus {
     match call(instance_ptr, instance, name, args) {
+        Ok(()) => CallStatus::Ok,
+        Err(err) => {
+            if let Some(wadge::Exit { code }) = err.downcast_ref() {
+                if let Some(exit_code) = unsafe { exit_code.as_mut() } {
+                    *exit_code = *code;
+                }
+                return CallStatus::Exit;
+            }
+            store_error(err);
+            CallStatus::Error
+        }
+    }
+}
+
+/// Sets status code of the response populated by `OutgoingHttpHandler`, defaults to `200`
+#[no_mangle]
+pub extern "C" fn http_response_set_status(response: *mut c_void, status: u16) -> bool {
+    match response_set_status(response, status) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Appends a header to the response populated by `OutgoingHttpHandler`
+#[no_mangle]
+pub extern "C" fn http_response_append_header(
+    response: *mut c_void,
+    name: List<u8>,
+    value: List<u8>,
+) -> bool {
+    match response_append_header(response, name, value) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Sets body of the response populated by `OutgoingHttpHandler`, `body` is copied
+#[no_mangle]
+pub extern "C" fn http_response_set_body(response: *mut c_void, body: List<u8>) -> bool {
+    match response_set_body(response, body) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Sets the error code, one of `HttpErrorCode`, returned to the guest if `OutgoingHttpHandler`
+/// returns `HttpAction_Fail`.
+/// `message` is only used for `HttpErrorCode_InternalError`, null `ptr` means none.
+#[no_mangle]
+pub extern "C" fn http_response_set_error(
+    response: *mut c_void,
+    code: u32,
+    message: List<u8>,
+) -> bool {
+    match response_set_error(response, code, message) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Reads file at `path` from in-memory filesystem preopened at `guest_path`.
+/// At most `len` bytes are copied into `buf` and the file size is written to `n`.
+#[no_mangle]
+pub extern "C" fn instance_fs_read(
+    instance_ptr: *mut c_void,
+    guest_path: *const c_char,
+    path: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match fs_read(instance_ptr, guest_path, path, buf, len, n) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Writes `contents` to file at `path` in in-memory filesystem preopened at `guest_path`.
+#[no_mangle]
+pub extern "C" fn instance_fs_write(
+    instance_ptr: *mut c_void,
+    guest_path: *const c_char,
+    path: *const c_char,
+    contents: List<u8>,
+) -> bool {
+    match fs_write(instance_ptr, guest_path, path, contents) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Writes identifiers of all `wasi:keyvalue` buckets to `buf`, each terminated by a NUL byte.
+/// At most `len` bytes are copied into `buf` and the total size is written to `n`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_keyvalue_buckets(
+    instance_ptr: *mut c_void,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match keyvalue_buckets(instance_ptr, buf, len, n) {
         Ok(()) => true,
         Err(err) => {
             store_error(err);
@@ -66,3 +197,366 @@ pub extern "C" fn instance_call(
         }
     }
 }
+
+/// Writes all keys in `wasi:keyvalue` bucket `bucket` to `buf`, each terminated by a NUL byte.
+/// At most `len` bytes are copied into `buf` and the total size is written to `n`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_keyvalue_keys(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match keyvalue_keys(instance_ptr, bucket, buf, len, n) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Reads value of `key` in `wasi:keyvalue` bucket `bucket`, fails if the key does not exist.
+/// At most `len` bytes are copied into `buf` and the value size is written to `n`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_keyvalue_get(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    key: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match keyvalue_get(instance_ptr, bucket, key, buf, len, n) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Sets value of `key` in `wasi:keyvalue` bucket `bucket` to `value`, `value` is copied.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_keyvalue_set(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    key: *const c_char,
+    value: List<u8>,
+) -> bool {
+    match keyvalue_set(instance_ptr, bucket, key, value) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Deletes `key` from `wasi:keyvalue` bucket `bucket`, deleting a missing key is not an error.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_keyvalue_delete(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    key: *const c_char,
+) -> bool {
+    match keyvalue_delete(instance_ptr, bucket, key) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Writes names of all `wasi:blobstore` containers to `buf`, each terminated by a NUL byte.
+/// At most `len` bytes are copied into `buf` and the total size is written to `n`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_blobstore_containers(
+    instance_ptr: *mut c_void,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match blobstore_containers(instance_ptr, buf, len, n) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Writes names of all objects in `wasi:blobstore` container `container` to `buf`,
+/// each terminated by a NUL byte, fails if the container does not exist.
+/// At most `len` bytes are copied into `buf` and the total size is written to `n`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_blobstore_objects(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match blobstore_objects(instance_ptr, container, buf, len, n) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Reads object `name` in `wasi:blobstore` container `container`, fails if it does not exist.
+/// At most `len` bytes are copied into `buf` and the object size is written to `n`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_blobstore_read(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    name: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> bool {
+    match blobstore_read(instance_ptr, container, name, buf, len, n) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Creates or replaces object `name` in `wasi:blobstore` container `container`, `data` is copied.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_blobstore_write(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    name: *const c_char,
+    data: List<u8>,
+) -> bool {
+    match blobstore_write(instance_ptr, container, name, data) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Deletes object `name` from `wasi:blobstore` container `container`,
+/// deleting a missing object is not an error.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_blobstore_delete(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    name: *const c_char,
+) -> bool {
+    match blobstore_delete(instance_ptr, container, name) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Calls `visitor` with `data` for each message recorded by the `wasi:messaging` broker
+/// in order of publication. This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_messaging_published(
+    instance_ptr: *mut c_void,
+    visitor: MessageVisitor,
+    data: *mut c_void,
+) -> bool {
+    match messaging_published(instance_ptr, false, visitor, data) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Calls `visitor` with `data` for each message recorded by the `wasi:messaging` broker
+/// in order of publication and removes them from the broker.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_messaging_take_published(
+    instance_ptr: *mut c_void,
+    visitor: MessageVisitor,
+    data: *mut c_void,
+) -> bool {
+    match messaging_published(instance_ptr, true, visitor, data) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Delivers `message` to the `wasi:messaging/incoming-handler` export of the component,
+/// `message` is copied
+#[no_mangle]
+pub extern "C" fn instance_messaging_handle(instance_ptr: *mut c_void, message: Message) -> bool {
+    match messaging_handle(instance_ptr, message) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Appends a reply to the replies populated by `MessagingReplyHandler`, `reply` is copied
+#[no_mangle]
+pub extern "C" fn messaging_replies_push(replies: *mut c_void, reply: Message) -> bool {
+    match replies_push(replies, reply) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Appends `buf` to guest stdin, which must be configured with `Input_Pipe`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_stdin_push(instance: *mut c_void, buf: List<u8>) -> bool {
+    match stdin_push(instance, buf) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Closes guest stdin, guest will observe end-of-stream once all buffered bytes are read.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_stdin_close(instance: *mut c_void) -> bool {
+    match stdin_close(instance) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Writes outgoing `wasi:http` exchanges recorded since the last flush to the cassette, if any,
+/// and syncs the HAR log, if any, to disk.
+/// Recorded exchanges are also written once the instance is freed.
+/// This blocks until any in-progress `instance_call` returns.
+#[no_mangle]
+pub extern "C" fn instance_flush(instance: *mut c_void) -> bool {
+    match flush(instance) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Sets virtual wall clock time to `wall_ns` nanoseconds since Unix epoch,
+/// monotonic clock is not affected. Instance must be configured with `Clock_Virtual`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_clock_set(instance: *mut c_void, wall_ns: u64) -> bool {
+    match clock_set(instance, wall_ns) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Advances virtual wall and monotonic clocks by `ns` nanoseconds, resolving guest pollables,
+/// which deadlines are reached. Instance must be configured with `Clock_Virtual`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_clock_advance(instance: *mut c_void, ns: u64) -> bool {
+    match clock_advance(instance, ns) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Configures whether guest waiting on `monotonic-clock` pollables advances the virtual clock.
+/// Instance must be configured with `Clock_Virtual`.
+/// This can be called concurrently with `instance_call`.
+#[no_mangle]
+pub extern "C" fn instance_clock_set_auto_advance(
+    instance: *mut c_void,
+    auto_advance: bool,
+) -> bool {
+    match clock_set_auto_advance(instance, auto_advance) {
+        Ok(()) => true,
+        Err(err) => {
+            store_error(err);
+            false
+        }
+    }
+}
+
+/// Returns the number of bytes captured from guest stdout
+#[no_mangle]
+pub extern "C" fn instance_stdout_len(instance: *mut c_void) -> usize {
+    let Some(instance) = (unsafe { instance.cast::<Instance>().as_ref() }) else {
+        return 0;
+    };
+    instance
+        .stdout
+        .as_ref()
+        .map_or(0, wadge::OutputCapture::len)
+}
+
+/// Copies at most `len` bytes captured from guest stdout into `buf`, removing them from the capture buffer.
+/// Returns the number of bytes copied.
+#[no_mangle]
+pub extern "C" fn instance_stdout_take(instance: *mut c_void, buf: *mut u8, len: usize) -> usize {
+    let Some(instance) = (unsafe { instance.cast::<Instance>().as_ref() }) else {
+        return 0;
+    };
+    output_take(instance.stdout.as_ref(), buf, len)
+}
+
+/// Returns the number of bytes captured from guest stderr
+#[no_mangle]
+pub extern "C" fn instance_stderr_len(instance: *mut c_void) -> usize {
+    let Some(instance) = (unsafe { instance.cast::<Instance>().as_ref() }) else {
+        return 0;
+    };
+    instance
+        .stderr
+        .as_ref()
+        .map_or(0, wadge::OutputCapture::len)
+}
+
+/// Copies at most `len` bytes captured from guest stderr into `buf`, removing them from the capture buffer.
+/// Returns the number of bytes copied.
+#[no_mangle]
+pub extern "C" fn instance_stderr_take(instance: *mut c_void, buf: *mut u8, len: usize) -> usize {
+    let Some(instance) = (unsafe { instance.cast::<Instance>().as_ref() }) else {
+        return 0;
+    };
+    output_take(instance.stderr.as_ref(), buf, len)
+}
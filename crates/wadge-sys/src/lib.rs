@@ -1,17 +1,39 @@
 use core::ffi::{c_char, c_void, CStr};
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
 use core::slice;
+use core::time::Duration;
 
+use std::env;
 use std::sync::{Arc, LazyLock, Mutex};
+use std::time::SystemTime;
 
 use anyhow::{bail, ensure, Context as _};
-use tracing::{instrument, trace_span};
+use tracing::{error, instrument, trace_span};
 use tracing_subscriber::EnvFilter;
 use wasmtime::component::{Resource, ResourceAny, Val};
 use wasmtime_cabish::{deref_arg, lift_params, lower_results, CabishView};
 
 mod ffi;
 
+/// Implements [`TryFrom<u32>`] for a fieldless enum, which crosses the FFI
+/// boundary as `u32`, since out-of-range values of a Rust enum are undefined behavior
+macro_rules! ffi_enum {
+    ($name:ident { $($variant:ident),+ $(,)? }) => {
+        impl TryFrom<u32> for $name {
+            type Error = anyhow::Error;
+
+            fn try_from(value: u32) -> anyhow::Result<Self> {
+                $(
+                    if value == Self::$variant as u32 {
+                        return Ok(Self::$variant);
+                    }
+                )+
+                bail!("invalid `{}` value `{value}`", stringify!($name))
+            }
+        }
+    };
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct List<T> {
@@ -19,39 +41,1447 @@ pub struct List<T> {
     pub len: usize,
 }
 
+impl<T> List<T> {
+    /// Returns the list as a slice, null `ptr` is treated as an empty list
+    unsafe fn as_slice(&self) -> &[T] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+}
+
+impl<T> From<&[T]> for List<T> {
+    fn from(s: &[T]) -> Self {
+        Self {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+}
+
+impl List<u8> {
+    /// Returns the list as a UTF-8 string, null `ptr` is treated as an empty string
+    unsafe fn to_str(&self) -> anyhow::Result<&str> {
+        str::from_utf8(unsafe { self.as_slice() }).context("string is not valid UTF-8")
+    }
+
+    /// Returns the list as a UTF-8 string, null `ptr` is treated as `None`
+    unsafe fn to_option_str(&self) -> anyhow::Result<Option<&str>> {
+        if self.ptr.is_null() {
+            Ok(None)
+        } else {
+            unsafe { self.to_str() }.map(Some)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct KeyValue {
+    pub key: List<u8>,
+    pub value: List<u8>,
+}
+
 static ENGINE: LazyLock<wasmtime::Engine> = LazyLock::new(wasmtime::Engine::default);
 
+/// HAR log shared by all instances, configured via `WADGE_HAR`
+static HAR: Mutex<Option<wadge::Har>> = Mutex::new(None);
+
+/// Returns the HAR log at the path set in `WADGE_HAR`, if any.
+/// At most `WADGE_HAR_BODY_LIMIT` bytes of each body are logged.
+fn har() -> anyhow::Result<Option<wadge::Har>> {
+    let Some(path) = env::var_os("WADGE_HAR").filter(|path| !path.is_empty()) else {
+        return Ok(None);
+    };
+    let Ok(mut har) = HAR.lock() else {
+        bail!("failed to lock HAR mutex")
+    };
+    if let Some(har) = har.as_ref().filter(|har| har.path() == path) {
+        return Ok(Some(har.clone()));
+    }
+    let body_limit = match env::var("WADGE_HAR_BODY_LIMIT") {
+        Ok(limit) => limit
+            .parse()
+            .with_context(|| format!("invalid `WADGE_HAR_BODY_LIMIT` value `{limit}`"))?,
+        Err(..) => wadge::Har::DEFAULT_BODY_LIMIT,
+    };
+    let log = wadge::Har::new(path, body_limit).context("failed to create HAR log")?;
+    *har = Some(log.clone());
+    Ok(Some(log))
+}
+
+/// Host directory exposed to the guest via `wasi:filesystem/preopens`
+#[repr(C)]
+#[derive(Debug)]
+pub struct Preopen {
+    pub host_path: List<u8>,
+    pub guest_path: List<u8>,
+    /// Directory permission bits: `1` - read, `2` - mutate
+    pub dir_perms: usize,
+    /// File permission bits: `1` - read, `2` - write
+    pub file_perms: usize,
+}
+
+/// File contained in an in-memory filesystem
+#[repr(C)]
+#[derive(Debug)]
+pub struct MemoryFile {
+    pub path: List<u8>,
+    pub contents: List<u8>,
+}
+
+/// In-memory filesystem exposed to the guest via `wasi:filesystem/preopens`
+#[repr(C)]
+#[derive(Debug)]
+pub struct MemoryPreopen {
+    pub guest_path: List<u8>,
+    /// Files to seed the filesystem with
+    pub files: List<MemoryFile>,
+    /// Tar archive to seed the filesystem with, null `ptr` means none
+    pub tar: List<u8>,
+}
+
+/// Guest input stream handling
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum Input {
+    /// Empty input
+    Empty,
+    /// Read input from the host process
+    Inherit,
+    /// Read `stdin_bytes` followed by end-of-stream
+    Bytes,
+    /// Read `stdin_bytes` followed by bytes pushed via `instance_stdin_push`
+    /// until `instance_stdin_close` is called
+    Pipe,
+}
+
+ffi_enum!(Input {
+    Empty,
+    Inherit,
+    Bytes,
+    Pipe
+});
+
+/// Guest output stream handling
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum Output {
+    Inherit,
+    Capture,
+    Discard,
+}
+
+ffi_enum!(Output {
+    Inherit,
+    Capture,
+    Discard
+});
+
+impl From<Output> for wadge::Output {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Inherit => Self::Inherit,
+            Output::Capture => Self::Capture,
+            Output::Discard => Self::Discard,
+        }
+    }
+}
+
+/// Guest clock handling
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum Clock {
+    /// Use host clocks
+    Host,
+    /// Use a virtual clock starting at `clock_wall_ns`, controlled via `instance_clock_*`
+    Virtual,
+}
+
+ffi_enum!(Clock { Host, Virtual });
+
+/// Outgoing `wasi:http` cassette mode
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum CassetteMode {
+    /// Send requests to their destinations and write all exchanges to `cassette`
+    /// on `instance_flush` and once the instance is freed
+    Record,
+    /// Serve requests from `cassette`, unmatched requests fail
+    Replay,
+}
+
+ffi_enum!(CassetteMode { Record, Replay });
+
+/// Guest `wasi:sockets` network policy
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum NetworkPolicy {
+    /// Allow all socket addresses
+    Inherit,
+    /// Allow only loopback socket addresses
+    Loopback,
+    /// Deny all socket addresses
+    DenyAll,
+    /// Allow only socket addresses matching at least one of `network_allow` rules
+    Allow,
+}
+
+ffi_enum!(NetworkPolicy {
+    Inherit,
+    Loopback,
+    DenyAll,
+    Allow
+});
+
+/// Outgoing `wasi:http` policy
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum HttpPolicy {
+    /// Allow all requests
+    AllowAll,
+    /// Deny all requests
+    Offline,
+    /// Allow only requests matching at least one of `http_allow` authorities or URL prefixes
+    Allow,
+}
+
+ffi_enum!(HttpPolicy {
+    AllowAll,
+    Offline,
+    Allow
+});
+
+/// Socket addresses the guest is allowed to connect to or bind
+#[repr(C)]
+#[derive(Debug)]
+pub struct NetworkRule {
+    /// Allowed IP address range in CIDR notation, e.g. `10.0.0.0/8`
+    pub cidr: List<u8>,
+    /// Lowest allowed port
+    pub port_min: u16,
+    /// Highest allowed port
+    pub port_max: u16,
+}
+
+/// Hostname override used by `wasi:sockets/ip-name-lookup` and outgoing `wasi:http` requests
+#[repr(C)]
+#[derive(Debug)]
+pub struct HostOverride {
+    pub name: List<u8>,
+    /// IP addresses the hostname resolves to
+    pub addrs: List<List<u8>>,
+}
+
+/// Route dispatching outgoing `wasi:http` requests to an in-process component
+#[repr(C)]
+#[derive(Debug)]
+pub struct HttpRoute {
+    /// Request authority, `host` or `host:port`
+    pub authority: List<u8>,
+    /// Instance returned by `instance_new`, which handles the requests via its
+    /// `wasi:http/incoming-handler` export. It may be freed independently.
+    pub instance: *mut c_void,
+}
+
+/// Guest `wasi:keyvalue` store backend
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum KeyValueBackend {
+    /// Keep buckets in memory
+    Memory,
+    /// Persist each bucket in a separate JSON file within `keyvalue_path` directory
+    Dir,
+    /// Persist all buckets in a single JSON file at `keyvalue_path`
+    File,
+}
+
+ffi_enum!(KeyValueBackend { Memory, Dir, File });
+
+/// Entry to seed a guest `wasi:keyvalue` bucket with
+#[repr(C)]
+#[derive(Debug)]
+pub struct KeyValueEntry {
+    pub bucket: List<u8>,
+    pub key: List<u8>,
+    pub value: List<u8>,
+}
+
+/// Object to seed a guest `wasi:blobstore` container with
+#[repr(C)]
+#[derive(Debug)]
+pub struct BlobObject {
+    pub name: List<u8>,
+    pub data: List<u8>,
+}
+
+/// Guest `wasi:blobstore` container
+#[repr(C)]
+#[derive(Debug)]
+pub struct BlobContainer {
+    pub name: List<u8>,
+    /// Host directory backing the container, null `ptr` means the container is kept in memory
+    pub dir: List<u8>,
+    /// Objects to seed the container with
+    pub objects: List<BlobObject>,
+}
+
+/// Guest `wasi:messaging` message, null `ptr` of `topic`, `reply_to` and `content_type`
+/// means the field is not set
+#[repr(C)]
+#[derive(Debug)]
+pub struct Message {
+    pub topic: List<u8>,
+    /// Topic replies to the message are published on
+    pub reply_to: List<u8>,
+    pub content_type: List<u8>,
+    pub data: List<u8>,
+    pub metadata: List<KeyValue>,
+}
+
+impl Message {
+    /// Copies the message into a [`wadge::Message`]
+    unsafe fn to_message(&self) -> anyhow::Result<wadge::Message> {
+        let Self {
+            topic,
+            reply_to,
+            content_type,
+            data,
+            metadata,
+        } = self;
+        let metadata = unsafe { metadata.as_slice() }
+            .iter()
+            .map(|KeyValue { key, value }| {
+                let key = unsafe { key.to_str() }.context("invalid metadata key")?;
+                let value = unsafe { value.to_str() }.context("invalid metadata value")?;
+                Ok((key.into(), value.into()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(wadge::Message {
+            topic: unsafe { topic.to_option_str() }
+                .context("invalid topic")?
+                .map(Into::into),
+            reply_to: unsafe { reply_to.to_option_str() }
+                .context("invalid reply-to topic")?
+                .map(Into::into),
+            content_type: unsafe { content_type.to_option_str() }
+                .context("invalid content type")?
+                .map(Into::into),
+            data: unsafe { data.as_slice() }.to_vec(),
+            metadata,
+        })
+    }
+}
+
+/// Returns `s` as a list, `None` is represented by null `ptr`
+fn option_list(s: Option<&str>) -> List<u8> {
+    s.map_or(
+        List {
+            ptr: ptr::null(),
+            len: 0,
+        },
+        |s| List::from(s.as_bytes()),
+    )
+}
+
+/// Calls `f` with `message` borrowed as a [`Message`]
+fn with_message<T>(message: &wadge::Message, f: impl FnOnce(&Message) -> T) -> T {
+    let metadata = message
+        .metadata
+        .iter()
+        .map(|(key, value)| KeyValue {
+            key: List::from(key.as_bytes()),
+            value: List::from(value.as_bytes()),
+        })
+        .collect::<Vec<_>>();
+    f(&Message {
+        topic: option_list(message.topic.as_deref()),
+        reply_to: option_list(message.reply_to.as_deref()),
+        content_type: option_list(message.content_type.as_deref()),
+        data: List::from(message.data.as_slice()),
+        metadata: List::from(metadata.as_slice()),
+    })
+}
+
+/// Native handler responding to guest `wasi:messaging` requests on `messaging_reply_topics`.
+/// `data` is the `messaging_reply_handler_data` pointer, `request` is valid for
+/// the duration of the call and `replies` must only be passed to `messaging_replies_push`.
+/// The handler may be called concurrently from multiple threads.
+pub type MessagingReplyHandler =
+    Option<unsafe extern "C" fn(data: *mut c_void, request: *const Message, replies: *mut c_void)>;
+
+/// Callback receiving messages recorded by the guest `wasi:messaging` broker.
+/// `data` is the pointer passed along with the callback, `message` is valid for the duration of the call.
+pub type MessageVisitor = Option<unsafe extern "C" fn(data: *mut c_void, message: *const Message)>;
+
+/// Outgoing guest `wasi:http` request passed to `OutgoingHttpHandler`
+#[repr(C)]
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: List<u8>,
+    pub uri: List<u8>,
+    pub headers: List<KeyValue>,
+    pub body: List<u8>,
+}
+
+/// Action taken on an outgoing guest `wasi:http` request
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum HttpAction {
+    /// Send the request to its destination
+    PassThrough,
+    /// Respond to the guest with the response populated via `http_response_*` functions
+    Respond,
+    /// Fail the request with the error code set via `http_response_set_error`
+    Fail,
+}
+
+ffi_enum!(HttpAction {
+    PassThrough,
+    Respond,
+    Fail
+});
+
+/// Outcome of an `instance_call`
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallStatus {
+    /// The call returned successfully
+    Ok,
+    /// The call failed, the error can be retrieved via `error_take`
+    Error,
+    /// The guest exited via `wasi:cli/exit` during the call
+    Exit,
+}
+
+/// `wasi:http` error code
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum HttpErrorCode {
+    InternalError,
+    DnsTimeout,
+    DestinationNotFound,
+    DestinationUnavailable,
+    DestinationIpProhibited,
+    DestinationIpUnroutable,
+    ConnectionRefused,
+    ConnectionTerminated,
+    ConnectionTimeout,
+    ConnectionReadTimeout,
+    ConnectionWriteTimeout,
+    ConnectionLimitReached,
+    TlsProtocolError,
+    TlsCertificateError,
+    HttpRequestDenied,
+    HttpRequestLengthRequired,
+    HttpRequestMethodInvalid,
+    HttpRequestUriInvalid,
+    HttpRequestUriTooLong,
+    HttpResponseIncomplete,
+    HttpResponseTimeout,
+    HttpUpgradeFailed,
+    HttpProtocolError,
+    LoopDetected,
+    ConfigurationError,
+}
+
+ffi_enum!(HttpErrorCode {
+    InternalError,
+    DnsTimeout,
+    DestinationNotFound,
+    DestinationUnavailable,
+    DestinationIpProhibited,
+    DestinationIpUnroutable,
+    ConnectionRefused,
+    ConnectionTerminated,
+    ConnectionTimeout,
+    ConnectionReadTimeout,
+    ConnectionWriteTimeout,
+    ConnectionLimitReached,
+    TlsProtocolError,
+    TlsCertificateError,
+    HttpRequestDenied,
+    HttpRequestLengthRequired,
+    HttpRequestMethodInvalid,
+    HttpRequestUriInvalid,
+    HttpRequestUriTooLong,
+    HttpResponseIncomplete,
+    HttpResponseTimeout,
+    HttpUpgradeFailed,
+    HttpProtocolError,
+    LoopDetected,
+    ConfigurationError
+});
+
+impl HttpErrorCode {
+    fn into_error_code(self, message: Option<String>) -> wadge::HttpErrorCode {
+        use wadge::HttpErrorCode as E;
+        match self {
+            Self::InternalError => E::InternalError(message),
+            Self::DnsTimeout => E::DnsTimeout,
+            Self::DestinationNotFound => E::DestinationNotFound,
+            Self::DestinationUnavailable => E::DestinationUnavailable,
+            Self::DestinationIpProhibited => E::DestinationIpProhibited,
+            Self::DestinationIpUnroutable => E::DestinationIpUnroutable,
+            Self::ConnectionRefused => E::ConnectionRefused,
+            Self::ConnectionTerminated => E::ConnectionTerminated,
+            Self::ConnectionTimeout => E::ConnectionTimeout,
+            Self::ConnectionReadTimeout => E::ConnectionReadTimeout,
+            Self::ConnectionWriteTimeout => E::ConnectionWriteTimeout,
+            Self::ConnectionLimitReached => E::ConnectionLimitReached,
+            Self::TlsProtocolError => E::TlsProtocolError,
+            Self::TlsCertificateError => E::TlsCertificateError,
+            Self::HttpRequestDenied => E::HttpRequestDenied,
+            Self::HttpRequestLengthRequired => E::HttpRequestLengthRequired,
+            Self::HttpRequestMethodInvalid => E::HttpRequestMethodInvalid,
+            Self::HttpRequestUriInvalid => E::HttpRequestUriInvalid,
+            Self::HttpRequestUriTooLong => E::HttpRequestUriTooLong,
+            Self::HttpResponseIncomplete => E::HttpResponseIncomplete,
+            Self::HttpResponseTimeout => E::HttpResponseTimeout,
+            Self::HttpUpgradeFailed => E::HttpUpgradeFailed,
+            Self::HttpProtocolError => E::HttpProtocolError,
+            Self::LoopDetected => E::LoopDetected,
+            Self::ConfigurationError => E::ConfigurationError,
+        }
+    }
+}
+
+/// Native handler intercepting outgoing guest `wasi:http` requests.
+/// `data` is the `outgoing_http_handler_data` pointer, `request` is valid for
+/// the duration of the call and `response` must only be passed to `http_response_*` functions.
+/// The handler returns one of `HttpAction` and may be called concurrently from multiple threads.
+pub type OutgoingHttpHandler = Option<
+    unsafe extern "C" fn(
+        data: *mut c_void,
+        request: *const HttpRequest,
+        response: *mut c_void,
+    ) -> u32,
+>;
+
+/// Response populated by `OutgoingHttpHandler`
+struct HttpResponse {
+    response: http::Response<bytes::Bytes>,
+    error: Option<wadge::HttpErrorCode>,
+}
+
+/// `outgoing_http_handler_data` or `messaging_reply_handler_data` pointer,
+/// which is passed back to the handler as-is
+#[derive(Clone, Copy)]
+struct HandlerData(*mut c_void);
+
+unsafe impl Send for HandlerData {}
+unsafe impl Sync for HandlerData {}
+
+fn outgoing_http_handler(
+    handler: unsafe extern "C" fn(*mut c_void, *const HttpRequest, *mut c_void) -> u32,
+    data: *mut c_void,
+) -> wadge::OutgoingHttpHandler {
+    let data = HandlerData(data);
+    Arc::new(move |req| {
+        let data = data;
+        let method = req.method().as_str();
+        let uri = req.uri().to_string();
+        let headers = req
+            .headers()
+            .iter()
+            .map(|(name, value)| KeyValue {
+                key: List::from(name.as_str().as_bytes()),
+                value: List::from(value.as_bytes()),
+            })
+            .collect::<Vec<_>>();
+        let request = HttpRequest {
+            method: List::from(method.as_bytes()),
+            uri: List::from(uri.as_bytes()),
+            headers: List::from(headers.as_slice()),
+            body: List::from(req.body().as_ref()),
+        };
+        let mut response = HttpResponse {
+            response: http::Response::default(),
+            error: None,
+        };
+        let action = unsafe { handler(data.0, &request, (&raw mut response).cast()) };
+        match HttpAction::try_from(action) {
+            Ok(HttpAction::PassThrough) => wadge::Intercept::PassThrough,
+            Ok(HttpAction::Respond) => wadge::Intercept::Respond(response.response),
+            Ok(HttpAction::Fail) => wadge::Intercept::Fail(
+                response
+                    .error
+                    .unwrap_or(wadge::HttpErrorCode::InternalError(None)),
+            ),
+            Err(err) => {
+                error!(?err, "outgoing HTTP handler returned an invalid action");
+                wadge::Intercept::Fail(wadge::HttpErrorCode::InternalError(Some(format!(
+                    "{err:#}"
+                ))))
+            }
+        }
+    })
+}
+
+fn messaging_reply_handler(
+    handler: unsafe extern "C" fn(*mut c_void, *const Message, *mut c_void),
+    data: *mut c_void,
+) -> wadge::ReplyHandler {
+    let data = HandlerData(data);
+    Arc::new(move |request| {
+        let data = data;
+        let mut replies = Vec::<wadge::Message>::default();
+        with_message(request, |request| unsafe {
+            handler(data.0, request, (&raw mut replies).cast());
+        });
+        replies
+    })
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum InheritEnv {
+    All,
+    None,
+    Allow,
+}
+
+ffi_enum!(InheritEnv { All, None, Allow });
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct Config {
     pub wasm: List<u8>,
+    /// Environment variables exposed to the guest
+    pub env: List<KeyValue>,
+    /// Host environment variable inheritance policy, one of `InheritEnv`
+    pub inherit_env: u32,
+    /// Names of host environment variables to inherit if `inherit_env` is `Allow`
+    pub inherit_env_allow: List<List<u8>>,
+    /// Arguments exposed to the guest
+    pub args: List<List<u8>>,
+    /// Initial working directory exposed to the guest, null `ptr` means none
+    pub cwd: List<u8>,
+    /// Host directories exposed to the guest
+    pub preopens: List<Preopen>,
+    /// In-memory filesystems exposed to the guest
+    pub memory_preopens: List<MemoryPreopen>,
+    /// Guest stdin handling, one of `Input`
+    pub stdin: u32,
+    /// Initial guest stdin contents
+    pub stdin_bytes: List<u8>,
+    /// Guest stdout handling, one of `Output`
+    pub stdout: u32,
+    /// Guest stderr handling, one of `Output`
+    pub stderr: u32,
+    /// Guest clock handling, one of `Clock`
+    pub clock: u32,
+    /// Initial virtual wall clock time in nanoseconds since Unix epoch
+    pub clock_wall_ns: u64,
+    /// Whether guest waiting on `monotonic-clock` pollables advances the virtual clock
+    pub clock_auto_advance: bool,
+    /// Whether guest `wasi:random` interfaces are seeded with `random_seed`
+    /// and therefore deterministic
+    pub random_seeded: bool,
+    /// Seed for guest `wasi:random` interfaces if `random_seeded` is set
+    pub random_seed: u64,
+    /// Guest `wasi:sockets` network policy, one of `NetworkPolicy`
+    pub network: u32,
+    /// Network rules used if `network` is `Allow`
+    pub network_allow: List<NetworkRule>,
+    /// Hostname overrides
+    pub hosts: List<HostOverride>,
+    /// Whether `wasi:sockets/ip-name-lookup` resolves hostnames not present
+    /// in `hosts` using the system resolver, disabled by default
+    pub resolve_fallthrough: bool,
+    /// Native handler intercepting outgoing guest `wasi:http` requests, null means none
+    pub outgoing_http_handler: OutgoingHttpHandler,
+    /// Pointer passed to `outgoing_http_handler` on each call
+    pub outgoing_http_handler_data: *mut c_void,
+    /// Path to outgoing `wasi:http` JSON cassette file, null `ptr` means none
+    pub cassette: List<u8>,
+    /// Outgoing `wasi:http` cassette mode, one of `CassetteMode`
+    pub cassette_mode: u32,
+    /// Names of request headers, which must match in addition to method and URI
+    /// for a request to be served from `cassette` in `CassetteMode_Replay`
+    pub cassette_match_headers: List<List<u8>>,
+    /// Routes dispatching outgoing `wasi:http` requests to in-process components
+    pub http_routes: List<HttpRoute>,
+    /// Timeout in nanoseconds between frames of HTTP bodies constructed via
+    /// `wasiext:http/ext`, `0` means the default of 1s
+    pub http_between_bytes_timeout_ns: u64,
+    /// Maximum size of HTTP request fields constructed via `wasiext:http/ext`,
+    /// `0` means the default of 2GiB
+    pub http_field_size_limit: usize,
+    /// Authority of HTTP requests constructed via `wasiext:http/ext`, which
+    /// do not specify one, null `ptr` means the default of `wadge`
+    pub http_default_authority: List<u8>,
+    /// Policy applied to outgoing `wasi:http` requests leaving the process, one of `HttpPolicy`
+    pub http_policy: u32,
+    /// Authorities (`host` or `host:port`) or URL prefixes used if `http_policy` is `Allow`.
+    /// URL prefixes match scheme, host and port exactly and the path on `/` boundaries
+    pub http_allow: List<List<u8>>,
+    /// Entries to seed guest `wasi:keyvalue` buckets with
+    pub keyvalue: List<KeyValueEntry>,
+    /// Guest `wasi:keyvalue` store backend, one of `KeyValueBackend`
+    pub keyvalue_backend: u32,
+    /// Path used by `keyvalue_backend`
+    pub keyvalue_path: List<u8>,
+    /// Configuration values exposed to the guest via `wasi:config/store`
+    pub runtime_config: List<KeyValue>,
+    /// Containers of guest `wasi:blobstore`
+    pub blobstore: List<BlobContainer>,
+    /// Topics of guest `wasi:messaging` requests handled by `messaging_reply_handler`
+    pub messaging_reply_topics: List<List<u8>>,
+    /// Native handler responding to guest `wasi:messaging` requests
+    pub messaging_reply_handler: MessagingReplyHandler,
+    /// Pointer passed to `messaging_reply_handler` on each call
+    pub messaging_reply_handler_data: *mut c_void,
 }
 
 pub struct Instance {
-    instance: Mutex<wadge::Instance>,
+    instance: Arc<Mutex<wadge::Instance>>,
+    stdin: Option<wadge::InputPipe>,
+    stdout: Option<wadge::OutputCapture>,
+    stderr: Option<wadge::OutputCapture>,
+    clock: Option<wadge::VirtualClock>,
+    keyvalue: wadge::KeyValueStore,
+    blobstore: wadge::BlobStore,
+    messaging: wadge::MessageBroker,
     subscriber: Arc<dyn tracing::Subscriber + Send + Sync + 'static>,
 }
 
 #[instrument(level = "trace")]
 fn instantiate(config: Config) -> anyhow::Result<Instance> {
-    let Config { wasm } = config;
+    let Config {
+        wasm,
+        env,
+        inherit_env,
+        inherit_env_allow,
+        args,
+        cwd,
+        preopens,
+        memory_preopens,
+        stdin,
+        stdin_bytes,
+        stdout,
+        stderr,
+        clock,
+        clock_wall_ns,
+        clock_auto_advance,
+        random_seeded,
+        random_seed,
+        network,
+        network_allow,
+        hosts,
+        resolve_fallthrough,
+        outgoing_http_handler: handler,
+        outgoing_http_handler_data: handler_data,
+        cassette,
+        cassette_mode,
+        cassette_match_headers,
+        http_routes,
+        http_between_bytes_timeout_ns,
+        http_field_size_limit,
+        http_default_authority,
+        http_policy,
+        http_allow,
+        keyvalue,
+        keyvalue_backend,
+        keyvalue_path,
+        runtime_config,
+        blobstore,
+        messaging_reply_topics,
+        messaging_reply_handler: reply_handler,
+        messaging_reply_handler_data: reply_handler_data,
+    } = config;
     ensure!(!wasm.ptr.is_null(), "`wasm_ptr` must not be null");
     let wasm = unsafe { slice::from_raw_parts(wasm.ptr, wasm.len) };
+    let env = unsafe { env.as_slice() }
+        .iter()
+        .map(|KeyValue { key, value }| {
+            let key = unsafe { key.to_str() }.context("invalid environment variable name")?;
+            let value = unsafe { value.to_str() }.context("invalid environment variable value")?;
+            Ok((key.into(), value.into()))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let inherit_env = match InheritEnv::try_from(inherit_env)? {
+        InheritEnv::All => wadge::InheritEnv::All,
+        InheritEnv::None => wadge::InheritEnv::None,
+        InheritEnv::Allow => {
+            let names = unsafe { inherit_env_allow.as_slice() }
+                .iter()
+                .map(|name| {
+                    unsafe { name.to_str() }
+                        .map(Into::into)
+                        .context("invalid environment variable name")
+                })
+                .collect::<anyhow::Result<_>>()?;
+            wadge::InheritEnv::Allow(names)
+        }
+    };
+    let args = unsafe { args.as_slice() }
+        .iter()
+        .map(|arg| {
+            unsafe { arg.to_str() }
+                .map(Into::into)
+                .context("invalid argument")
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let cwd = unsafe { cwd.to_option_str() }
+        .context("invalid initial working directory")?
+        .map(Into::into);
+    let preopens = unsafe { preopens.as_slice() }
+        .iter()
+        .map(
+            |Preopen {
+                 host_path,
+                 guest_path,
+                 dir_perms,
+                 file_perms,
+             }| {
+                let host_path = unsafe { host_path.to_str() }.context("invalid host path")?;
+                let guest_path = unsafe { guest_path.to_str() }.context("invalid guest path")?;
+                Ok(wadge::Preopen {
+                    host_path: host_path.into(),
+                    guest_path: guest_path.into(),
+                    dir_perms: wadge::DirPerms::from_bits_truncate(*dir_perms),
+                    file_perms: wadge::FilePerms::from_bits_truncate(*file_perms),
+                })
+            },
+        )
+        .collect::<anyhow::Result<_>>()?;
+    let memory_preopens = unsafe { memory_preopens.as_slice() }
+        .iter()
+        .map(
+            |MemoryPreopen {
+                 guest_path,
+                 files,
+                 tar,
+             }| {
+                let guest_path = unsafe { guest_path.to_str() }.context("invalid guest path")?;
+                let fs = if tar.ptr.is_null() {
+                    wadge::MemoryFs::new()
+                } else {
+                    wadge::MemoryFs::from_tar(unsafe { tar.as_slice() })
+                        .context("failed to unpack tar archive")?
+                };
+                for MemoryFile { path, contents } in unsafe { files.as_slice() } {
+                    let path = unsafe { path.to_str() }.context("invalid file path")?;
+                    fs.write(path, unsafe { contents.as_slice() })
+                        .with_context(|| format!("failed to write `{path}`"))?;
+                }
+                Ok((guest_path.into(), fs))
+            },
+        )
+        .collect::<anyhow::Result<_>>()?;
+    let stdin = match Input::try_from(stdin)? {
+        Input::Empty => wadge::Input::Empty,
+        Input::Inherit => wadge::Input::Inherit,
+        Input::Bytes => wadge::Input::Bytes(unsafe { stdin_bytes.as_slice() }.to_vec()),
+        Input::Pipe => wadge::Input::Pipe(unsafe { stdin_bytes.as_slice() }.to_vec()),
+    };
+    let clock = match Clock::try_from(clock)? {
+        Clock::Host => None,
+        Clock::Virtual => {
+            let clock = wadge::VirtualClock::new(
+                SystemTime::UNIX_EPOCH + Duration::from_nanos(clock_wall_ns),
+            );
+            clock.set_auto_advance(clock_auto_advance);
+            Some(clock)
+        }
+    };
+    let network = match NetworkPolicy::try_from(network)? {
+        NetworkPolicy::Inherit => wadge::NetworkPolicy::Inherit,
+        NetworkPolicy::Loopback => wadge::NetworkPolicy::Loopback,
+        NetworkPolicy::DenyAll => wadge::NetworkPolicy::DenyAll,
+        NetworkPolicy::Allow => {
+            let rules = unsafe { network_allow.as_slice() }
+                .iter()
+                .map(
+                    |NetworkRule {
+                         cidr,
+                         port_min,
+                         port_max,
+                     }| {
+                        let cidr = unsafe { cidr.to_str() }.context("invalid CIDR")?;
+                        let cidr = cidr
+                            .parse()
+                            .with_context(|| format!("invalid CIDR `{cidr}`"))?;
+                        Ok(wadge::NetworkRule {
+                            cidr,
+                            ports: *port_min..=*port_max,
+                        })
+                    },
+                )
+                .collect::<anyhow::Result<_>>()?;
+            wadge::NetworkPolicy::Allow(rules)
+        }
+    };
+    let hosts = unsafe { hosts.as_slice() }
+        .iter()
+        .map(|HostOverride { name, addrs }| {
+            let name = unsafe { name.to_str() }.context("invalid hostname")?;
+            let addrs = unsafe { addrs.as_slice() }
+                .iter()
+                .map(|addr| {
+                    let addr = unsafe { addr.to_str() }.context("invalid IP address")?;
+                    addr.parse()
+                        .with_context(|| format!("invalid IP address `{addr}`"))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok((name.into(), addrs))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let cassette = if cassette.ptr.is_null() {
+        None
+    } else {
+        let path = unsafe { cassette.to_str() }.context("invalid cassette path")?;
+        let match_headers = unsafe { cassette_match_headers.as_slice() }
+            .iter()
+            .map(|name| {
+                let name = unsafe { name.to_str() }.context("invalid header name")?;
+                Ok(name.into())
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Some(wadge::Cassette {
+            path: path.into(),
+            mode: match CassetteMode::try_from(cassette_mode)? {
+                CassetteMode::Record => wadge::CassetteMode::Record,
+                CassetteMode::Replay => wadge::CassetteMode::Replay,
+            },
+            match_headers,
+        })
+    };
+    let http_routes = unsafe { http_routes.as_slice() }
+        .iter()
+        .map(
+            |HttpRoute {
+                 authority,
+                 instance,
+             }| {
+                let authority = unsafe { authority.to_str() }.context("invalid authority")?;
+                let instance = unsafe { instance.cast::<Instance>().as_ref() }
+                    .with_context(|| format!("instance for `{authority}` must not be null"))?;
+                Ok((authority.into(), Arc::clone(&instance.instance)))
+            },
+        )
+        .collect::<anyhow::Result<_>>()?;
+    let mut http_ext = wadge::HttpExtConfig::default();
+    if http_between_bytes_timeout_ns > 0 {
+        http_ext.between_bytes_timeout = Duration::from_nanos(http_between_bytes_timeout_ns);
+    }
+    if http_field_size_limit > 0 {
+        http_ext.field_size_limit = http_field_size_limit;
+    }
+    if let Some(authority) =
+        unsafe { http_default_authority.to_option_str() }.context("invalid default authority")?
+    {
+        http_ext.default_authority = authority.into();
+    }
+    let http_policy = match HttpPolicy::try_from(http_policy)? {
+        HttpPolicy::AllowAll => wadge::HttpPolicy::AllowAll,
+        HttpPolicy::Offline => wadge::HttpPolicy::Offline,
+        HttpPolicy::Allow => {
+            let allowed = unsafe { http_allow.as_slice() }
+                .iter()
+                .map(|allowed| {
+                    let allowed =
+                        unsafe { allowed.to_str() }.context("invalid authority or URL prefix")?;
+                    Ok(allowed.into())
+                })
+                .collect::<anyhow::Result<_>>()?;
+            wadge::HttpPolicy::Allow(allowed)
+        }
+    };
+    let runtime_config = unsafe { runtime_config.as_slice() }
+        .iter()
+        .map(|KeyValue { key, value }| {
+            let key = unsafe { key.to_str() }.context("invalid configuration key")?;
+            let value = unsafe { value.to_str() }.context("invalid configuration value")?;
+            Ok((key.into(), value.into()))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let keyvalue_store = match KeyValueBackend::try_from(keyvalue_backend)? {
+        KeyValueBackend::Memory => wadge::KeyValueStore::new(),
+        KeyValueBackend::Dir => {
+            let path = unsafe { keyvalue_path.to_str() }.context("invalid keyvalue path")?;
+            wadge::KeyValueStore::open_dir(path)
+                .with_context(|| format!("failed to open keyvalue directory `{path}`"))?
+        }
+        KeyValueBackend::File => {
+            let path = unsafe { keyvalue_path.to_str() }.context("invalid keyvalue path")?;
+            wadge::KeyValueStore::open_file(path)
+                .with_context(|| format!("failed to open keyvalue file `{path}`"))?
+        }
+    };
+    for KeyValueEntry { bucket, key, value } in unsafe { keyvalue.as_slice() } {
+        let bucket = unsafe { bucket.to_str() }.context("invalid bucket identifier")?;
+        let key = unsafe { key.to_str() }.context("invalid key")?;
+        keyvalue_store.set(bucket, key, unsafe { value.as_slice() })?;
+    }
+    let blobstore_store = wadge::BlobStore::new();
+    for BlobContainer { name, dir, objects } in unsafe { blobstore.as_slice() } {
+        let name = unsafe { name.to_str() }.context("invalid container name")?;
+        match unsafe { dir.to_option_str() }.context("invalid container directory")? {
+            Some(dir) => blobstore_store.create_dir_container(name, dir)?,
+            None => blobstore_store.create_container(name)?,
+        }
+        for BlobObject { name: object, data } in unsafe { objects.as_slice() } {
+            let object = unsafe { object.to_str() }.context("invalid object name")?;
+            blobstore_store.write(name, object, unsafe { data.as_slice() })?;
+        }
+    }
+    let messaging = wadge::MessageBroker::new();
+    let reply_topics = unsafe { messaging_reply_topics.as_slice() };
+    if !reply_topics.is_empty() {
+        let handler = reply_handler.context(
+            "`messaging_reply_handler` must be set if `messaging_reply_topics` is not empty",
+        )?;
+        let handler = messaging_reply_handler(handler, reply_handler_data);
+        for topic in reply_topics {
+            let topic = unsafe { topic.to_str() }.context("invalid reply topic")?;
+            messaging.set_reply_handler(topic, Arc::clone(&handler));
+        }
+    }
     let instance = wadge::instantiate(wadge::Config {
         engine: ENGINE.clone(),
         wasm,
+        env,
+        inherit_env,
+        args,
+        cwd,
+        preopens,
+        memory_preopens,
+        stdin,
+        stdout: Output::try_from(stdout)?.into(),
+        stderr: Output::try_from(stderr)?.into(),
+        clock,
+        random_seed: random_seeded.then_some(random_seed),
+        network,
+        hosts,
+        resolve_fallthrough,
+        outgoing_http_handler: handler.map(|handler| outgoing_http_handler(handler, handler_data)),
+        cassette,
+        http_routes,
+        http_ext,
+        har: har()?,
+        http_policy,
+        keyvalue: keyvalue_store,
+        runtime_config,
+        blobstore: blobstore_store,
+        messaging,
     })
     .context("failed to instantiate component")?;
+    let stdin = instance.stdin().cloned();
+    let stdout = instance.stdout().cloned();
+    let stderr = instance.stderr().cloned();
+    let clock = instance.clock().cloned();
+    let keyvalue = instance.keyvalue().clone();
+    let blobstore = instance.blobstore().clone();
+    let messaging = instance.messaging().clone();
     let subscriber = tracing_subscriber::fmt()
         .without_time()
         .with_env_filter(EnvFilter::from_env("WADGE_LOG"))
         .finish();
     Ok(Instance {
-        instance: instance.into(),
+        instance: Arc::new(instance.into()),
+        stdin,
+        stdout,
+        stderr,
+        clock,
+        keyvalue,
+        blobstore,
+        messaging,
         subscriber: Arc::new(subscriber),
     })
 }
 
+/// Looks up the in-memory filesystem preopened at `guest_path` within `instance_ptr`
+fn memory_fs(
+    instance_ptr: *mut c_void,
+    guest_path: *const c_char,
+) -> anyhow::Result<wadge::MemoryFs> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    ensure!(!guest_path.is_null(), "`guest_path` must not be null");
+    let guest_path = unsafe { CStr::from_ptr(guest_path) }
+        .to_str()
+        .context("`guest_path` is not valid UTF-8")?;
+    let inst = unsafe { inst.as_ref() };
+    let Ok(inst) = inst.instance.lock() else {
+        bail!("failed to lock instance mutex")
+    };
+    inst.memory_fs(guest_path)
+        .cloned()
+        .with_context(|| format!("in-memory filesystem `{guest_path}` not found"))
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn fs_read(
+    instance_ptr: *mut c_void,
+    guest_path: *const c_char,
+    path: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    ensure!(!path.is_null(), "`path` must not be null");
+    let path = unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .context("`path` is not valid UTF-8")?;
+    let fs = memory_fs(instance_ptr, guest_path)?;
+    let contents = fs.read(path)?;
+    copy_out(&contents, buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn fs_write(
+    instance_ptr: *mut c_void,
+    guest_path: *const c_char,
+    path: *const c_char,
+    contents: List<u8>,
+) -> anyhow::Result<()> {
+    ensure!(!path.is_null(), "`path` must not be null");
+    let path = unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .context("`path` is not valid UTF-8")?;
+    let fs = memory_fs(instance_ptr, guest_path)?;
+    fs.write(path, unsafe { contents.as_slice() })
+}
+
+/// Returns the `wasi:keyvalue` store of `instance_ptr`
+fn keyvalue(instance_ptr: *mut c_void) -> anyhow::Result<wadge::KeyValueStore> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let inst = unsafe { inst.as_ref() };
+    Ok(inst.keyvalue.clone())
+}
+
+/// Returns `s` as a UTF-8 string, failing if it is null
+fn c_str<'a>(s: *const c_char, name: &str) -> anyhow::Result<&'a str> {
+    ensure!(!s.is_null(), "`{name}` must not be null");
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .with_context(|| format!("`{name}` is not valid UTF-8"))
+}
+
+/// Copies at most `len` bytes of `src` into `buf` and writes the length of `src` to `n`
+fn copy_out(src: &[u8], buf: *mut u8, len: usize, n: *mut usize) -> anyhow::Result<()> {
+    ensure!(!n.is_null(), "`n` must not be null");
+    ensure!(len == 0 || !buf.is_null(), "`buf` must not be null");
+    if len > 0 {
+        unsafe { ptr::copy_nonoverlapping(src.as_ptr(), buf, src.len().min(len)) };
+    }
+    unsafe { n.write(src.len()) };
+    Ok(())
+}
+
+/// Joins `names`, terminating each with a NUL byte
+fn join_nul(names: Vec<String>) -> Vec<u8> {
+    names
+        .into_iter()
+        .flat_map(|name| name.into_bytes().into_iter().chain([0]))
+        .collect()
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn keyvalue_buckets(
+    instance_ptr: *mut c_void,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    let buckets = keyvalue(instance_ptr)?.buckets();
+    copy_out(&join_nul(buckets), buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn keyvalue_keys(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    let bucket = c_str(bucket, "bucket")?;
+    let keys = keyvalue(instance_ptr)?.keys(bucket);
+    copy_out(&join_nul(keys), buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn keyvalue_get(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    key: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    let bucket = c_str(bucket, "bucket")?;
+    let key = c_str(key, "key")?;
+    let value = keyvalue(instance_ptr)?
+        .get(bucket, key)
+        .with_context(|| format!("key `{key}` not found in bucket `{bucket}`"))?;
+    copy_out(&value, buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn keyvalue_set(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    key: *const c_char,
+    value: List<u8>,
+) -> anyhow::Result<()> {
+    let bucket = c_str(bucket, "bucket")?;
+    let key = c_str(key, "key")?;
+    keyvalue(instance_ptr)?.set(bucket, key, unsafe { value.as_slice() })
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn keyvalue_delete(
+    instance_ptr: *mut c_void,
+    bucket: *const c_char,
+    key: *const c_char,
+) -> anyhow::Result<()> {
+    let bucket = c_str(bucket, "bucket")?;
+    let key = c_str(key, "key")?;
+    keyvalue(instance_ptr)?.delete(bucket, key)?;
+    Ok(())
+}
+
+/// Returns the `wasi:blobstore` store of `instance_ptr`
+fn blobstore(instance_ptr: *mut c_void) -> anyhow::Result<wadge::BlobStore> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let inst = unsafe { inst.as_ref() };
+    Ok(inst.blobstore.clone())
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn blobstore_containers(
+    instance_ptr: *mut c_void,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    let containers = blobstore(instance_ptr)?.containers();
+    copy_out(&join_nul(containers), buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn blobstore_objects(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    let container = c_str(container, "container")?;
+    let objects = blobstore(instance_ptr)?.objects(container)?;
+    copy_out(&join_nul(objects), buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn blobstore_read(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    name: *const c_char,
+    buf: *mut u8,
+    len: usize,
+    n: *mut usize,
+) -> anyhow::Result<()> {
+    let container = c_str(container, "container")?;
+    let name = c_str(name, "name")?;
+    let data = blobstore(instance_ptr)?.read(container, name)?;
+    copy_out(&data, buf, len, n)
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn blobstore_write(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    name: *const c_char,
+    data: List<u8>,
+) -> anyhow::Result<()> {
+    let container = c_str(container, "container")?;
+    let name = c_str(name, "name")?;
+    blobstore(instance_ptr)?.write(container, name, unsafe { data.as_slice() })
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn blobstore_delete(
+    instance_ptr: *mut c_void,
+    container: *const c_char,
+    name: *const c_char,
+) -> anyhow::Result<()> {
+    let container = c_str(container, "container")?;
+    let name = c_str(name, "name")?;
+    blobstore(instance_ptr)?.delete(container, name)
+}
+
+/// Returns the `wasi:messaging` broker of `instance_ptr`
+fn messaging(instance_ptr: *mut c_void) -> anyhow::Result<wadge::MessageBroker> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let inst = unsafe { inst.as_ref() };
+    Ok(inst.messaging.clone())
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn messaging_published(
+    instance_ptr: *mut c_void,
+    take: bool,
+    visitor: MessageVisitor,
+    data: *mut c_void,
+) -> anyhow::Result<()> {
+    let visitor = visitor.context("`visitor` must not be null")?;
+    let messaging = messaging(instance_ptr)?;
+    let published = if take {
+        messaging.take_published()
+    } else {
+        messaging.published()
+    };
+    for message in &published {
+        with_message(message, |message| unsafe { visitor(data, message) });
+    }
+    Ok(())
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn messaging_handle(instance_ptr: *mut c_void, message: Message) -> anyhow::Result<()> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let message = unsafe { message.to_message() }?;
+    let inst = unsafe { inst.as_ref() };
+    let _log = tracing::subscriber::set_default(Arc::clone(&inst.subscriber));
+    let Ok(mut inst) = inst.instance.lock() else {
+        bail!("failed to lock instance mutex")
+    };
+    inst.handle_message(message)
+}
+
+/// Appends `reply` to the replies populated by `MessagingReplyHandler`
+fn replies_push(replies: *mut c_void, reply: Message) -> anyhow::Result<()> {
+    let replies = unsafe { replies.cast::<Vec<wadge::Message>>().as_mut() }
+        .context("`replies` must not be null")?;
+    replies.push(unsafe { reply.to_message() }?);
+    Ok(())
+}
+
+/// Returns the guest stdin buffer of `instance_ptr`
+fn stdin(instance_ptr: *mut c_void) -> anyhow::Result<wadge::InputPipe> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let inst = unsafe { inst.as_ref() };
+    inst.stdin
+        .clone()
+        .context("instance stdin is not fed by native code")
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn stdin_push(instance_ptr: *mut c_void, buf: List<u8>) -> anyhow::Result<()> {
+    stdin(instance_ptr)?.push(unsafe { buf.as_slice() })
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn stdin_close(instance_ptr: *mut c_void) -> anyhow::Result<()> {
+    stdin(instance_ptr)?.close();
+    Ok(())
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn flush(instance_ptr: *mut c_void) -> anyhow::Result<()> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let inst = unsafe { inst.as_ref() };
+    let _log = tracing::subscriber::set_default(Arc::clone(&inst.subscriber));
+    let Ok(inst) = inst.instance.lock() else {
+        bail!("failed to lock instance mutex")
+    };
+    inst.flush()
+}
+
+/// Returns the virtual clock of `instance_ptr`
+fn clock(instance_ptr: *mut c_void) -> anyhow::Result<wadge::VirtualClock> {
+    let inst =
+        NonNull::new(instance_ptr.cast::<Instance>()).context("`instance_ptr` must not be null")?;
+    let inst = unsafe { inst.as_ref() };
+    inst.clock
+        .clone()
+        .context("instance does not use a virtual clock")
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn clock_set(instance_ptr: *mut c_void, wall_ns: u64) -> anyhow::Result<()> {
+    clock(instance_ptr)?.set(SystemTime::UNIX_EPOCH + Duration::from_nanos(wall_ns));
+    Ok(())
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn clock_advance(instance_ptr: *mut c_void, ns: u64) -> anyhow::Result<()> {
+    clock(instance_ptr)?.advance(Duration::from_nanos(ns));
+    Ok(())
+}
+
+#[instrument(level = "debug", ret(level = "debug"))]
+fn clock_set_auto_advance(instance_ptr: *mut c_void, auto_advance: bool) -> anyhow::Result<()> {
+    clock(instance_ptr)?.set_auto_advance(auto_advance);
+    Ok(())
+}
+
+/// Copies at most `len` bytes captured by `capture` into `buf`, removing them from the capture buffer
+fn output_take(capture: Option<&wadge::OutputCapture>, buf: *mut u8, len: usize) -> usize {
+    let Some(capture) = capture else {
+        return 0;
+    };
+    if buf.is_null() {
+        return 0;
+    }
+    let taken = capture.take_n(len);
+    unsafe { ptr::copy_nonoverlapping(taken.as_ptr(), buf, taken.len()) };
+    taken.len()
+}
+
+/// Returns the response populated by `OutgoingHttpHandler`
+fn http_response<'a>(response: *mut c_void) -> anyhow::Result<&'a mut HttpResponse> {
+    unsafe { response.cast::<HttpResponse>().as_mut() }.context("`response` must not be null")
+}
+
+fn response_set_status(response: *mut c_void, status: u16) -> anyhow::Result<()> {
+    let response = http_response(response)?;
+    *response.response.status_mut() =
+        http::StatusCode::from_u16(status).with_context(|| format!("invalid status `{status}`"))?;
+    Ok(())
+}
+
+fn response_append_header(
+    response: *mut c_void,
+    name: List<u8>,
+    value: List<u8>,
+) -> anyhow::Result<()> {
+    let response = http_response(response)?;
+    let name =
+        http::HeaderName::from_bytes(unsafe { name.as_slice() }).context("invalid header name")?;
+    let value = http::HeaderValue::from_bytes(unsafe { value.as_slice() })
+        .context("invalid header value")?;
+    response.response.headers_mut().append(name, value);
+    Ok(())
+}
+
+fn response_set_body(response: *mut c_void, body: List<u8>) -> anyhow::Result<()> {
+    let response = http_response(response)?;
+    *response.response.body_mut() = bytes::Bytes::copy_from_slice(unsafe { body.as_slice() });
+    Ok(())
+}
+
+fn response_set_error(response: *mut c_void, code: u32, message: List<u8>) -> anyhow::Result<()> {
+    let response = http_response(response)?;
+    let code = HttpErrorCode::try_from(code)?;
+    let message = unsafe { message.to_option_str() }
+        .context("invalid error message")?
+        .map(Into::into);
+    response.error = Some(code.into_error_code(message));
+    Ok(())
+}
+
 #[instrument(level = "debug", ret(level = "debug"))]
 fn call(
     instance_ptr: *mut c_void,
@@ -105,3 +1535,139 @@ fn call(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_enum() {
+        assert!(matches!(Input::try_from(0), Ok(Input::Empty)));
+        assert!(matches!(Input::try_from(3), Ok(Input::Pipe)));
+        assert!(Input::try_from(4).is_err());
+        assert!(matches!(HttpAction::try_from(2), Ok(HttpAction::Fail)));
+        assert!(HttpAction::try_from(u32::MAX).is_err());
+        assert!(matches!(
+            HttpErrorCode::try_from(HttpErrorCode::ConfigurationError as u32),
+            Ok(HttpErrorCode::ConfigurationError)
+        ));
+        assert!(HttpErrorCode::try_from(HttpErrorCode::ConfigurationError as u32 + 1).is_err());
+    }
+
+    /// Constructs an [`Instance`] from `config`
+    fn test_instance(config: wadge::Config) -> Instance {
+        let instance = wadge::instantiate(config).expect("failed to instantiate component");
+        Instance {
+            instance: Arc::new(instance.into()),
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            clock: None,
+            keyvalue: wadge::KeyValueStore::new(),
+            blobstore: wadge::BlobStore::new(),
+            messaging: wadge::MessageBroker::new(),
+            subscriber: Arc::new(tracing_subscriber::fmt().finish()),
+        }
+    }
+
+    /// Constructs an [`Instance`] of an empty component with an in-memory filesystem
+    /// preopened at `/data`
+    fn memory_fs_instance(fs: wadge::MemoryFs) -> Instance {
+        test_instance(wadge::Config {
+            wasm: b"(component)",
+            memory_preopens: vec![("/data".into(), fs)],
+            ..wadge::Config::default()
+        })
+    }
+
+    #[test]
+    fn instance_call_status() {
+        let mut instance = test_instance(wadge::Config {
+            wasm: br#"(component
+                (import "wasi:cli/exit@0.2.0" (instance $exit
+                    (export "exit" (func (param "status" (result))))
+                ))
+                (core func $exit (canon lower (func $exit "exit")))
+                (core module $m
+                    (import "" "exit" (func $exit (param i32)))
+                    (func (export "exit") (call $exit (i32.const 1)))
+                    (func (export "noop"))
+                )
+                (core instance $i (instantiate $m
+                    (with "" (instance (export "exit" (func $exit))))
+                ))
+                (func $exit-fn (canon lift (core func $i "exit")))
+                (func $noop-fn (canon lift (core func $i "noop")))
+                (instance $test
+                    (export "exit" (func $exit-fn))
+                    (export "noop" (func $noop-fn))
+                )
+                (export "test" (instance $test))
+            )"#,
+            ..wadge::Config::default()
+        });
+        let instance_ptr = ptr::from_mut(&mut instance).cast();
+        let call = |name: &CStr, code: &mut i32| {
+            ffi::instance_call(
+                instance_ptr,
+                c"test".as_ptr(),
+                name.as_ptr(),
+                ptr::null(),
+                code,
+            )
+        };
+
+        let mut code = -1;
+        assert_eq!(call(c"noop", &mut code), CallStatus::Ok);
+        assert_eq!(call(c"missing", &mut code), CallStatus::Error);
+        assert_eq!(call(c"exit", &mut code), CallStatus::Exit);
+        assert_eq!(code, 1);
+        // the instance cannot be reentered after exit, which must not be reported as
+        // another exit
+        code = -1;
+        assert_eq!(call(c"noop", &mut code), CallStatus::Error);
+        assert_eq!(code, -1);
+    }
+
+    #[test]
+    fn fs_read_sizing() {
+        let fs = wadge::MemoryFs::from_files([("foo", "bar")]).unwrap();
+        let mut instance = memory_fs_instance(fs);
+        let instance_ptr = ptr::from_mut(&mut instance).cast();
+        let guest_path = c"/data".as_ptr();
+        let path = c"foo".as_ptr();
+
+        // the length is queried first without a buffer
+        let mut n = 0;
+        fs_read(instance_ptr, guest_path, path, ptr::null_mut(), 0, &mut n).unwrap();
+        assert_eq!(n, 3);
+
+        let mut buf = [0; 2];
+        fs_read(instance_ptr, guest_path, path, buf.as_mut_ptr(), 2, &mut n).unwrap();
+        assert_eq!((n, &buf), (3, b"ba"));
+
+        let mut buf = [0; 3];
+        fs_read(instance_ptr, guest_path, path, buf.as_mut_ptr(), 3, &mut n).unwrap();
+        assert_eq!((n, &buf), (3, b"bar"));
+
+        assert!(fs_read(instance_ptr, guest_path, path, ptr::null_mut(), 3, &mut n).is_err());
+        assert!(fs_read(
+            instance_ptr,
+            guest_path,
+            path,
+            ptr::null_mut(),
+            0,
+            ptr::null_mut()
+        )
+        .is_err());
+        assert!(fs_read(
+            instance_ptr,
+            guest_path,
+            c"missing".as_ptr(),
+            ptr::null_mut(),
+            0,
+            &mut n
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,757 @@
+//! `wasi:blobstore` implementation backed by memory or directories
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, ensure, Context as _};
+use bytes::Bytes;
+use wasmtime::component::Resource;
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::p2::{DynInputStream, DynOutputStream};
+
+use crate::Ctx;
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "wasi:blobstore/imports@0.2.0-draft",
+        imports: { default: trappable },
+        with: {
+            "wasi:io": wasmtime_wasi::p2::bindings::sync::io,
+            "wasi:blobstore/container.container": super::Container,
+            "wasi:blobstore/container.stream-object-names": super::StreamObjectNames,
+            "wasi:blobstore/types.incoming-value": super::IncomingValue,
+            "wasi:blobstore/types.outgoing-value": super::OutgoingValue,
+        },
+    });
+}
+
+pub(crate) use bindings::wasi::blobstore::{blobstore, container, types};
+
+use types::{ContainerMetadata, Error, ObjectId, ObjectMetadata};
+
+#[derive(Debug)]
+struct Object {
+    data: Bytes,
+    created_at: SystemTime,
+}
+
+/// Storage of objects within a [`BlobStore`] container
+#[derive(Debug)]
+enum Objects {
+    Memory(BTreeMap<String, Object>),
+    /// Objects are stored as files within the directory, `/` in object names
+    /// separates path components
+    Dir(PathBuf),
+}
+
+#[derive(Debug)]
+struct ContainerState {
+    objects: Objects,
+    created_at: SystemTime,
+}
+
+/// Returns the path of object `name` within directory `dir`
+fn object_path(dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for component in name.split('/') {
+        ensure!(
+            !matches!(component, "" | "." | "..") && !component.contains('\\'),
+            "invalid object name `{name}`"
+        );
+        path.push(component);
+    }
+    Ok(path)
+}
+
+/// Appends names of all files within `path` to `names`, prefixed by `prefix`
+fn walk_dir(path: &Path, prefix: &str, names: &mut Vec<String>) -> anyhow::Result<()> {
+    let dir = fs::read_dir(path)
+        .with_context(|| format!("failed to read directory `{}`", path.display()))?;
+    for entry in dir {
+        let entry =
+            entry.with_context(|| format!("failed to read directory `{}`", path.display()))?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let ty = entry
+            .file_type()
+            .with_context(|| format!("failed to stat `{}`", entry.path().display()))?;
+        if ty.is_dir() {
+            walk_dir(&entry.path(), &format!("{prefix}{name}/"), names)?;
+        } else if ty.is_file() {
+            names.push(format!("{prefix}{name}"));
+        }
+    }
+    Ok(())
+}
+
+fn is_not_found(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotFound
+}
+
+impl Objects {
+    fn names(&self) -> anyhow::Result<Vec<String>> {
+        match self {
+            Self::Memory(objects) => Ok(objects.keys().cloned().collect()),
+            Self::Dir(path) => {
+                let mut names = Vec::default();
+                walk_dir(path, "", &mut names)?;
+                names.sort();
+                Ok(names)
+            }
+        }
+    }
+
+    fn get(&self, name: &str) -> anyhow::Result<Option<Object>> {
+        match self {
+            Self::Memory(objects) => {
+                Ok(objects.get(name).map(|Object { data, created_at }| Object {
+                    data: data.clone(),
+                    created_at: *created_at,
+                }))
+            }
+            Self::Dir(dir) => {
+                let path = object_path(dir, name)?;
+                let data = match fs::read(&path) {
+                    Ok(data) => data,
+                    Err(err) if is_not_found(&err) => return Ok(None),
+                    Err(err) => {
+                        return Err(err)
+                            .with_context(|| format!("failed to read `{}`", path.display()))
+                    }
+                };
+                let meta = fs::metadata(&path)
+                    .with_context(|| format!("failed to stat `{}`", path.display()))?;
+                let created_at = meta
+                    .created()
+                    .or_else(|_| meta.modified())
+                    .unwrap_or(UNIX_EPOCH);
+                Ok(Some(Object {
+                    data: data.into(),
+                    created_at,
+                }))
+            }
+        }
+    }
+
+    fn contains(&self, name: &str) -> anyhow::Result<bool> {
+        match self {
+            Self::Memory(objects) => Ok(objects.contains_key(name)),
+            Self::Dir(dir) => Ok(object_path(dir, name)?.is_file()),
+        }
+    }
+
+    fn put(&mut self, name: &str, data: Bytes, now: SystemTime) -> anyhow::Result<()> {
+        match self {
+            Self::Memory(objects) => {
+                objects.insert(
+                    name.into(),
+                    Object {
+                        data,
+                        created_at: now,
+                    },
+                );
+                Ok(())
+            }
+            Self::Dir(dir) => {
+                let path = object_path(dir, name)?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        format!("failed to create directory `{}`", parent.display())
+                    })?;
+                }
+                fs::write(&path, data)
+                    .with_context(|| format!("failed to write `{}`", path.display()))
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Memory(objects) => {
+                objects.remove(name);
+                Ok(())
+            }
+            Self::Dir(dir) => {
+                let path = object_path(dir, name)?;
+                match fs::remove_file(&path) {
+                    Ok(()) => Ok(()),
+                    Err(err) if is_not_found(&err) => Ok(()),
+                    Err(err) => {
+                        Err(err).with_context(|| format!("failed to remove `{}`", path.display()))
+                    }
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) -> anyhow::Result<()> {
+        match self {
+            Self::Memory(objects) => {
+                objects.clear();
+                Ok(())
+            }
+            Self::Dir(..) => {
+                for name in self.names()? {
+                    self.remove(&name)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Blob store backing guest `wasi:blobstore`. Containers are either kept in memory
+/// or backed by a directory on the host, in which case objects are stored as files
+/// and object names are paths relative to the directory.
+///
+/// Clones of [`BlobStore`] share the same underlying containers, which allows
+/// native code to seed and inspect objects stored by the guest.
+#[derive(Clone, Debug, Default)]
+pub struct BlobStore(Arc<Mutex<BTreeMap<String, ContainerState>>>);
+
+impl BlobStore {
+    /// Constructs a new empty [`BlobStore`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, ContainerState>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn insert(&self, name: &str, objects: Objects, now: SystemTime) -> anyhow::Result<()> {
+        let mut containers = self.lock();
+        ensure!(
+            !containers.contains_key(name),
+            "container `{name}` already exists"
+        );
+        containers.insert(
+            name.into(),
+            ContainerState {
+                objects,
+                created_at: now,
+            },
+        );
+        Ok(())
+    }
+
+    /// Calls `f` with the state of container `name`, failing if it does not exist
+    fn with<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut ContainerState) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut containers = self.lock();
+        let Some(container) = containers.get_mut(name) else {
+            bail!("container `{name}` does not exist")
+        };
+        f(container)
+    }
+
+    /// Creates an empty in-memory container `name`, failing if it already exists
+    pub fn create_container(&self, name: &str) -> anyhow::Result<()> {
+        self.insert(
+            name,
+            Objects::Memory(BTreeMap::default()),
+            SystemTime::now(),
+        )
+    }
+
+    /// Creates container `name` backed by a directory at `path`, failing if it already exists.
+    /// The directory is created if it does not exist.
+    pub fn create_dir_container(&self, name: &str, path: impl Into<PathBuf>) -> anyhow::Result<()> {
+        let path = path.into();
+        fs::create_dir_all(&path)
+            .with_context(|| format!("failed to create directory `{}`", path.display()))?;
+        self.insert(name, Objects::Dir(path), SystemTime::now())
+    }
+
+    /// Returns names of all containers in the store
+    #[must_use]
+    pub fn containers(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    /// Returns names of all objects in `container`
+    pub fn objects(&self, container: &str) -> anyhow::Result<Vec<String>> {
+        self.with(container, |state| state.objects.names())
+    }
+
+    /// Returns contents of object `name` in `container`
+    pub fn read(&self, container: &str, name: &str) -> anyhow::Result<Vec<u8>> {
+        self.with(container, |state| {
+            let Some(Object { data, .. }) = state.objects.get(name)? else {
+                bail!("object `{name}` does not exist in container `{container}`")
+            };
+            Ok(data.into())
+        })
+    }
+
+    /// Creates or replaces object `name` in `container`
+    pub fn write(
+        &self,
+        container: &str,
+        name: &str,
+        data: impl Into<Vec<u8>>,
+    ) -> anyhow::Result<()> {
+        let data = Bytes::from(data.into());
+        self.with(container, |state| {
+            state.objects.put(name, data, SystemTime::now())
+        })
+    }
+
+    /// Deletes object `name` from `container`, does nothing if the object does not exist
+    pub fn delete(&self, container: &str, name: &str) -> anyhow::Result<()> {
+        self.with(container, |state| state.objects.remove(name))
+    }
+}
+
+/// `wasi:blobstore/container.container` referring to a [`BlobStore`] container
+pub struct Container {
+    store: BlobStore,
+    name: String,
+}
+
+impl Container {
+    fn with<T>(
+        &self,
+        f: impl FnOnce(&mut ContainerState) -> anyhow::Result<T>,
+    ) -> Result<T, Error> {
+        self.store
+            .with(&self.name, f)
+            .map_err(|err| format!("{err:#}"))
+    }
+}
+
+/// `wasi:blobstore/container.stream-object-names` listing object names of a container
+pub struct StreamObjectNames(std::vec::IntoIter<String>);
+
+/// `wasi:blobstore/types.outgoing-value` buffering data written by the guest
+pub struct OutgoingValue(MemoryOutputPipe);
+
+/// `wasi:blobstore/types.incoming-value` holding data read by the guest
+pub struct IncomingValue(Bytes);
+
+/// Returns `t` as seconds since Unix epoch
+fn timestamp(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+impl Ctx {
+    /// Returns the current wall clock time observed by the guest
+    fn blobstore_now(&self) -> SystemTime {
+        self.clock
+            .as_ref()
+            .map_or_else(SystemTime::now, |clock| clock.now())
+    }
+
+    fn container(&self, container: &Resource<Container>) -> wasmtime::Result<&Container> {
+        Ok(self.table.get(container)?)
+    }
+
+    /// Copies `src` object to `dest`, removing `src` if `remove` is set
+    fn copy_blob(&self, src: &ObjectId, dest: &ObjectId, remove: bool) -> anyhow::Result<()> {
+        let now = self.blobstore_now();
+        let Some(Object { data, .. }) = self
+            .blobstore
+            .with(&src.container, |state| state.objects.get(&src.object))?
+        else {
+            bail!(
+                "object `{}` does not exist in container `{}`",
+                src.object,
+                src.container
+            )
+        };
+        if remove && src.container == dest.container && src.object == dest.object {
+            return Ok(());
+        }
+        self.blobstore.with(&dest.container, |state| {
+            state.objects.put(&dest.object, data, now)
+        })?;
+        if remove {
+            self.blobstore
+                .with(&src.container, |state| state.objects.remove(&src.object))?;
+        }
+        Ok(())
+    }
+}
+
+impl blobstore::Host for Ctx {
+    fn create_container(
+        &mut self,
+        name: String,
+    ) -> wasmtime::Result<Result<Resource<Container>, Error>> {
+        let now = self.blobstore_now();
+        if let Err(err) = self
+            .blobstore
+            .insert(&name, Objects::Memory(BTreeMap::default()), now)
+        {
+            return Ok(Err(format!("{err:#}")));
+        }
+        let container = self.table.push(Container {
+            store: self.blobstore.clone(),
+            name,
+        })?;
+        Ok(Ok(container))
+    }
+
+    fn get_container(
+        &mut self,
+        name: String,
+    ) -> wasmtime::Result<Result<Resource<Container>, Error>> {
+        if !self.blobstore.lock().contains_key(&name) {
+            return Ok(Err(format!("container `{name}` does not exist")));
+        }
+        let container = self.table.push(Container {
+            store: self.blobstore.clone(),
+            name,
+        })?;
+        Ok(Ok(container))
+    }
+
+    fn delete_container(&mut self, name: String) -> wasmtime::Result<Result<(), Error>> {
+        let mut containers = self.blobstore.lock();
+        let Some(container) = containers.get_mut(&name) else {
+            return Ok(Ok(()));
+        };
+        if let Err(err) = container.objects.clear() {
+            return Ok(Err(format!("{err:#}")));
+        }
+        containers.remove(&name);
+        Ok(Ok(()))
+    }
+
+    fn container_exists(&mut self, name: String) -> wasmtime::Result<Result<bool, Error>> {
+        Ok(Ok(self.blobstore.lock().contains_key(&name)))
+    }
+
+    fn copy_object(
+        &mut self,
+        src: ObjectId,
+        dest: ObjectId,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        Ok(self
+            .copy_blob(&src, &dest, false)
+            .map_err(|err| format!("{err:#}")))
+    }
+
+    fn move_object(
+        &mut self,
+        src: ObjectId,
+        dest: ObjectId,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        Ok(self
+            .copy_blob(&src, &dest, true)
+            .map_err(|err| format!("{err:#}")))
+    }
+}
+
+impl container::Host for Ctx {}
+
+impl container::HostContainer for Ctx {
+    fn name(&mut self, container: Resource<Container>) -> wasmtime::Result<Result<String, Error>> {
+        let container = self.container(&container)?;
+        Ok(Ok(container.name.clone()))
+    }
+
+    fn info(
+        &mut self,
+        container: Resource<Container>,
+    ) -> wasmtime::Result<Result<ContainerMetadata, Error>> {
+        let container = self.container(&container)?;
+        Ok(container.with(|state| {
+            Ok(ContainerMetadata {
+                name: container.name.clone(),
+                created_at: timestamp(state.created_at),
+            })
+        }))
+    }
+
+    fn get_data(
+        &mut self,
+        container: Resource<Container>,
+        name: String,
+        start: u64,
+        end: u64,
+    ) -> wasmtime::Result<Result<Resource<IncomingValue>, Error>> {
+        let container = self.container(&container)?;
+        let data = container.with(|state| {
+            let Some(Object { data, .. }) = state.objects.get(&name)? else {
+                bail!("object `{name}` does not exist")
+            };
+            // `start` and `end` are inclusive
+            let len = u64::try_from(data.len()).unwrap_or(u64::MAX);
+            let start = usize::try_from(start.min(len)).unwrap_or(usize::MAX);
+            let end = usize::try_from(end.saturating_add(1).min(len)).unwrap_or(usize::MAX);
+            Ok(data.slice(start..end.max(start)))
+        });
+        match data {
+            Ok(data) => {
+                let value = self.table.push(IncomingValue(data))?;
+                Ok(Ok(value))
+            }
+            Err(err) => Ok(Err(err)),
+        }
+    }
+
+    fn write_data(
+        &mut self,
+        container: Resource<Container>,
+        name: String,
+        data: Resource<OutgoingValue>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let now = self.blobstore_now();
+        let OutgoingValue(data) = self.table.get(&data)?;
+        let data = data.contents();
+        let container = self.container(&container)?;
+        Ok(container.with(|state| state.objects.put(&name, data, now)))
+    }
+
+    fn list_objects(
+        &mut self,
+        container: Resource<Container>,
+    ) -> wasmtime::Result<Result<Resource<StreamObjectNames>, Error>> {
+        let container = self.container(&container)?;
+        match container.with(|state| state.objects.names()) {
+            Ok(names) => {
+                let names = self.table.push(StreamObjectNames(names.into_iter()))?;
+                Ok(Ok(names))
+            }
+            Err(err) => Ok(Err(err)),
+        }
+    }
+
+    fn delete_object(
+        &mut self,
+        container: Resource<Container>,
+        name: String,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let container = self.container(&container)?;
+        Ok(container.with(|state| state.objects.remove(&name)))
+    }
+
+    fn delete_objects(
+        &mut self,
+        container: Resource<Container>,
+        names: Vec<String>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let container = self.container(&container)?;
+        Ok(container.with(|state| {
+            for name in names {
+                state.objects.remove(&name)?;
+            }
+            Ok(())
+        }))
+    }
+
+    fn has_object(
+        &mut self,
+        container: Resource<Container>,
+        name: String,
+    ) -> wasmtime::Result<Result<bool, Error>> {
+        let container = self.container(&container)?;
+        Ok(container.with(|state| state.objects.contains(&name)))
+    }
+
+    fn object_info(
+        &mut self,
+        container: Resource<Container>,
+        name: String,
+    ) -> wasmtime::Result<Result<ObjectMetadata, Error>> {
+        let container = self.container(&container)?;
+        Ok(container.with(|state| {
+            let Some(Object { data, created_at }) = state.objects.get(&name)? else {
+                bail!("object `{name}` does not exist")
+            };
+            Ok(ObjectMetadata {
+                name,
+                container: container.name.clone(),
+                created_at: timestamp(created_at),
+                size: u64::try_from(data.len()).unwrap_or(u64::MAX),
+            })
+        }))
+    }
+
+    fn clear(&mut self, container: Resource<Container>) -> wasmtime::Result<Result<(), Error>> {
+        let container = self.container(&container)?;
+        Ok(container.with(|state| state.objects.clear()))
+    }
+
+    fn drop(&mut self, container: Resource<Container>) -> wasmtime::Result<()> {
+        self.table.delete(container)?;
+        Ok(())
+    }
+}
+
+impl container::HostStreamObjectNames for Ctx {
+    fn read_stream_object_names(
+        &mut self,
+        names: Resource<StreamObjectNames>,
+        len: u64,
+    ) -> wasmtime::Result<Result<(Vec<String>, bool), Error>> {
+        let StreamObjectNames(names) = self.table.get_mut(&names)?;
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        let read = names.by_ref().take(len).collect();
+        Ok(Ok((read, names.len() == 0)))
+    }
+
+    fn skip_stream_object_names(
+        &mut self,
+        names: Resource<StreamObjectNames>,
+        num: u64,
+    ) -> wasmtime::Result<Result<(u64, bool), Error>> {
+        let StreamObjectNames(names) = self.table.get_mut(&names)?;
+        let num = usize::try_from(num).unwrap_or(usize::MAX);
+        let skipped = names.by_ref().take(num).count();
+        Ok(Ok((
+            u64::try_from(skipped).unwrap_or(u64::MAX),
+            names.len() == 0,
+        )))
+    }
+
+    fn drop(&mut self, names: Resource<StreamObjectNames>) -> wasmtime::Result<()> {
+        self.table.delete(names)?;
+        Ok(())
+    }
+}
+
+impl types::Host for Ctx {}
+
+impl types::HostOutgoingValue for Ctx {
+    fn new_outgoing_value(&mut self) -> wasmtime::Result<Resource<OutgoingValue>> {
+        let value = self
+            .table
+            .push(OutgoingValue(MemoryOutputPipe::new(usize::MAX)))?;
+        Ok(value)
+    }
+
+    fn outgoing_value_write_body(
+        &mut self,
+        value: Resource<OutgoingValue>,
+    ) -> wasmtime::Result<Result<Resource<DynOutputStream>, ()>> {
+        let OutgoingValue(pipe) = self.table.get(&value)?;
+        let stream: DynOutputStream = Box::new(pipe.clone());
+        let stream = self.table.push(stream)?;
+        Ok(Ok(stream))
+    }
+
+    fn finish(&mut self, value: Resource<OutgoingValue>) -> wasmtime::Result<Result<(), Error>> {
+        self.table.delete(value)?;
+        Ok(Ok(()))
+    }
+
+    fn drop(&mut self, value: Resource<OutgoingValue>) -> wasmtime::Result<()> {
+        self.table.delete(value)?;
+        Ok(())
+    }
+}
+
+impl types::HostIncomingValue for Ctx {
+    fn incoming_value_consume_sync(
+        &mut self,
+        value: Resource<IncomingValue>,
+    ) -> wasmtime::Result<Result<Vec<u8>, Error>> {
+        let IncomingValue(data) = self.table.delete(value)?;
+        Ok(Ok(data.into()))
+    }
+
+    fn incoming_value_consume_async(
+        &mut self,
+        value: Resource<IncomingValue>,
+    ) -> wasmtime::Result<Result<Resource<DynInputStream>, Error>> {
+        let IncomingValue(data) = self.table.delete(value)?;
+        let stream: DynInputStream = Box::new(MemoryInputPipe::new(data));
+        let stream = self.table.push(stream)?;
+        Ok(Ok(stream))
+    }
+
+    fn size(&mut self, value: Resource<IncomingValue>) -> wasmtime::Result<u64> {
+        let IncomingValue(data) = self.table.get(&value)?;
+        Ok(u64::try_from(data.len()).unwrap_or(u64::MAX))
+    }
+
+    fn drop(&mut self, value: Resource<IncomingValue>) -> wasmtime::Result<()> {
+        self.table.delete(value)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_names() {
+        let dir = Path::new("container");
+        for (name, path) in [
+            ("foo", "container/foo"),
+            ("foo.txt", "container/foo.txt"),
+            ("a/b/c", "container/a/b/c"),
+            ("...", "container/..."),
+            (".hidden", "container/.hidden"),
+        ] {
+            assert_eq!(object_path(dir, name).unwrap(), Path::new(path), "{name}");
+        }
+        for name in [
+            "", "/", "/foo", "foo/", "a//b", ".", "..", "../foo", "a/../b", "a/./b", "a\\b",
+        ] {
+            assert!(object_path(dir, name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn memory() {
+        let store = BlobStore::new();
+        store.create_container("foo").unwrap();
+        assert!(store.create_container("foo").is_err());
+        assert_eq!(store.containers(), ["foo"]);
+        assert!(store.objects("missing").is_err());
+
+        let clone = store.clone();
+        clone.write("foo", "b", "2").unwrap();
+        clone.write("foo", "a/b", "1").unwrap();
+        assert_eq!(store.objects("foo").unwrap(), ["a/b", "b"]);
+        assert_eq!(store.read("foo", "a/b").unwrap(), b"1");
+        assert!(store.read("foo", "a").is_err());
+
+        store.delete("foo", "a/b").unwrap();
+        store.delete("foo", "a/b").unwrap();
+        assert_eq!(clone.objects("foo").unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn dir() {
+        let path = std::env::temp_dir().join(format!("wadge-blobstore-{}", std::process::id()));
+        // remove leftovers of aborted runs
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("seeded/nested")).unwrap();
+        fs::write(path.join("seeded/nested/obj"), "seeded").unwrap();
+
+        let store = BlobStore::new();
+        store.create_dir_container("foo", &path).unwrap();
+        assert_eq!(store.objects("foo").unwrap(), ["seeded/nested/obj"]);
+        assert_eq!(store.read("foo", "seeded/nested/obj").unwrap(), b"seeded");
+
+        store.write("foo", "a/b/c", "value").unwrap();
+        assert_eq!(fs::read(path.join("a/b/c")).unwrap(), b"value");
+        assert!(store.write("foo", "../escape", "value").is_err());
+        assert!(store.read("foo", "a/../seeded/nested/obj").is_err());
+        assert!(!path.with_file_name("escape").exists());
+        assert_eq!(
+            store.objects("foo").unwrap(),
+            ["a/b/c", "seeded/nested/obj"]
+        );
+
+        store.delete("foo", "a/b/c").unwrap();
+        assert!(!path.join("a/b/c").exists());
+        assert_eq!(store.objects("foo").unwrap(), ["seeded/nested/obj"]);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
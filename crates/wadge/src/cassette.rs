@@ -0,0 +1,437 @@
+//! Outgoing `wasi:http` record/replay cassettes
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::Context as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{debug, warn};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+/// Outgoing `wasi:http` cassette mode
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CassetteMode {
+    /// Send requests to their destinations and write all exchanges to the cassette
+    /// on [`Instance::flush`](crate::Instance::flush) and once the instance is dropped
+    #[default]
+    Record,
+    /// Serve requests from the cassette, unmatched requests fail
+    Replay,
+}
+
+/// Outgoing `wasi:http` cassette configuration
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cassette {
+    /// Path to the JSON cassette file
+    pub path: PathBuf,
+    /// Cassette mode
+    pub mode: CassetteMode,
+    /// Names of request headers, which must match in addition to method and URI
+    /// for a request to be served from the cassette in replay mode
+    pub match_headers: Vec<String>,
+}
+
+fn serialize_body<S: Serializer>(body: &Bytes, s: S) -> Result<S::Ok, S::Error> {
+    BASE64.encode(body).serialize(s)
+}
+
+fn deserialize_body<'de, D: Deserializer<'de>>(d: D) -> Result<Bytes, D::Error> {
+    let body = String::deserialize(d)?;
+    BASE64
+        .decode(body)
+        .map(Bytes::from)
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Request {
+    pub method: String,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    #[serde(
+        serialize_with = "serialize_body",
+        deserialize_with = "deserialize_body"
+    )]
+    pub body: Bytes,
+}
+
+impl Request {
+    pub fn new(req: &http::Request<Bytes>) -> Self {
+        Self {
+            method: req.method().to_string(),
+            uri: req.uri().to_string(),
+            headers: headers(req.headers()),
+            body: req.body().clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(
+        serialize_with = "serialize_body",
+        deserialize_with = "deserialize_body"
+    )]
+    pub body: Bytes,
+}
+
+impl Response {
+    pub fn new(resp: &http::Response<Bytes>) -> Self {
+        Self {
+            status: resp.status().as_u16(),
+            headers: headers(resp.headers()),
+            body: resp.body().clone(),
+        }
+    }
+
+    fn to_http(&self) -> anyhow::Result<http::Response<Bytes>> {
+        let mut resp = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            resp = resp.header(name, value);
+        }
+        resp.body(self.body.clone())
+            .context("failed to construct response")
+    }
+}
+
+fn headers(headers: &http::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into(),
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Interaction {
+    request: Request,
+    response: Response,
+    #[serde(skip)]
+    replayed: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Interactions {
+    interactions: Vec<Interaction>,
+    /// Whether interactions were recorded since the cassette was last written
+    #[serde(skip)]
+    dirty: bool,
+}
+
+/// Loaded cassette shared by all outgoing requests of an instance.
+///
+/// In record mode, the cassette is written on [`CassetteState::flush`] and on drop,
+/// an existing cassette is only overwritten once at least one exchange is recorded.
+pub(crate) struct CassetteState {
+    config: Cassette,
+    interactions: Mutex<Interactions>,
+}
+
+impl CassetteState {
+    pub fn new(config: Cassette) -> anyhow::Result<Self> {
+        let interactions = match config.mode {
+            CassetteMode::Record => Interactions::default(),
+            CassetteMode::Replay => {
+                let buf = fs::read(&config.path).with_context(|| {
+                    format!("failed to read cassette `{}`", config.path.display())
+                })?;
+                serde_json::from_slice(&buf).with_context(|| {
+                    format!("failed to parse cassette `{}`", config.path.display())
+                })?
+            }
+        };
+        Ok(Self {
+            config,
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Interactions> {
+        self.interactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.config.mode
+    }
+
+    fn matches(&self, recorded: &Request, req: &http::Request<Bytes>) -> bool {
+        recorded.method == req.method().as_str()
+            && recorded.uri == req.uri().to_string()
+            && self.config.match_headers.iter().all(|name| {
+                let values = req
+                    .headers()
+                    .get_all(name.as_str())
+                    .iter()
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()));
+                let recorded = recorded
+                    .headers
+                    .iter()
+                    .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| v.as_str());
+                values.eq(recorded)
+            })
+    }
+
+    /// Returns the recorded response to `req`, preferring interactions not replayed yet
+    pub fn replay(&self, req: &http::Request<Bytes>) -> Result<http::Response<Bytes>, ErrorCode> {
+        let mut interactions = self.lock();
+        let interactions = &mut interactions.interactions;
+        let Some(i) = interactions
+            .iter()
+            .position(|interaction| {
+                !interaction.replayed && self.matches(&interaction.request, req)
+            })
+            .or_else(|| {
+                interactions
+                    .iter()
+                    .position(|interaction| self.matches(&interaction.request, req))
+            })
+        else {
+            warn!(method = %req.method(), uri = %req.uri(), "no cassette interaction matches request");
+            return Err(ErrorCode::InternalError(Some(format!(
+                "no interaction in cassette `{}` matches `{} {}`",
+                self.config.path.display(),
+                req.method(),
+                req.uri(),
+            ))));
+        };
+        let interaction = &mut interactions[i];
+        interaction.replayed = true;
+        debug!(method = %req.method(), uri = %req.uri(), "replaying cassette interaction");
+        interaction.response.to_http().map_err(|err| {
+            ErrorCode::InternalError(Some(format!("invalid cassette response: {err:#}")))
+        })
+    }
+
+    /// Appends an exchange to the cassette
+    pub fn record(&self, req: Request, resp: Response) {
+        let mut interactions = self.lock();
+        interactions.interactions.push(Interaction {
+            request: req,
+            response: resp,
+            replayed: false,
+        });
+        interactions.dirty = true;
+    }
+
+    /// Writes the cassette to disk, if any exchanges were recorded since it was last written
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let mut interactions = self.lock();
+        if !interactions.dirty {
+            return Ok(());
+        }
+        let buf = serde_json::to_vec_pretty(&*interactions).context("failed to encode cassette")?;
+        fs::write(&self.config.path, buf).with_context(|| {
+            format!("failed to write cassette `{}`", self.config.path.display())
+        })?;
+        interactions.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for CassetteState {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            warn!(?err, "failed to write cassette");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    fn request(method: &str, uri: &str, headers: &[(&str, &str)]) -> http::Request<Bytes> {
+        let mut req = http::Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(Bytes::new()).expect("failed to construct request")
+    }
+
+    fn interaction(req: &http::Request<Bytes>, status: u16) -> Interaction {
+        Interaction {
+            request: Request::new(req),
+            response: Response {
+                status,
+                headers: vec![("content-type".into(), "text/plain".into())],
+                body: Bytes::from_static(b"recorded"),
+            },
+            replayed: false,
+        }
+    }
+
+    fn cassette(match_headers: &[&str], interactions: Vec<Interaction>) -> CassetteState {
+        CassetteState {
+            config: Cassette {
+                path: PathBuf::from("cassette.json"),
+                mode: CassetteMode::Replay,
+                match_headers: match_headers.iter().map(|name| (*name).into()).collect(),
+            },
+            interactions: Mutex::new(Interactions {
+                interactions,
+                dirty: false,
+            }),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("wadge-cassette-{}-{name}.json", process::id()))
+    }
+
+    #[test]
+    fn matches() {
+        let recorded = Request::new(&request(
+            "GET",
+            "http://example.com/foo?bar=baz",
+            &[("accept", "text/plain"), ("x-id", "1"), ("x-id", "2")],
+        ));
+        let state = cassette(&[], Vec::default());
+        assert!(state.matches(
+            &recorded,
+            &request("GET", "http://example.com/foo?bar=baz", &[])
+        ));
+        assert!(!state.matches(
+            &recorded,
+            &request("POST", "http://example.com/foo?bar=baz", &[])
+        ));
+        assert!(!state.matches(&recorded, &request("GET", "http://example.com/foo", &[])));
+        assert!(!state.matches(
+            &recorded,
+            &request("GET", "https://example.com/foo?bar=baz", &[])
+        ));
+
+        let state = cassette(&["Accept", "x-id"], Vec::default());
+        assert!(state.matches(
+            &recorded,
+            &request(
+                "GET",
+                "http://example.com/foo?bar=baz",
+                &[("Accept", "text/plain"), ("X-Id", "1"), ("X-Id", "2")],
+            )
+        ));
+        assert!(!state.matches(
+            &recorded,
+            &request(
+                "GET",
+                "http://example.com/foo?bar=baz",
+                &[("accept", "application/json"), ("x-id", "1"), ("x-id", "2")],
+            )
+        ));
+        assert!(!state.matches(
+            &recorded,
+            &request(
+                "GET",
+                "http://example.com/foo?bar=baz",
+                &[("accept", "text/plain"), ("x-id", "2"), ("x-id", "1")],
+            )
+        ));
+        assert!(!state.matches(
+            &recorded,
+            &request(
+                "GET",
+                "http://example.com/foo?bar=baz",
+                &[("accept", "text/plain")],
+            )
+        ));
+    }
+
+    #[test]
+    fn replay() {
+        let foo = request("GET", "http://example.com/foo", &[]);
+        let foo_json = request(
+            "GET",
+            "http://example.com/foo",
+            &[("accept", "application/json")],
+        );
+        let state = cassette(
+            &["accept"],
+            vec![
+                interaction(&foo, 200),
+                interaction(&foo_json, 201),
+                interaction(&foo, 202),
+            ],
+        );
+
+        let resp = state.replay(&foo_json).unwrap();
+        assert_eq!(resp.status(), 201);
+        assert_eq!(resp.headers()["content-type"], "text/plain");
+        assert_eq!(resp.body().as_ref(), b"recorded");
+
+        // Interactions not replayed yet are preferred, then the first match is reused
+        assert_eq!(state.replay(&foo).unwrap().status(), 200);
+        assert_eq!(state.replay(&foo).unwrap().status(), 202);
+        assert_eq!(state.replay(&foo).unwrap().status(), 200);
+        assert_eq!(state.replay(&foo_json).unwrap().status(), 201);
+
+        let err = state
+            .replay(&request("GET", "http://example.com/bar", &[]))
+            .unwrap_err();
+        assert!(matches!(err, ErrorCode::InternalError(Some(..))), "{err:?}");
+        let err = state
+            .replay(&request(
+                "GET",
+                "http://example.com/foo",
+                &[("accept", "text/html")],
+            ))
+            .unwrap_err();
+        assert!(matches!(err, ErrorCode::InternalError(Some(..))), "{err:?}");
+    }
+
+    #[test]
+    fn record() {
+        let path = temp_path("record");
+        fs::write(&path, "existing").unwrap();
+        let config = Cassette {
+            path: path.clone(),
+            mode: CassetteMode::Record,
+            match_headers: Vec::default(),
+        };
+
+        // Existing cassette is kept intact if nothing is recorded
+        let state = CassetteState::new(config.clone()).unwrap();
+        state.flush().unwrap();
+        drop(state);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "existing");
+
+        let state = CassetteState::new(config.clone()).unwrap();
+        let foo = request("GET", "http://example.com/foo", &[]);
+        let bar = request("POST", "http://example.com/bar", &[]);
+        let Interaction {
+            request, response, ..
+        } = interaction(&foo, 200);
+        state.record(request, response);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "existing");
+        state.flush().unwrap();
+        let Interaction {
+            request, response, ..
+        } = interaction(&bar, 201);
+        state.record(request, response);
+        drop(state);
+
+        let state = CassetteState::new(Cassette {
+            mode: CassetteMode::Replay,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(state.replay(&foo).unwrap().status(), 200);
+        assert_eq!(state.replay(&bar).unwrap().status(), 201);
+        drop(state);
+        fs::remove_file(&path).unwrap();
+    }
+}
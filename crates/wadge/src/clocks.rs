@@ -0,0 +1,405 @@
+//! Virtual `wasi:clocks` implementation
+
+use core::future::poll_fn;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use anyhow::Context as _;
+use tracing::debug;
+use wasmtime::component::Resource;
+use wasmtime_wasi::clocks::WasiClocksView as _;
+use wasmtime_wasi::p2::bindings::clocks::monotonic_clock::{self, Instant};
+use wasmtime_wasi::p2::{subscribe, DynPollable, Pollable};
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock};
+
+use crate::Ctx;
+
+struct State {
+    /// Wall clock time since Unix epoch
+    wall: Duration,
+    /// Monotonic clock time in nanoseconds
+    monotonic: u64,
+    auto_advance: bool,
+    /// Deadlines currently awaited by the guest, keyed by waiter ID
+    waiting: HashMap<u64, Waiting>,
+    next_waiter: u64,
+}
+
+struct Waiting {
+    deadline: u64,
+    waker: Waker,
+    /// Whether the waiter has already been polled once since the last advance.
+    /// Auto-advance only happens on the second poll, which gives all other
+    /// pollables the guest is waiting on a chance to resolve first.
+    armed: bool,
+}
+
+impl State {
+    fn advance(&mut self, duration: Duration) {
+        self.wall = self.wall.saturating_add(duration);
+        self.monotonic = self
+            .monotonic
+            .saturating_add(duration.as_nanos().try_into().unwrap_or(u64::MAX));
+        for waiting in self.waiting.values_mut() {
+            waiting.armed = false;
+            waiting.waker.wake_by_ref();
+        }
+    }
+}
+
+/// Virtual clock backing `wasi:clocks/wall-clock` and `wasi:clocks/monotonic-clock`.
+///
+/// Time is frozen unless advanced explicitly via [`VirtualClock::advance`] or,
+/// if auto-advance is enabled, by the guest blocking on `monotonic-clock` pollables,
+/// in which case the clock jumps to the earliest deadline being waited on.
+///
+/// Clones of [`VirtualClock`] share the same underlying state.
+#[derive(Clone)]
+pub struct VirtualClock(Arc<Mutex<State>>);
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl VirtualClock {
+    /// Constructs a new frozen [`VirtualClock`] with wall clock set to `now`
+    #[must_use]
+    pub fn new(now: SystemTime) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            wall: now
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default(),
+            monotonic: 0,
+            auto_advance: false,
+            waiting: HashMap::default(),
+            next_waiter: 0,
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns current wall clock time
+    #[must_use]
+    pub fn now(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + self.lock().wall
+    }
+
+    /// Returns current monotonic clock time in nanoseconds
+    #[must_use]
+    pub fn monotonic_now(&self) -> u64 {
+        self.lock().monotonic
+    }
+
+    /// Sets wall clock time to `now`, monotonic clock is not affected
+    pub fn set(&self, now: SystemTime) {
+        self.lock().wall = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+    }
+
+    /// Advances both wall and monotonic clocks by `duration`, resolving
+    /// all guest pollables, which deadlines are reached
+    pub fn advance(&self, duration: Duration) {
+        self.lock().advance(duration);
+    }
+
+    /// Configures whether guest waiting on `monotonic-clock` pollables advances the clock
+    pub fn set_auto_advance(&self, auto_advance: bool) {
+        self.lock().auto_advance = auto_advance;
+    }
+
+    /// Returns `true` if guest waiting on `monotonic-clock` pollables advances the clock
+    #[must_use]
+    pub fn auto_advance(&self) -> bool {
+        self.lock().auto_advance
+    }
+
+    fn poll_deadline(&self, cx: &mut Context<'_>, id: u64, deadline: u64) -> Poll<()> {
+        let mut state = self.lock();
+        if state.monotonic >= deadline {
+            state.waiting.remove(&id);
+            return Poll::Ready(());
+        }
+        let auto_advance = state.auto_advance;
+        match state.waiting.get_mut(&id) {
+            Some(waiting) if auto_advance && waiting.armed => {}
+            Some(waiting) => {
+                if !waiting.waker.will_wake(cx.waker()) {
+                    waiting.waker = cx.waker().clone();
+                }
+                waiting.armed = true;
+                if auto_advance {
+                    cx.waker().wake_by_ref();
+                }
+                return Poll::Pending;
+            }
+            None => {
+                state.waiting.insert(
+                    id,
+                    Waiting {
+                        deadline,
+                        waker: cx.waker().clone(),
+                        armed: true,
+                    },
+                );
+                if auto_advance {
+                    cx.waker().wake_by_ref();
+                }
+                return Poll::Pending;
+            }
+        }
+        // The guest is blocked on deadlines only, advance to the earliest one
+        let next = state
+            .waiting
+            .values()
+            .map(|Waiting { deadline, .. }| *deadline)
+            .min()
+            .unwrap_or(deadline);
+        let duration = Duration::from_nanos(next.saturating_sub(state.monotonic));
+        debug!(?duration, "auto-advancing virtual clock");
+        state.advance(duration);
+        if state.monotonic >= deadline {
+            state.waiting.remove(&id);
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn waiter(&self, deadline: u64) -> Waiter {
+        let mut state = self.lock();
+        let id = state.next_waiter;
+        state.next_waiter = state.next_waiter.wrapping_add(1);
+        Waiter {
+            clock: self.clone(),
+            id,
+            deadline,
+        }
+    }
+}
+
+/// A single guest wait on a [`Deadline`], unregistered from the clock on drop
+struct Waiter {
+    clock: VirtualClock,
+    id: u64,
+    deadline: u64,
+}
+
+impl Waiter {
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.clock.poll_deadline(cx, self.id, self.deadline)
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.clock.lock().waiting.remove(&self.id);
+    }
+}
+
+pub(crate) struct WallClock(pub VirtualClock);
+
+impl HostWallClock for WallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.0.lock().wall
+    }
+}
+
+pub(crate) struct MonotonicClock(pub VirtualClock);
+
+impl HostMonotonicClock for MonotonicClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.0.monotonic_now()
+    }
+}
+
+struct Deadline {
+    clock: VirtualClock,
+    deadline: u64,
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for Deadline {
+    async fn ready(&mut self) {
+        let waiter = self.clock.waiter(self.deadline);
+        poll_fn(|cx| waiter.poll(cx)).await;
+    }
+}
+
+impl Ctx {
+    fn subscribe_virtual(
+        &mut self,
+        clock: VirtualClock,
+        deadline: u64,
+    ) -> wasmtime::Result<Resource<DynPollable>> {
+        let deadline = self
+            .table
+            .push(Deadline { clock, deadline })
+            .context("failed to push deadline into resource table")?;
+        subscribe(&mut self.table, deadline)
+    }
+}
+
+impl monotonic_clock::Host for Ctx {
+    fn now(&mut self) -> wasmtime::Result<Instant> {
+        monotonic_clock::Host::now(&mut self.clocks())
+    }
+
+    fn resolution(&mut self) -> wasmtime::Result<Instant> {
+        monotonic_clock::Host::resolution(&mut self.clocks())
+    }
+
+    fn subscribe_instant(&mut self, when: Instant) -> wasmtime::Result<Resource<DynPollable>> {
+        let Some(clock) = self.clock.clone() else {
+            return monotonic_clock::Host::subscribe_instant(&mut self.clocks(), when);
+        };
+        self.subscribe_virtual(clock, when)
+    }
+
+    fn subscribe_duration(
+        &mut self,
+        duration: monotonic_clock::Duration,
+    ) -> wasmtime::Result<Resource<DynPollable>> {
+        let Some(clock) = self.clock.clone() else {
+            return monotonic_clock::Host::subscribe_duration(&mut self.clocks(), duration);
+        };
+        let deadline = clock.monotonic_now().saturating_add(duration);
+        self.subscribe_virtual(clock, deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl CountingWaker {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn advance() {
+        let clock = VirtualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(42));
+        let waker = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&waker));
+        let mut cx = Context::from_waker(&waker);
+
+        let waiter = clock.waiter(10);
+        assert!(waiter.poll(&mut cx).is_pending());
+        clock.advance(Duration::from_nanos(5));
+        assert!(waiter.poll(&mut cx).is_pending());
+        clock.advance(Duration::from_nanos(5));
+        assert!(waiter.poll(&mut cx).is_ready());
+        assert_eq!(clock.monotonic_now(), 10);
+        assert_eq!(
+            clock.now(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(42) + Duration::from_nanos(10)
+        );
+
+        clock.set(SystemTime::UNIX_EPOCH);
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+        assert_eq!(clock.monotonic_now(), 10);
+    }
+
+    #[test]
+    fn single_waker_per_deadline() {
+        let clock = VirtualClock::default();
+        let wakes = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let waiter = clock.waiter(10);
+        for _ in 0..3 {
+            assert!(waiter.poll(&mut cx).is_pending());
+        }
+        assert_eq!(clock.lock().waiting.len(), 1);
+        clock.advance(Duration::from_nanos(1));
+        assert_eq!(wakes.count(), 1);
+
+        drop(waiter);
+        assert!(clock.lock().waiting.is_empty());
+        clock.advance(Duration::from_nanos(1));
+        assert_eq!(wakes.count(), 1);
+    }
+
+    #[test]
+    fn auto_advance_when_blocked() {
+        let clock = VirtualClock::default();
+        clock.set_auto_advance(true);
+        let wakes = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        // Creating a waiter or polling it once, like `pollable.ready` does, does not advance
+        let waiter = clock.waiter(10);
+        assert_eq!(clock.monotonic_now(), 0);
+        assert!(waiter.poll(&mut cx).is_pending());
+        assert_eq!(clock.monotonic_now(), 0);
+        assert_eq!(wakes.count(), 1);
+        drop(waiter);
+
+        // Polling again, like `pollable.block` does, advances to the deadline
+        let waiter = clock.waiter(10);
+        assert!(waiter.poll(&mut cx).is_pending());
+        assert!(waiter.poll(&mut cx).is_ready());
+        assert_eq!(clock.monotonic_now(), 10);
+        assert!(clock.lock().waiting.is_empty());
+    }
+
+    #[test]
+    fn auto_advance_earliest_deadline() {
+        let clock = VirtualClock::default();
+        clock.set_auto_advance(true);
+        let wakes = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        // Emulates `poll` with later deadline listed first
+        let late = clock.waiter(20);
+        let early = clock.waiter(10);
+        assert!(late.poll(&mut cx).is_pending());
+        assert!(early.poll(&mut cx).is_pending());
+        assert!(late.poll(&mut cx).is_pending());
+        assert!(early.poll(&mut cx).is_ready());
+        assert_eq!(clock.monotonic_now(), 10);
+
+        // Remaining deadline needs to be waited on again to advance further
+        assert!(late.poll(&mut cx).is_pending());
+        assert_eq!(clock.monotonic_now(), 10);
+        assert!(late.poll(&mut cx).is_ready());
+        assert_eq!(clock.monotonic_now(), 20);
+    }
+}
@@ -0,0 +1,26 @@
+//! `wasi:config` implementation
+
+use crate::Ctx;
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "wasi:config/imports@0.2.0-draft",
+        imports: { default: trappable },
+    });
+}
+
+pub(crate) use bindings::wasi::config::store;
+
+impl store::Host for Ctx {
+    fn get(&mut self, key: String) -> wasmtime::Result<Result<Option<String>, store::Error>> {
+        Ok(Ok(self.runtime_config.get(&key).cloned()))
+    }
+
+    fn get_all(&mut self) -> wasmtime::Result<Result<Vec<(String, String)>, store::Error>> {
+        Ok(Ok(self
+            .runtime_config
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()))
+    }
+}
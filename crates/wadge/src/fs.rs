@@ -0,0 +1,1267 @@
+//! In-memory `wasi:filesystem` implementation
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Context as _};
+use bytes::Bytes;
+use wasmtime::component::Resource;
+use wasmtime_wasi::filesystem::WasiFilesystemView as _;
+use wasmtime_wasi::p2::bindings::filesystem::preopens;
+use wasmtime_wasi::p2::bindings::filesystem::types::ErrorCode;
+use wasmtime_wasi::p2::bindings::sync::filesystem::types::{
+    self, Advice, DescriptorFlags, DescriptorStat, DescriptorType, DirectoryEntry, Filesize,
+    HostDescriptor, HostDirectoryEntryStream, MetadataHashValue, NewTimestamp, OpenFlags,
+    PathFlags,
+};
+use wasmtime_wasi::p2::bindings::sync::io::streams;
+use wasmtime_wasi::p2::{
+    DynInputStream, DynOutputStream, FsError, FsResult, InputStream, OutputStream, Pollable,
+    StreamError, StreamResult,
+};
+
+use crate::Ctx;
+
+/// Maximum number of bytes accepted by a single write to an in-memory file stream
+const WRITE_BUDGET: usize = 1 << 20;
+
+type File = Arc<Mutex<Vec<u8>>>;
+type Dir = Arc<Mutex<BTreeMap<String, Node>>>;
+
+#[derive(Clone)]
+enum Node {
+    File(File),
+    Dir(Dir),
+}
+
+impl Node {
+    fn ty(&self) -> DescriptorType {
+        match self {
+            Self::File(..) => DescriptorType::RegularFile,
+            Self::Dir(..) => DescriptorType::Directory,
+        }
+    }
+
+    fn id(&self) -> u64 {
+        match self {
+            Self::File(file) => Arc::as_ptr(file) as u64,
+            Self::Dir(dir) => Arc::as_ptr(dir) as u64,
+        }
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Splits `path` into normalized components, rejecting paths escaping the base directory
+fn normalize(path: &str) -> FsResult<Vec<&str>> {
+    if path.starts_with('/') {
+        return Err(ErrorCode::NotPermitted.into());
+    }
+    let mut names = Vec::default();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                if names.pop().is_none() {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+            }
+            name => names.push(name),
+        }
+    }
+    Ok(names)
+}
+
+fn lookup_dir(base: &Dir, names: &[&str]) -> FsResult<Dir> {
+    let mut dir = Arc::clone(base);
+    for name in names {
+        let next = match lock(&dir).get(*name) {
+            Some(Node::Dir(dir)) => Arc::clone(dir),
+            Some(Node::File(..)) => return Err(ErrorCode::NotDirectory.into()),
+            None => return Err(ErrorCode::NoEntry.into()),
+        };
+        dir = next;
+    }
+    Ok(dir)
+}
+
+fn lookup(base: &Dir, path: &str) -> FsResult<Node> {
+    let names = normalize(path)?;
+    let Some((name, parents)) = names.split_last() else {
+        return Ok(Node::Dir(Arc::clone(base)));
+    };
+    let dir = lookup_dir(base, parents)?;
+    let node = lock(&dir).get(*name).cloned();
+    node.ok_or_else(|| ErrorCode::NoEntry.into())
+}
+
+/// Looks up the parent directory of `path` and returns it along with the final path component
+fn lookup_parent<'a>(base: &Dir, path: &'a str) -> FsResult<(Dir, &'a str)> {
+    let names = normalize(path)?;
+    let Some((name, parents)) = names.split_last() else {
+        return Err(ErrorCode::Invalid.into());
+    };
+    let dir = lookup_dir(base, parents)?;
+    Ok((dir, name))
+}
+
+/// Returns `true` if `dir` is `target` or contains it
+fn contains_dir(dir: &Dir, target: &Dir) -> bool {
+    if Arc::ptr_eq(dir, target) {
+        return true;
+    }
+    let children = lock(dir)
+        .values()
+        .filter_map(|node| match node {
+            Node::Dir(dir) => Some(Arc::clone(dir)),
+            Node::File(..) => None,
+        })
+        .collect::<Vec<_>>();
+    children.iter().any(|child| contains_dir(child, target))
+}
+
+fn stat(node: &Node) -> DescriptorStat {
+    let size = match node {
+        Node::File(file) => lock(file).len(),
+        Node::Dir(dir) => lock(dir).len(),
+    };
+    DescriptorStat {
+        type_: node.ty(),
+        link_count: 1,
+        size: size.try_into().unwrap_or(u64::MAX),
+        data_access_timestamp: None,
+        data_modification_timestamp: None,
+        status_change_timestamp: None,
+    }
+}
+
+fn metadata_hash(node: &Node) -> MetadataHashValue {
+    MetadataHashValue {
+        lower: node.id(),
+        upper: 0,
+    }
+}
+
+/// In-memory filesystem, which can be exposed to the guest as a preopened directory.
+///
+/// Clones of [`MemoryFs`] share the same underlying filesystem, which allows
+/// native code to inspect files written by the guest.
+#[derive(Clone, Default)]
+pub struct MemoryFs {
+    root: Dir,
+}
+
+impl MemoryFs {
+    /// Constructs a new empty [`MemoryFs`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a new [`MemoryFs`] containing `files`, indexed by path
+    pub fn from_files<P, B>(files: impl IntoIterator<Item = (P, B)>) -> anyhow::Result<Self>
+    where
+        P: AsRef<str>,
+        B: Into<Vec<u8>>,
+    {
+        let fs = Self::new();
+        for (path, buf) in files {
+            let path = path.as_ref();
+            fs.write(path, buf)
+                .with_context(|| format!("failed to write `{path}`"))?;
+        }
+        Ok(fs)
+    }
+
+    /// Constructs a new [`MemoryFs`] containing files and directories from a tar archive
+    pub fn from_tar(tar: impl Read) -> anyhow::Result<Self> {
+        let fs = Self::new();
+        let mut tar = tar::Archive::new(tar);
+        for entry in tar.entries().context("failed to read tar entries")? {
+            let mut entry = entry.context("failed to read tar entry")?;
+            let path = entry.path().context("failed to read tar entry path")?;
+            let path = path
+                .to_str()
+                .context("tar entry path is not valid UTF-8")?
+                .to_string();
+            match entry.header().entry_type() {
+                tar::EntryType::Directory => {
+                    fs.create_dir_all(&path)
+                        .with_context(|| format!("failed to create directory `{path}`"))?;
+                }
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let mut buf = Vec::default();
+                    entry
+                        .read_to_end(&mut buf)
+                        .with_context(|| format!("failed to read `{path}` from tar"))?;
+                    fs.write(&path, buf)
+                        .with_context(|| format!("failed to write `{path}`"))?;
+                }
+                ty => bail!("unsupported tar entry type `{ty:?}` at `{path}`"),
+            }
+        }
+        Ok(fs)
+    }
+
+    /// Creates a directory at `path` along with all missing parent directories
+    pub fn create_dir_all(&self, path: &str) -> anyhow::Result<()> {
+        let names = normalize(path).map_err(|_| anyhow::anyhow!("invalid path"))?;
+        let mut dir = Arc::clone(&self.root);
+        for name in names {
+            let next = match lock(&dir)
+                .entry(name.into())
+                .or_insert_with(|| Node::Dir(Dir::default()))
+            {
+                Node::Dir(dir) => Arc::clone(dir),
+                Node::File(..) => bail!("`{name}` is not a directory"),
+            };
+            dir = next;
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` to a file at `path`, creating it along with all missing
+    /// parent directories if it does not exist
+    pub fn write(&self, path: &str, buf: impl Into<Vec<u8>>) -> anyhow::Result<()> {
+        let names = normalize(path).map_err(|_| anyhow::anyhow!("invalid path"))?;
+        let Some((name, parents)) = names.split_last() else {
+            bail!("path must not be empty")
+        };
+        self.create_dir_all(&parents.join("/"))?;
+        let dir = lookup_dir(&self.root, parents).map_err(|_| anyhow::anyhow!("invalid path"))?;
+        let mut dir = lock(&dir);
+        match dir.get(*name) {
+            Some(Node::File(file)) => {
+                *lock(file) = buf.into();
+            }
+            Some(Node::Dir(..)) => bail!("`{path}` is a directory"),
+            None => {
+                dir.insert((*name).into(), Node::File(Arc::new(Mutex::new(buf.into()))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads contents of a file at `path`
+    pub fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        match lookup(&self.root, path) {
+            Ok(Node::File(file)) => Ok(lock(&file).clone()),
+            Ok(Node::Dir(..)) => bail!("`{path}` is a directory"),
+            Err(..) => bail!("`{path}` not found"),
+        }
+    }
+
+    /// Returns contents of all files in the filesystem, indexed by path
+    #[must_use]
+    pub fn files(&self) -> BTreeMap<String, Vec<u8>> {
+        fn collect(dir: &Dir, prefix: &str, files: &mut BTreeMap<String, Vec<u8>>) {
+            for (name, node) in lock(dir).iter() {
+                let path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{prefix}/{name}")
+                };
+                match node {
+                    Node::File(file) => {
+                        files.insert(path, lock(file).clone());
+                    }
+                    Node::Dir(dir) => collect(dir, &path, files),
+                }
+            }
+        }
+        let mut files = BTreeMap::default();
+        collect(&self.root, "", &mut files);
+        files
+    }
+
+    pub(crate) fn root_descriptor(&self) -> Descriptor {
+        Descriptor {
+            node: Node::Dir(Arc::clone(&self.root)),
+            flags: DescriptorFlags::READ | DescriptorFlags::MUTATE_DIRECTORY,
+        }
+    }
+}
+
+/// `wasi:filesystem/types.descriptor` referring to a [`MemoryFs`] node
+#[derive(Clone)]
+pub(crate) struct Descriptor {
+    node: Node,
+    flags: DescriptorFlags,
+}
+
+impl Descriptor {
+    fn file(&self) -> FsResult<&File> {
+        match &self.node {
+            Node::File(file) => Ok(file),
+            Node::Dir(..) => Err(ErrorCode::IsDirectory.into()),
+        }
+    }
+
+    fn dir(&self) -> FsResult<&Dir> {
+        match &self.node {
+            Node::Dir(dir) => Ok(dir),
+            Node::File(..) => Err(ErrorCode::NotDirectory.into()),
+        }
+    }
+
+    fn writable_file(&self) -> FsResult<&File> {
+        let file = self.file()?;
+        if !self.flags.contains(DescriptorFlags::WRITE) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        Ok(file)
+    }
+
+    fn mutable_dir(&self) -> FsResult<&Dir> {
+        let dir = self.dir()?;
+        if !self.flags.contains(DescriptorFlags::MUTATE_DIRECTORY) {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        Ok(dir)
+    }
+
+    fn open_at(&self, path: &str, oflags: OpenFlags, flags: DescriptorFlags) -> FsResult<Self> {
+        let base = self.dir()?;
+        if flags.contains(DescriptorFlags::MUTATE_DIRECTORY)
+            && !self.flags.contains(DescriptorFlags::MUTATE_DIRECTORY)
+        {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let node = if oflags.contains(OpenFlags::CREATE) {
+            let (dir, name) = lookup_parent(base, path)?;
+            let mut dir = lock(&dir);
+            match dir.get(name) {
+                Some(..) if oflags.contains(OpenFlags::EXCLUSIVE) => {
+                    return Err(ErrorCode::Exist.into())
+                }
+                Some(node) => node.clone(),
+                None => {
+                    if !self.flags.contains(DescriptorFlags::MUTATE_DIRECTORY) {
+                        return Err(ErrorCode::NotPermitted.into());
+                    }
+                    let node = Node::File(File::default());
+                    dir.insert(name.into(), node.clone());
+                    node
+                }
+            }
+        } else {
+            lookup(base, path)?
+        };
+        match &node {
+            Node::Dir(..) if flags.contains(DescriptorFlags::WRITE) => {
+                return Err(ErrorCode::IsDirectory.into())
+            }
+            Node::File(..) if oflags.contains(OpenFlags::DIRECTORY) => {
+                return Err(ErrorCode::NotDirectory.into())
+            }
+            Node::File(file) if oflags.contains(OpenFlags::TRUNCATE) => {
+                if !flags.contains(DescriptorFlags::WRITE) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                lock(file).clear();
+            }
+            _ => {}
+        }
+        Ok(Self { node, flags })
+    }
+
+    fn read(&self, len: Filesize, offset: Filesize) -> FsResult<(Vec<u8>, bool)> {
+        if !self.flags.contains(DescriptorFlags::READ) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let file = lock(self.file()?);
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        let Some(buf) = file.get(offset..) else {
+            return Ok((Vec::default(), true));
+        };
+        let len = usize::try_from(len).unwrap_or(usize::MAX).min(buf.len());
+        Ok((buf[..len].to_vec(), len == buf.len()))
+    }
+
+    fn write(&self, buf: &[u8], offset: Filesize) -> FsResult<Filesize> {
+        let mut file = lock(self.writable_file()?);
+        let offset = usize::try_from(offset).map_err(|_| ErrorCode::FileTooLarge)?;
+        write_at(&mut file, buf, offset)?;
+        Ok(buf.len().try_into().unwrap_or(u64::MAX))
+    }
+
+    fn set_size(&self, size: Filesize) -> FsResult<()> {
+        let mut file = lock(self.writable_file()?);
+        let size = usize::try_from(size).map_err(|_| ErrorCode::FileTooLarge)?;
+        file.resize(size, 0);
+        Ok(())
+    }
+
+    fn read_directory(&self) -> FsResult<DirectoryEntryStream> {
+        let entries = lock(self.dir()?)
+            .iter()
+            .map(|(name, node)| DirectoryEntry {
+                type_: node.ty(),
+                name: name.clone(),
+            })
+            .collect::<Vec<_>>();
+        Ok(DirectoryEntryStream(entries.into_iter()))
+    }
+
+    fn create_directory_at(&self, path: &str) -> FsResult<()> {
+        let (dir, name) = lookup_parent(self.mutable_dir()?, path)?;
+        let mut dir = lock(&dir);
+        if dir.contains_key(name) {
+            return Err(ErrorCode::Exist.into());
+        }
+        dir.insert(name.into(), Node::Dir(Dir::default()));
+        Ok(())
+    }
+
+    fn link_at(&self, old_path: &str, new: &Self, new_path: &str) -> FsResult<()> {
+        let node = lookup(self.dir()?, old_path)?;
+        if let Node::Dir(..) = node {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let (dir, name) = lookup_parent(new.mutable_dir()?, new_path)?;
+        let mut dir = lock(&dir);
+        if dir.contains_key(name) {
+            return Err(ErrorCode::Exist.into());
+        }
+        dir.insert(name.into(), node);
+        Ok(())
+    }
+
+    fn remove_directory_at(&self, path: &str) -> FsResult<()> {
+        let (dir, name) = lookup_parent(self.mutable_dir()?, path)?;
+        let mut dir = lock(&dir);
+        match dir.get(name) {
+            Some(Node::Dir(child)) if !lock(child).is_empty() => Err(ErrorCode::NotEmpty.into()),
+            Some(Node::Dir(..)) => {
+                dir.remove(name);
+                Ok(())
+            }
+            Some(Node::File(..)) => Err(ErrorCode::NotDirectory.into()),
+            None => Err(ErrorCode::NoEntry.into()),
+        }
+    }
+
+    fn unlink_file_at(&self, path: &str) -> FsResult<()> {
+        let (dir, name) = lookup_parent(self.mutable_dir()?, path)?;
+        let mut dir = lock(&dir);
+        match dir.get(name) {
+            Some(Node::File(..)) => {
+                dir.remove(name);
+                Ok(())
+            }
+            Some(Node::Dir(..)) => Err(ErrorCode::IsDirectory.into()),
+            None => Err(ErrorCode::NoEntry.into()),
+        }
+    }
+
+    fn rename_at(&self, old_path: &str, new: &Self, new_path: &str) -> FsResult<()> {
+        let (old_dir, old_name) = lookup_parent(self.mutable_dir()?, old_path)?;
+        let (new_dir, new_name) = lookup_parent(new.mutable_dir()?, new_path)?;
+        let node = lock(&old_dir)
+            .get(old_name)
+            .cloned()
+            .ok_or(ErrorCode::NoEntry)?;
+        if let Node::Dir(dir) = &node {
+            if contains_dir(dir, &new_dir) {
+                return Err(ErrorCode::Invalid.into());
+            }
+        }
+        {
+            let new_dir = lock(&new_dir);
+            match (&node, new_dir.get(new_name)) {
+                (Node::File(..), Some(Node::Dir(..))) => return Err(ErrorCode::IsDirectory.into()),
+                (Node::Dir(..), Some(Node::File(..))) => return Err(ErrorCode::NotDirectory.into()),
+                (Node::Dir(..), Some(Node::Dir(dir))) if !lock(dir).is_empty() => {
+                    return Err(ErrorCode::NotEmpty.into())
+                }
+                _ => {}
+            }
+        }
+        lock(&old_dir).remove(old_name);
+        lock(&new_dir).insert(new_name.into(), node);
+        Ok(())
+    }
+
+    fn read_via_stream(&self, offset: Filesize) -> FsResult<FileInputStream> {
+        if !self.flags.contains(DescriptorFlags::READ) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let file = Arc::clone(self.file()?);
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        Ok(FileInputStream { file, offset })
+    }
+
+    fn write_via_stream(&self, offset: Option<Filesize>) -> FsResult<FileOutputStream> {
+        let file = Arc::clone(self.writable_file()?);
+        let offset = offset
+            .map(usize::try_from)
+            .transpose()
+            .map_err(|_| ErrorCode::FileTooLarge)?;
+        Ok(FileOutputStream { file, offset })
+    }
+}
+
+fn write_at(file: &mut Vec<u8>, buf: &[u8], offset: usize) -> FsResult<()> {
+    let end = offset
+        .checked_add(buf.len())
+        .ok_or(ErrorCode::FileTooLarge)?;
+    if file.len() < end {
+        file.resize(end, 0);
+    }
+    file[offset..end].copy_from_slice(buf);
+    Ok(())
+}
+
+/// `wasi:filesystem/types.directory-entry-stream` listing a [`MemoryFs`] directory
+pub(crate) struct DirectoryEntryStream(std::vec::IntoIter<DirectoryEntry>);
+
+struct FileInputStream {
+    file: File,
+    offset: usize,
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for FileInputStream {
+    async fn ready(&mut self) {}
+}
+
+impl InputStream for FileInputStream {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        let file = lock(&self.file);
+        let buf = file.get(self.offset..).unwrap_or_default();
+        if buf.is_empty() {
+            return Err(StreamError::Closed);
+        }
+        let n = size.min(buf.len());
+        let buf = Bytes::copy_from_slice(&buf[..n]);
+        self.offset = self.offset.saturating_add(n);
+        Ok(buf)
+    }
+}
+
+struct FileOutputStream {
+    file: File,
+    /// Write offset, `None` means append
+    offset: Option<usize>,
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for FileOutputStream {
+    async fn ready(&mut self) {}
+}
+
+impl OutputStream for FileOutputStream {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        let mut file = lock(&self.file);
+        let offset = self.offset.unwrap_or(file.len());
+        write_at(&mut file, &bytes, offset)
+            .map_err(|_| StreamError::trap("in-memory file too large"))?;
+        if let Some(offset) = &mut self.offset {
+            *offset = offset.saturating_add(bytes.len());
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(WRITE_BUDGET)
+    }
+}
+
+impl Ctx {
+    fn memory_descriptor(&mut self, fd: &Resource<types::Descriptor>) -> Option<Descriptor> {
+        self.table
+            .get_any_mut(fd.rep())
+            .ok()?
+            .downcast_ref::<Descriptor>()
+            .cloned()
+    }
+
+    fn push_memory_descriptor(
+        &mut self,
+        fd: Descriptor,
+    ) -> anyhow::Result<Resource<types::Descriptor>> {
+        let fd = self
+            .table
+            .push(fd)
+            .context("failed to push descriptor into resource table")?;
+        Ok(Resource::new_own(fd.rep()))
+    }
+}
+
+impl preopens::Host for Ctx {
+    fn get_directories(&mut self) -> wasmtime::Result<Vec<(Resource<types::Descriptor>, String)>> {
+        let mut dirs = preopens::Host::get_directories(&mut self.filesystem())?;
+        let fs = self
+            .memory_preopens
+            .iter()
+            .map(|(path, fs)| (path.clone(), fs.root_descriptor()))
+            .collect::<Vec<_>>();
+        for (path, fd) in fs {
+            let fd = self.push_memory_descriptor(fd)?;
+            dirs.push((fd, path));
+        }
+        Ok(dirs)
+    }
+}
+
+impl types::Host for Ctx {
+    fn convert_error_code(
+        &mut self,
+        err: wasmtime_wasi::p2::FsError,
+    ) -> wasmtime::Result<types::ErrorCode> {
+        types::Host::convert_error_code(&mut self.filesystem(), err)
+    }
+
+    fn filesystem_error_code(
+        &mut self,
+        err: Resource<streams::Error>,
+    ) -> wasmtime::Result<Option<types::ErrorCode>> {
+        types::Host::filesystem_error_code(&mut self.filesystem(), err)
+    }
+}
+
+impl HostDescriptor for Ctx {
+    fn read_via_stream(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        offset: Filesize,
+    ) -> FsResult<Resource<streams::InputStream>> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::read_via_stream(&mut self.filesystem(), fd, offset);
+        };
+        let stream: DynInputStream = Box::new(fd.read_via_stream(offset)?);
+        Ok(self.table.push(stream)?)
+    }
+
+    fn write_via_stream(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        offset: Filesize,
+    ) -> FsResult<Resource<streams::OutputStream>> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::write_via_stream(&mut self.filesystem(), fd, offset);
+        };
+        let stream: DynOutputStream = Box::new(fd.write_via_stream(Some(offset))?);
+        Ok(self.table.push(stream)?)
+    }
+
+    fn append_via_stream(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+    ) -> FsResult<Resource<streams::OutputStream>> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::append_via_stream(&mut self.filesystem(), fd);
+        };
+        let stream: DynOutputStream = Box::new(fd.write_via_stream(None)?);
+        Ok(self.table.push(stream)?)
+    }
+
+    fn advise(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        offset: Filesize,
+        len: Filesize,
+        advice: Advice,
+    ) -> FsResult<()> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::advise(&mut self.filesystem(), fd, offset, len, advice);
+        };
+        fd.file()?;
+        Ok(())
+    }
+
+    fn sync_data(&mut self, fd: Resource<types::Descriptor>) -> FsResult<()> {
+        if self.memory_descriptor(&fd).is_some() {
+            return Ok(());
+        }
+        HostDescriptor::sync_data(&mut self.filesystem(), fd)
+    }
+
+    fn get_flags(&mut self, fd: Resource<types::Descriptor>) -> FsResult<DescriptorFlags> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::get_flags(&mut self.filesystem(), fd);
+        };
+        Ok(fd.flags)
+    }
+
+    fn get_type(&mut self, fd: Resource<types::Descriptor>) -> FsResult<DescriptorType> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::get_type(&mut self.filesystem(), fd);
+        };
+        Ok(fd.node.ty())
+    }
+
+    fn set_size(&mut self, fd: Resource<types::Descriptor>, size: Filesize) -> FsResult<()> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::set_size(&mut self.filesystem(), fd, size);
+        };
+        fd.set_size(size)
+    }
+
+    fn set_times(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        atim: NewTimestamp,
+        mtim: NewTimestamp,
+    ) -> FsResult<()> {
+        if self.memory_descriptor(&fd).is_some() {
+            // timestamps are not tracked by `MemoryFs`
+            return Ok(());
+        }
+        HostDescriptor::set_times(&mut self.filesystem(), fd, atim, mtim)
+    }
+
+    fn read(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        len: Filesize,
+        offset: Filesize,
+    ) -> FsResult<(Vec<u8>, bool)> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::read(&mut self.filesystem(), fd, len, offset);
+        };
+        fd.read(len, offset)
+    }
+
+    fn write(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        buf: Vec<u8>,
+        offset: Filesize,
+    ) -> FsResult<Filesize> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::write(&mut self.filesystem(), fd, buf, offset);
+        };
+        fd.write(&buf, offset)
+    }
+
+    fn read_directory(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+    ) -> FsResult<Resource<types::DirectoryEntryStream>> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::read_directory(&mut self.filesystem(), fd);
+        };
+        let entries = self.table.push(fd.read_directory()?)?;
+        Ok(Resource::new_own(entries.rep()))
+    }
+
+    fn sync(&mut self, fd: Resource<types::Descriptor>) -> FsResult<()> {
+        if self.memory_descriptor(&fd).is_some() {
+            return Ok(());
+        }
+        HostDescriptor::sync(&mut self.filesystem(), fd)
+    }
+
+    fn create_directory_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path: String,
+    ) -> FsResult<()> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::create_directory_at(&mut self.filesystem(), fd, path);
+        };
+        fd.create_directory_at(&path)
+    }
+
+    fn stat(&mut self, fd: Resource<types::Descriptor>) -> FsResult<DescriptorStat> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::stat(&mut self.filesystem(), fd);
+        };
+        Ok(stat(&fd.node))
+    }
+
+    fn stat_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+    ) -> FsResult<DescriptorStat> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::stat_at(&mut self.filesystem(), fd, path_flags, path);
+        };
+        let node = lookup(fd.dir()?, &path)?;
+        Ok(stat(&node))
+    }
+
+    fn set_times_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+        atim: NewTimestamp,
+        mtim: NewTimestamp,
+    ) -> FsResult<()> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::set_times_at(
+                &mut self.filesystem(),
+                fd,
+                path_flags,
+                path,
+                atim,
+                mtim,
+            );
+        };
+        // timestamps are not tracked by `MemoryFs`
+        lookup(fd.dir()?, &path)?;
+        Ok(())
+    }
+
+    fn link_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        old_path_flags: PathFlags,
+        old_path: String,
+        new_fd: Resource<types::Descriptor>,
+        new_path: String,
+    ) -> FsResult<()> {
+        match (self.memory_descriptor(&fd), self.memory_descriptor(&new_fd)) {
+            (Some(fd), Some(new_fd)) => fd.link_at(&old_path, &new_fd, &new_path),
+            (None, None) => HostDescriptor::link_at(
+                &mut self.filesystem(),
+                fd,
+                old_path_flags,
+                old_path,
+                new_fd,
+                new_path,
+            ),
+            _ => Err(ErrorCode::CrossDevice.into()),
+        }
+    }
+
+    fn open_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+        oflags: OpenFlags,
+        flags: DescriptorFlags,
+    ) -> FsResult<Resource<types::Descriptor>> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::open_at(
+                &mut self.filesystem(),
+                fd,
+                path_flags,
+                path,
+                oflags,
+                flags,
+            );
+        };
+        let fd = fd.open_at(&path, oflags, flags)?;
+        self.push_memory_descriptor(fd).map_err(FsError::trap)
+    }
+
+    fn readlink_at(&mut self, fd: Resource<types::Descriptor>, path: String) -> FsResult<String> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::readlink_at(&mut self.filesystem(), fd, path);
+        };
+        // symbolic links are not supported by `MemoryFs`
+        lookup(fd.dir()?, &path)?;
+        Err(ErrorCode::Invalid.into())
+    }
+
+    fn remove_directory_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path: String,
+    ) -> FsResult<()> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::remove_directory_at(&mut self.filesystem(), fd, path);
+        };
+        fd.remove_directory_at(&path)
+    }
+
+    fn rename_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        old_path: String,
+        new_fd: Resource<types::Descriptor>,
+        new_path: String,
+    ) -> FsResult<()> {
+        match (self.memory_descriptor(&fd), self.memory_descriptor(&new_fd)) {
+            (Some(fd), Some(new_fd)) => fd.rename_at(&old_path, &new_fd, &new_path),
+            (None, None) => {
+                HostDescriptor::rename_at(&mut self.filesystem(), fd, old_path, new_fd, new_path)
+            }
+            _ => Err(ErrorCode::CrossDevice.into()),
+        }
+    }
+
+    fn symlink_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        old_path: String,
+        new_path: String,
+    ) -> FsResult<()> {
+        if self.memory_descriptor(&fd).is_some() {
+            // symbolic links are not supported by `MemoryFs`
+            return Err(ErrorCode::Unsupported.into());
+        }
+        HostDescriptor::symlink_at(&mut self.filesystem(), fd, old_path, new_path)
+    }
+
+    fn unlink_file_at(&mut self, fd: Resource<types::Descriptor>, path: String) -> FsResult<()> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::unlink_file_at(&mut self.filesystem(), fd, path);
+        };
+        fd.unlink_file_at(&path)
+    }
+
+    fn is_same_object(
+        &mut self,
+        a: Resource<types::Descriptor>,
+        b: Resource<types::Descriptor>,
+    ) -> wasmtime::Result<bool> {
+        match (self.memory_descriptor(&a), self.memory_descriptor(&b)) {
+            (Some(a), Some(b)) => Ok(a.node.id() == b.node.id()),
+            (None, None) => HostDescriptor::is_same_object(&mut self.filesystem(), a, b),
+            _ => Ok(false),
+        }
+    }
+
+    fn metadata_hash(&mut self, fd: Resource<types::Descriptor>) -> FsResult<MetadataHashValue> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::metadata_hash(&mut self.filesystem(), fd);
+        };
+        Ok(metadata_hash(&fd.node))
+    }
+
+    fn metadata_hash_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+    ) -> FsResult<MetadataHashValue> {
+        let Some(fd) = self.memory_descriptor(&fd) else {
+            return HostDescriptor::metadata_hash_at(&mut self.filesystem(), fd, path_flags, path);
+        };
+        let node = lookup(fd.dir()?, &path)?;
+        Ok(metadata_hash(&node))
+    }
+
+    fn drop(&mut self, fd: Resource<types::Descriptor>) -> wasmtime::Result<()> {
+        if self.memory_descriptor(&fd).is_none() {
+            return HostDescriptor::drop(&mut self.filesystem(), fd);
+        }
+        self.table
+            .delete(Resource::<Descriptor>::new_own(fd.rep()))
+            .context("failed to delete descriptor from resource table")?;
+        Ok(())
+    }
+}
+
+impl HostDirectoryEntryStream for Ctx {
+    fn read_directory_entry(
+        &mut self,
+        stream: Resource<types::DirectoryEntryStream>,
+    ) -> FsResult<Option<DirectoryEntry>> {
+        match self
+            .table
+            .get_any_mut(stream.rep())
+            .map(|stream| stream.downcast_mut::<DirectoryEntryStream>())
+        {
+            Ok(Some(DirectoryEntryStream(entries))) => Ok(entries.next()),
+            _ => HostDirectoryEntryStream::read_directory_entry(&mut self.filesystem(), stream),
+        }
+    }
+
+    fn drop(&mut self, stream: Resource<types::DirectoryEntryStream>) -> wasmtime::Result<()> {
+        let is_memory = self
+            .table
+            .get_any_mut(stream.rep())
+            .is_ok_and(|stream| stream.is::<DirectoryEntryStream>());
+        if !is_memory {
+            return HostDirectoryEntryStream::drop(&mut self.filesystem(), stream);
+        }
+        self.table
+            .delete(Resource::<DirectoryEntryStream>::new_own(stream.rep()))
+            .context("failed to delete directory entry stream from resource table")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar(entries: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::default());
+        for (path, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            match contents {
+                Some(contents) => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(contents.len().try_into().unwrap());
+                    header.set_mode(0o644);
+                    header.set_cksum();
+                    tar.append_data(&mut header, path, *contents).unwrap();
+                }
+                None => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_size(0);
+                    header.set_mode(0o755);
+                    header.set_cksum();
+                    tar.append_data(&mut header, path, &[][..]).unwrap();
+                }
+            }
+        }
+        tar.into_inner().unwrap()
+    }
+
+    fn error_code(err: FsError) -> ErrorCode {
+        err.downcast().expect("error is not an error code")
+    }
+
+    #[test]
+    fn from_files() {
+        let fs = MemoryFs::from_files([("a/b/c.txt", "foo"), ("d.txt", "bar")]).unwrap();
+        assert_eq!(fs.read("a/b/c.txt").unwrap(), b"foo");
+        assert_eq!(fs.read("./a/../d.txt").unwrap(), b"bar");
+        assert!(fs.read("a/b").is_err());
+        assert!(fs.read("missing").is_err());
+        assert_eq!(
+            fs.files(),
+            BTreeMap::from([
+                ("a/b/c.txt".into(), b"foo".to_vec()),
+                ("d.txt".into(), b"bar".to_vec()),
+            ])
+        );
+    }
+
+    #[test]
+    fn from_tar() {
+        let tar = tar(&[
+            ("empty/", None),
+            ("dir/", None),
+            ("dir/file.txt", Some(b"foo")),
+            ("nested/file.txt", Some(b"bar")),
+        ]);
+        let fs = MemoryFs::from_tar(tar.as_slice()).unwrap();
+        assert_eq!(fs.read("dir/file.txt").unwrap(), b"foo");
+        assert_eq!(fs.read("nested/file.txt").unwrap(), b"bar");
+        assert!(matches!(
+            lookup(&fs.root, "empty"),
+            Ok(Node::Dir(dir)) if lock(&dir).is_empty()
+        ));
+    }
+
+    #[test]
+    fn from_tar_unsupported_entry() {
+        let mut tar = tar::Builder::new(Vec::default());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append_link(&mut header, "link", "target").unwrap();
+        let tar = tar.into_inner().unwrap();
+        assert!(MemoryFs::from_tar(tar.as_slice()).is_err());
+    }
+
+    #[test]
+    fn path_traversal() {
+        let fs = MemoryFs::from_files([("dir/file.txt", "foo")]).unwrap();
+        assert!(fs.write("../escape.txt", "bar").is_err());
+        assert!(fs.write("dir/../../escape.txt", "bar").is_err());
+        assert!(fs.write("/abs.txt", "bar").is_err());
+        assert!(fs.read("../dir/file.txt").is_err());
+        assert_eq!(fs.read("dir/../dir/file.txt").unwrap(), b"foo");
+
+        let root = fs.root_descriptor();
+        for path in ["..", "../dir/file.txt", "dir/../..", "/dir/file.txt"] {
+            let err = root
+                .open_at(path, OpenFlags::empty(), DescriptorFlags::READ)
+                .err()
+                .unwrap_or_else(|| panic!("`{path}` must not be opened"));
+            assert_eq!(error_code(err), ErrorCode::NotPermitted, "{path}");
+        }
+        let dir = root
+            .open_at("dir", OpenFlags::DIRECTORY, DescriptorFlags::READ)
+            .unwrap();
+        let err = dir
+            .open_at("../dir/file.txt", OpenFlags::empty(), DescriptorFlags::READ)
+            .err()
+            .unwrap();
+        assert_eq!(error_code(err), ErrorCode::NotPermitted);
+    }
+
+    #[test]
+    fn read_write() {
+        let fs = MemoryFs::new();
+        let root = fs.root_descriptor();
+        let file = root
+            .open_at(
+                "file.txt",
+                OpenFlags::CREATE,
+                DescriptorFlags::READ | DescriptorFlags::WRITE,
+            )
+            .unwrap();
+        assert_eq!(file.write(b"foo", 0).unwrap(), 3);
+        assert_eq!(file.write(b"bar", 5).unwrap(), 3);
+        assert_eq!(fs.read("file.txt").unwrap(), b"foo\0\0bar");
+        assert_eq!(file.read(3, 0).unwrap(), (b"foo".to_vec(), false));
+        assert_eq!(file.read(16, 5).unwrap(), (b"bar".to_vec(), true));
+        assert_eq!(file.read(16, 16).unwrap(), (Vec::default(), true));
+
+        file.set_size(2).unwrap();
+        assert_eq!(fs.read("file.txt").unwrap(), b"fo");
+
+        fs.write("file.txt", "native").unwrap();
+        assert_eq!(file.read(16, 0).unwrap(), (b"native".to_vec(), true));
+
+        let err = root
+            .open_at(
+                "file.txt",
+                OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+                DescriptorFlags::READ,
+            )
+            .err()
+            .unwrap();
+        assert_eq!(error_code(err), ErrorCode::Exist);
+
+        let file = root
+            .open_at(
+                "file.txt",
+                OpenFlags::TRUNCATE,
+                DescriptorFlags::READ | DescriptorFlags::WRITE,
+            )
+            .unwrap();
+        assert_eq!(file.read(16, 0).unwrap(), (Vec::default(), true));
+    }
+
+    #[test]
+    fn permissions() {
+        let fs = MemoryFs::from_files([("dir/file.txt", "foo")]).unwrap();
+        let root = fs.root_descriptor();
+
+        let file = root
+            .open_at("dir/file.txt", OpenFlags::empty(), DescriptorFlags::READ)
+            .unwrap();
+        assert_eq!(
+            error_code(file.write(b"bar", 0).unwrap_err()),
+            ErrorCode::BadDescriptor
+        );
+        assert_eq!(
+            error_code(file.set_size(0).unwrap_err()),
+            ErrorCode::BadDescriptor
+        );
+        assert!(file.write_via_stream(None).is_err());
+
+        let file = root
+            .open_at("dir/file.txt", OpenFlags::empty(), DescriptorFlags::WRITE)
+            .unwrap();
+        assert_eq!(
+            error_code(file.read(3, 0).unwrap_err()),
+            ErrorCode::BadDescriptor
+        );
+        assert!(file.read_via_stream(0).is_err());
+
+        let err = root
+            .open_at("dir/file.txt", OpenFlags::TRUNCATE, DescriptorFlags::READ)
+            .err()
+            .unwrap();
+        assert_eq!(error_code(err), ErrorCode::NotPermitted);
+        assert_eq!(fs.read("dir/file.txt").unwrap(), b"foo");
+
+        // directories opened without `MUTATE_DIRECTORY` cannot be modified
+        let dir = root
+            .open_at("dir", OpenFlags::DIRECTORY, DescriptorFlags::READ)
+            .unwrap();
+        let err = dir
+            .open_at("new.txt", OpenFlags::CREATE, DescriptorFlags::WRITE)
+            .err()
+            .unwrap();
+        assert_eq!(error_code(err), ErrorCode::NotPermitted);
+        assert_eq!(
+            error_code(dir.create_directory_at("new").unwrap_err()),
+            ErrorCode::NotPermitted
+        );
+        assert_eq!(
+            error_code(dir.unlink_file_at("file.txt").unwrap_err()),
+            ErrorCode::NotPermitted
+        );
+        let err = dir
+            .open_at(
+                ".",
+                OpenFlags::DIRECTORY,
+                DescriptorFlags::READ | DescriptorFlags::MUTATE_DIRECTORY,
+            )
+            .err()
+            .unwrap();
+        assert_eq!(error_code(err), ErrorCode::NotPermitted);
+
+        let err = root
+            .open_at("dir", OpenFlags::empty(), DescriptorFlags::WRITE)
+            .err()
+            .unwrap();
+        assert_eq!(error_code(err), ErrorCode::IsDirectory);
+        assert_eq!(fs.files().len(), 1);
+    }
+
+    #[test]
+    fn directories() {
+        let fs = MemoryFs::from_files([("a/file.txt", "foo")]).unwrap();
+        let root = fs.root_descriptor();
+
+        root.create_directory_at("b").unwrap();
+        assert_eq!(
+            error_code(root.create_directory_at("b").unwrap_err()),
+            ErrorCode::Exist
+        );
+        assert_eq!(
+            error_code(root.remove_directory_at("a").unwrap_err()),
+            ErrorCode::NotEmpty
+        );
+
+        root.rename_at("a/file.txt", &root, "b/renamed.txt")
+            .unwrap();
+        assert!(fs.read("a/file.txt").is_err());
+        assert_eq!(fs.read("b/renamed.txt").unwrap(), b"foo");
+
+        // directories cannot be moved into themselves
+        assert_eq!(
+            error_code(root.rename_at("b", &root, "b/nested").unwrap_err()),
+            ErrorCode::Invalid
+        );
+
+        root.link_at("b/renamed.txt", &root, "linked.txt").unwrap();
+        fs.write("b/renamed.txt", "bar").unwrap();
+        assert_eq!(fs.read("linked.txt").unwrap(), b"bar");
+
+        root.unlink_file_at("linked.txt").unwrap();
+        root.remove_directory_at("a").unwrap();
+        let DirectoryEntryStream(entries) = root.read_directory().unwrap();
+        assert_eq!(entries.map(|entry| entry.name).collect::<Vec<_>>(), ["b"]);
+    }
+
+    #[test]
+    fn streams() {
+        let fs = MemoryFs::new();
+        let root = fs.root_descriptor();
+        let file = root
+            .open_at(
+                "file.txt",
+                OpenFlags::CREATE,
+                DescriptorFlags::READ | DescriptorFlags::WRITE,
+            )
+            .unwrap();
+
+        let mut tx = file.write_via_stream(None).unwrap();
+        tx.write(Bytes::from_static(b"foo")).unwrap();
+        tx.write(Bytes::from_static(b"bar")).unwrap();
+        let mut tx = file.write_via_stream(Some(1)).unwrap();
+        tx.write(Bytes::from_static(b"OO")).unwrap();
+        assert_eq!(fs.read("file.txt").unwrap(), b"fOObar");
+
+        let mut rx = file.read_via_stream(2).unwrap();
+        assert_eq!(rx.read(2).unwrap(), &b"Ob"[..]);
+        assert_eq!(rx.read(16).unwrap(), &b"ar"[..]);
+        assert!(matches!(rx.read(16), Err(StreamError::Closed)));
+    }
+}
@@ -0,0 +1,646 @@
+//! HTTP Archive (HAR) logging of `wasi:http` exchanges
+
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use core::time::Duration;
+
+use std::fs::File;
+use std::io::{Seek as _, SeekFrom, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Instant, SystemTime};
+
+use anyhow::Context as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use bytes::Bytes;
+use http_body_util::BodyExt as _;
+use hyper::body::{Body, Frame, SizeHint};
+use serde::Serialize;
+use tracing::warn;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+/// Direction of a logged `wasi:http` exchange
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Direction {
+    /// Request handled by the `wasi:http/incoming-handler` export of the guest
+    Incoming,
+    /// Request sent by the guest via `wasi:http/outgoing-handler`
+    Outgoing,
+}
+
+/// HTTP Archive (HAR) log of `wasi:http` exchanges.
+///
+/// Each completed exchange is appended to the file in place, so that it contains
+/// a valid log at all times. Bodies are truncated to the configured limit.
+/// Clones refer to the same log, so it may be shared by multiple instances.
+#[derive(Clone)]
+pub struct Har(Arc<HarLog>);
+
+struct HarLog {
+    path: PathBuf,
+    body_limit: usize,
+    file: Mutex<HarFile>,
+}
+
+/// HAR file terminated by [`TRAILER`]
+struct HarFile {
+    file: File,
+    entries: usize,
+}
+
+/// Suffix closing the `entries` array and the log, entries are inserted before it
+const TRAILER: &[u8] = b"\n]}}\n";
+
+#[derive(Serialize)]
+struct Creator {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+struct Pair {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    mime_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    method: String,
+    url: String,
+    http_version: String,
+    cookies: [(); 0],
+    headers: Vec<Pair>,
+    query_string: Vec<Pair>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    size: i64,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: [(); 0],
+    headers: Vec<Pair>,
+    content: Content,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+    #[serde(rename = "_error", skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Timings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: String,
+    time: f64,
+    request: Request,
+    response: Response,
+    cache: Cache,
+    timings: Timings,
+    comment: &'static str,
+}
+
+#[derive(Serialize)]
+struct Cache {}
+
+/// Body prefix captured for the log
+#[derive(Default)]
+struct Captured {
+    buf: Vec<u8>,
+    size: u64,
+}
+
+impl Captured {
+    fn push(&mut self, data: &[u8], limit: usize) {
+        let n = limit.saturating_sub(self.buf.len()).min(data.len());
+        self.buf.extend_from_slice(&data[..n]);
+        self.size = self.size.saturating_add(data.len() as u64);
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.size > self.buf.len() as u64
+    }
+
+    /// Returns the captured text and its encoding, binary data is base64-encoded
+    fn text(&self) -> (String, Option<&'static str>) {
+        match std::str::from_utf8(&self.buf) {
+            Ok(text) => (text.into(), None),
+            Err(_) => (BASE64.encode(&self.buf), Some("base64")),
+        }
+    }
+
+    fn comment(&self) -> Option<String> {
+        self.is_truncated()
+            .then(|| format!("truncated to {} of {} bytes", self.buf.len(), self.size))
+    }
+}
+
+/// State of an exchange in flight
+struct Pending {
+    direction: Direction,
+    started: SystemTime,
+    start: Instant,
+    method: String,
+    url: String,
+    http_version: String,
+    request_headers: Vec<Pair>,
+    query_string: Vec<Pair>,
+    request_mime_type: String,
+    request_body: Captured,
+    request_end: Option<Instant>,
+    response: Option<(http::StatusCode, http::Version, http::HeaderMap, Instant)>,
+    response_body: Captured,
+    error: Option<String>,
+    done: bool,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.
+}
+
+fn pairs(headers: &http::HeaderMap) -> Vec<Pair> {
+    headers
+        .iter()
+        .map(|(name, value)| Pair {
+            name: name.to_string(),
+            value: String::from_utf8_lossy(value.as_bytes()).into(),
+        })
+        .collect()
+}
+
+fn mime_type(headers: &http::HeaderMap) -> String {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into())
+        .unwrap_or_default()
+}
+
+fn query_string(uri: &http::Uri) -> Vec<Pair> {
+    uri.query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Pair {
+                name: name.into(),
+                value: value.into(),
+            }
+        })
+        .collect()
+}
+
+/// Formats `t` as an RFC 3339 UTC timestamp with millisecond precision
+fn rfc3339(t: SystemTime) -> String {
+    let d = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        d.subsec_millis(),
+    )
+}
+
+impl Pending {
+    fn entry(&mut self) -> Entry {
+        let now = Instant::now();
+        let request_end = self.request_end.unwrap_or(now);
+        let response_start = self.response.as_ref().map_or(now, |(.., start)| *start);
+        let (text, _) = self.request_body.text();
+        let post_data = (self.request_body.size > 0).then(|| PostData {
+            mime_type: self.request_mime_type.clone(),
+            text,
+            comment: self.request_body.comment(),
+        });
+        let (text, encoding) = self.response_body.text();
+        let response = match self.response.take() {
+            Some((status, version, headers, _)) => Response {
+                status: status.as_u16(),
+                status_text: status.canonical_reason().unwrap_or_default().into(),
+                http_version: format!("{version:?}"),
+                cookies: [],
+                headers: pairs(&headers),
+                content: Content {
+                    size: self.response_body.size as i64,
+                    mime_type: mime_type(&headers),
+                    text: Some(text),
+                    encoding,
+                    comment: self.response_body.comment(),
+                },
+                redirect_url: headers
+                    .get(http::header::LOCATION)
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()).into())
+                    .unwrap_or_default(),
+                headers_size: -1,
+                body_size: self.response_body.size as i64,
+                error: self.error.take(),
+            },
+            None => Response {
+                status: 0,
+                status_text: String::default(),
+                http_version: String::default(),
+                cookies: [],
+                headers: Vec::default(),
+                content: Content {
+                    size: 0,
+                    mime_type: String::default(),
+                    text: None,
+                    encoding: None,
+                    comment: None,
+                },
+                redirect_url: String::default(),
+                headers_size: -1,
+                body_size: -1,
+                error: self.error.take(),
+            },
+        };
+        let send = request_end.saturating_duration_since(self.start);
+        let wait = response_start.saturating_duration_since(request_end);
+        let receive = now.saturating_duration_since(response_start.max(request_end));
+        Entry {
+            started_date_time: rfc3339(self.started),
+            time: millis(now.saturating_duration_since(self.start)),
+            request: Request {
+                method: self.method.clone(),
+                url: self.url.clone(),
+                http_version: self.http_version.clone(),
+                cookies: [],
+                headers: std::mem::take(&mut self.request_headers),
+                query_string: std::mem::take(&mut self.query_string),
+                post_data,
+                headers_size: -1,
+                body_size: self.request_body.size as i64,
+            },
+            response,
+            cache: Cache {},
+            timings: Timings {
+                send: millis(send),
+                wait: millis(wait),
+                receive: millis(receive),
+            },
+            comment: match self.direction {
+                Direction::Incoming => "incoming",
+                Direction::Outgoing => "outgoing",
+            },
+        }
+    }
+}
+
+/// `wasi:http` exchange being logged
+pub(crate) struct Exchange {
+    har: Har,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl Har {
+    /// Default maximum number of body bytes logged per message
+    pub const DEFAULT_BODY_LIMIT: usize = 64 << 10;
+
+    /// Creates a new HAR log at `path`, truncating the file if it exists.
+    ///
+    /// At most `body_limit` bytes of each request and response body are logged.
+    pub fn new(path: impl Into<PathBuf>, body_limit: usize) -> anyhow::Result<Self> {
+        let path = path.into();
+        let mut file = File::create(&path)
+            .with_context(|| format!("failed to create HAR log `{}`", path.display()))?;
+        let creator = serde_json::to_string(&Creator {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+        })
+        .context("failed to encode HAR creator")?;
+        write!(
+            file,
+            r#"{{"log":{{"version":"1.2","creator":{creator},"entries":["#
+        )
+        .and_then(|()| file.write_all(TRAILER))
+        .with_context(|| format!("failed to write HAR log `{}`", path.display()))?;
+        Ok(Self(Arc::new(HarLog {
+            path,
+            body_limit,
+            file: Mutex::new(HarFile { file, entries: 0 }),
+        })))
+    }
+
+    /// Syncs the entries logged so far to disk
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.0
+            .lock()
+            .file
+            .sync_data()
+            .with_context(|| format!("failed to sync HAR log `{}`", self.0.path.display()))
+    }
+
+    /// Returns the path of the HAR file
+    pub fn path(&self) -> &Path {
+        &self.0.path
+    }
+
+    /// Starts logging an exchange, returning `request` with the body captured
+    pub(crate) fn begin(
+        &self,
+        direction: Direction,
+        request: hyper::Request<HyperOutgoingBody>,
+    ) -> (hyper::Request<HyperOutgoingBody>, Exchange) {
+        let (parts, body) = request.into_parts();
+        let pending = Arc::new(Mutex::new(Pending {
+            direction,
+            started: SystemTime::now(),
+            start: Instant::now(),
+            method: parts.method.to_string(),
+            url: parts.uri.to_string(),
+            http_version: format!("{:?}", parts.version),
+            request_headers: pairs(&parts.headers),
+            query_string: query_string(&parts.uri),
+            request_mime_type: mime_type(&parts.headers),
+            request_body: Captured::default(),
+            request_end: None,
+            response: None,
+            response_body: Captured::default(),
+            error: None,
+            done: false,
+        }));
+        let exchange = Exchange {
+            har: self.clone(),
+            pending: Arc::clone(&pending),
+        };
+        let body = CaptureBody {
+            inner: body,
+            har: self.clone(),
+            pending,
+            side: Side::Request,
+        }
+        .boxed_unsync();
+        (hyper::Request::from_parts(parts, body), exchange)
+    }
+}
+
+impl HarLog {
+    fn lock(&self) -> MutexGuard<'_, HarFile> {
+        self.file.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Appends `entry` to the file, overwriting and rewriting the [`TRAILER`]
+    fn append(&self, entry: &Entry) -> anyhow::Result<()> {
+        let mut buf = serde_json::to_vec(entry).context("failed to encode HAR entry")?;
+        buf.extend_from_slice(TRAILER);
+        let mut file = self.lock();
+        let sep: &[u8] = if file.entries > 0 { b",\n" } else { b"\n" };
+        file.file
+            .seek(SeekFrom::End(-(TRAILER.len() as i64)))
+            .and_then(|_| file.file.write_all(sep))
+            .and_then(|()| file.file.write_all(&buf))
+            .with_context(|| format!("failed to write HAR log `{}`", self.path.display()))?;
+        file.entries += 1;
+        Ok(())
+    }
+}
+
+impl Har {
+    /// Completes the exchange, appending it to the log, unless already done
+    fn finish(&self, pending: &Mutex<Pending>) {
+        let mut pending = pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if pending.done {
+            return;
+        }
+        pending.done = true;
+        let entry = pending.entry();
+        drop(pending);
+        if let Err(err) = self.0.append(&entry) {
+            warn!(?err, "failed to write HAR log");
+        }
+    }
+}
+
+impl Exchange {
+    /// Records the response head, returning `response` with the body captured.
+    /// The exchange is logged once the response body is consumed or dropped.
+    pub(crate) fn respond(
+        self,
+        response: hyper::Response<HyperOutgoingBody>,
+    ) -> hyper::Response<HyperOutgoingBody> {
+        let (parts, body) = response.into_parts();
+        {
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            pending.response = Some((
+                parts.status,
+                parts.version,
+                parts.headers.clone(),
+                Instant::now(),
+            ));
+        }
+        let body = CaptureBody {
+            inner: body,
+            har: self.har,
+            pending: self.pending,
+            side: Side::Response,
+        }
+        .boxed_unsync();
+        hyper::Response::from_parts(parts, body)
+    }
+
+    /// Logs the exchange as failed with `err`
+    pub(crate) fn fail(self, err: impl ToString) {
+        {
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            pending.error = Some(err.to_string());
+        }
+        self.har.finish(&self.pending);
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Side {
+    Request,
+    Response,
+}
+
+/// Body wrapper capturing a prefix of the data for the log
+struct CaptureBody {
+    inner: HyperOutgoingBody,
+    har: Har,
+    pending: Arc<Mutex<Pending>>,
+    side: Side,
+}
+
+impl CaptureBody {
+    fn end(&self) {
+        match self.side {
+            Side::Request => {
+                let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+                pending.request_end.get_or_insert_with(Instant::now);
+            }
+            Side::Response => self.har.finish(&self.pending),
+        }
+    }
+}
+
+impl Body for CaptureBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    let limit = self.har.0.body_limit;
+                    let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+                    match self.side {
+                        Side::Request => pending.request_body.push(data, limit),
+                        Side::Response => pending.response_body.push(data, limit),
+                    }
+                }
+                if self.inner.is_end_stream() {
+                    self.end();
+                }
+            }
+            Some(Err(err)) => {
+                if self.side == Side::Response {
+                    let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+                    pending.error = Some(err.to_string());
+                }
+                self.end();
+            }
+            None => self.end(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CaptureBody {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use http_body_util::Full;
+
+    fn at(secs: u64, millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn rfc3339_format() {
+        assert_eq!(rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(at(94_694_399, 999)), "1972-12-31T23:59:59.999Z");
+        // leap year divisible by 400
+        assert_eq!(rfc3339(at(951_782_400, 0)), "2000-02-29T00:00:00.000Z");
+        assert_eq!(rfc3339(at(1_709_210_096, 789)), "2024-02-29T12:34:56.789Z");
+        // not a leap year, divisible by 100
+        assert_eq!(rfc3339(at(4_107_542_400, 0)), "2100-03-01T00:00:00.000Z");
+    }
+
+    fn request(uri: &str) -> hyper::Request<HyperOutgoingBody> {
+        hyper::Request::builder()
+            .uri(uri)
+            .body(
+                Full::new(Bytes::from("body"))
+                    .map_err(|err| match err {})
+                    .boxed_unsync(),
+            )
+            .expect("failed to build request")
+    }
+
+    fn entries(har: &Har) -> Vec<serde_json::Value> {
+        let buf = std::fs::read(har.path()).expect("failed to read HAR log");
+        let log: serde_json::Value = serde_json::from_slice(&buf).expect("invalid HAR log");
+        assert_eq!(log["log"]["version"], "1.2");
+        log["log"]["entries"]
+            .as_array()
+            .expect("`entries` is not an array")
+            .clone()
+    }
+
+    #[test]
+    fn append() {
+        let path = env::temp_dir().join(format!("wadge-har-{}.har", process::id()));
+        let har = Har::new(&path, Har::DEFAULT_BODY_LIMIT).expect("failed to create HAR log");
+        assert!(entries(&har).is_empty());
+
+        let (_, exchange) = har.begin(Direction::Outgoing, request("http://example.com/a?x=1"));
+        exchange.fail("first");
+        let (_, exchange) = har.begin(Direction::Incoming, request("http://example.com/b"));
+        exchange.fail("second");
+        har.flush().expect("failed to flush HAR log");
+
+        let entries = entries(&har);
+        std::fs::remove_file(&path).expect("failed to remove HAR log");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["request"]["url"], "http://example.com/a?x=1");
+        assert_eq!(entries[0]["request"]["queryString"][0]["name"], "x");
+        assert_eq!(entries[0]["response"]["_error"], "first");
+        assert_eq!(entries[0]["comment"], "outgoing");
+        assert_eq!(entries[1]["request"]["url"], "http://example.com/b");
+        assert_eq!(entries[1]["response"]["_error"], "second");
+        assert_eq!(entries[1]["comment"], "incoming");
+    }
+}
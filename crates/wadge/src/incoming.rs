@@ -0,0 +1,292 @@
+//! In-process `wasi:http/incoming-handler` invocation
+
+use core::fmt;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+
+use std::sync::{Arc, Mutex, TryLockError};
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use http_body_util::BodyExt as _;
+use hyper::body::{Body, Frame};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+use wasmtime::component::Resource;
+use wasmtime_wasi_http::bindings::http::types::{ErrorCode, Scheme};
+use wasmtime_wasi_http::body::{HyperIncomingBody, HyperOutgoingBody};
+use wasmtime_wasi_http::types::{
+    FieldMap, HostIncomingRequest, HostOutgoingRequest, HostResponseOutparam, IncomingResponse,
+    OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::WasiHttpView;
+
+use crate::bindings::wasiext::http::ext;
+use crate::har::{Direction, Exchange};
+use crate::Instance;
+
+/// Prefix of `wasi:http/incoming-handler` export names, versions are matched as `0.2.x`
+const INCOMING_HANDLER: &str = "wasi:http/incoming-handler@0.2.";
+
+type ResponseSender = oneshot::Sender<Result<hyper::Response<HyperOutgoingBody>, ErrorCode>>;
+
+impl Instance {
+    /// Handles `request` using the `wasi:http/incoming-handler` export of the component.
+    ///
+    /// This method returns once the handler returns. Response body frames are forwarded
+    /// to the returned body as they are written by the component, so the handler never
+    /// blocks on the caller reading the body.
+    pub fn handle_http<B>(
+        &mut self,
+        request: http::Request<B>,
+    ) -> anyhow::Result<http::Response<HyperIncomingBody>>
+    where
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: fmt::Display,
+    {
+        let request = request.map(|body| {
+            body.map_err(|err| ErrorCode::InternalError(Some(err.to_string())))
+                .boxed_unsync()
+        });
+        let (tx, rx): (ResponseSender, _) = oneshot::channel();
+        let resp = wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
+            tokio::spawn(async move {
+                let Ok(resp) = rx.await else {
+                    return Ok(None);
+                };
+                let (parts, body) = resp
+                    .context("handler responded with an error")?
+                    .into_parts();
+                let (frames, rx) = ChannelBody::new();
+                tokio::spawn(forward_frames(body, frames));
+                anyhow::Ok(Some(http::Response::from_parts(parts, rx.boxed_unsync())))
+            })
+        });
+        self.handle_outgoing_request(request, tx)?;
+        wasmtime_wasi::runtime::in_tokio(resp)
+            .context("failed to receive response")??
+            .context("handler did not set a response")
+    }
+
+    /// Returns the name of the `wasi:http/incoming-handler` export
+    pub(crate) fn incoming_handler(&self) -> anyhow::Result<String> {
+        self.find_export(INCOMING_HANDLER)
+            .context("component does not export `wasi:http/incoming-handler@0.2.x`")
+    }
+
+    /// Invokes the `wasi:http/incoming-handler` export with `request`, using the
+    /// `wasiext:http/ext.new-incoming-request` conversion.
+    /// The response is sent on `tx`, once set by the component.
+    pub(crate) fn handle_outgoing_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        tx: ResponseSender,
+    ) -> anyhow::Result<()> {
+        let name = self.incoming_handler()?;
+        let (request, tx) = match self.store.data().har.clone() {
+            Some(har) => {
+                let (request, exchange) = har.begin(Direction::Incoming, request);
+                (request, log_response(exchange, tx))
+            }
+            None => (request, tx),
+        };
+        let (parts, body) = request.into_parts();
+        let scheme = parts.uri.scheme().map(|scheme| {
+            if *scheme == http::uri::Scheme::HTTP {
+                Scheme::Http
+            } else if *scheme == http::uri::Scheme::HTTPS {
+                Scheme::Https
+            } else {
+                Scheme::Other(scheme.to_string())
+            }
+        });
+        let req = HostOutgoingRequest {
+            method: parts.method.into(),
+            scheme,
+            authority: parts.uri.authority().map(ToString::to_string),
+            path_with_query: parts.uri.path_and_query().map(ToString::to_string),
+            headers: FieldMap::new(parts.headers, usize::MAX),
+            body: Some(body),
+        };
+        let cx = self.store.data_mut();
+        let req = cx
+            .table
+            .push(req)
+            .context("failed to push `outgoing-request` into resource table")?;
+        let req = ext::Host::new_incoming_request(cx, req)
+            .context("failed to construct `incoming-request`")?;
+        let out = WasiHttpView::new_response_outparam(cx, tx)
+            .context("failed to construct `response-outparam`")?;
+        let func = self
+            .func(&name, "handle")
+            .context("failed to lookup function")?;
+        let handle = func
+            .func
+            .typed::<(
+                Resource<HostIncomingRequest>,
+                Resource<HostResponseOutparam>,
+            ), ()>(&*func.store)
+            .with_context(|| format!("`{name}#handle` has an unexpected type"))?;
+        debug!(name, "invoking `wasi:http/incoming-handler`");
+        handle
+            .call(&mut self.store, (req, out))
+            .context("failed to call function")?;
+        handle
+            .post_return(&mut self.store)
+            .context("failed to invoke `post-return`")
+    }
+}
+
+/// Returns a sender logging the response sent on it to `exchange` and forwarding it to `tx`
+fn log_response(exchange: Exchange, tx: ResponseSender) -> ResponseSender {
+    let (log_tx, rx) = oneshot::channel();
+    wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
+        tokio::spawn(async move {
+            let res = match rx.await {
+                Ok(Ok(resp)) => Ok(exchange.respond(resp)),
+                Ok(Err(err)) => {
+                    exchange.fail(&err);
+                    Err(err)
+                }
+                Err(..) => {
+                    exchange.fail("handler did not set a response");
+                    return;
+                }
+            };
+            // receiver may have been dropped
+            let _ = tx.send(res);
+        })
+    });
+    log_tx
+}
+
+/// Frame sent on a [`ChannelBody`]
+type FrameResult = Result<Frame<Bytes>, ErrorCode>;
+
+/// Forwards all frames of `body` to `frames`
+async fn forward_frames(mut body: HyperOutgoingBody, frames: mpsc::UnboundedSender<FrameResult>) {
+    while let Some(frame) = body.frame().await {
+        let err = frame.is_err();
+        if frames.send(frame).is_err() || err {
+            return;
+        }
+    }
+}
+
+/// Body receiving frames from an unbounded channel
+struct ChannelBody {
+    rx: mpsc::UnboundedReceiver<FrameResult>,
+    done: bool,
+}
+
+impl ChannelBody {
+    fn new() -> (mpsc::UnboundedSender<FrameResult>, Self) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Self { rx, done: false })
+    }
+}
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(self.rx.poll_recv(cx));
+        if frame.is_none() {
+            self.done = true;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+/// Sends `request` to the `wasi:http/incoming-handler` export of `instance`.
+///
+/// Fails with [`ErrorCode::LoopDetected`] if `instance` is busy, e.g. if the request
+/// is routed back to an instance already handling a call further up the chain.
+pub(crate) async fn send_request(
+    instance: Arc<Mutex<Instance>>,
+    request: hyper::Request<HyperOutgoingBody>,
+    config: OutgoingRequestConfig,
+) -> Result<IncomingResponse, ErrorCode> {
+    let (tx, rx) = oneshot::channel();
+    let uri = request.uri().to_string();
+    let worker = wasmtime_wasi::runtime::spawn_blocking(move || {
+        let mut instance = match instance.try_lock() {
+            Ok(instance) => instance,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                warn!(
+                    uri,
+                    "in-process `wasi:http/incoming-handler` is busy, loop detected"
+                );
+                // receiver may have been dropped
+                let _ = tx.send(Err(ErrorCode::LoopDetected));
+                return;
+            }
+        };
+        if let Err(err) = instance.handle_outgoing_request(request, tx) {
+            warn!(?err, uri, "in-process `wasi:http/incoming-handler` failed");
+        }
+    });
+    match rx.await {
+        Ok(Ok(resp)) => Ok(IncomingResponse {
+            resp,
+            worker: Some(worker),
+            between_bytes_timeout: config.between_bytes_timeout,
+        }),
+        Ok(Err(err)) => Err(err),
+        Err(..) => Err(ErrorCode::InternalError(Some(
+            "in-process `wasi:http/incoming-handler` did not set a response".into(),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use http_body_util::Full;
+
+    #[test]
+    fn forward() {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("x-trailer", http::HeaderValue::from_static("value"));
+        let body = Full::new(Bytes::from("foobar"))
+            .map_err(|err| match err {})
+            .with_trailers({
+                let trailers = trailers.clone();
+                async { Some(Ok(trailers)) }
+            })
+            .boxed_unsync();
+        let (frames, rx) = ChannelBody::new();
+        let body = wasmtime_wasi::runtime::in_tokio(async {
+            tokio::spawn(forward_frames(body, frames));
+            rx.collect().await
+        })
+        .expect("failed to collect body");
+        assert_eq!(body.trailers(), Some(&trailers));
+        assert_eq!(body.to_bytes(), "foobar");
+    }
+
+    #[test]
+    fn forward_error() {
+        let body = Full::new(Bytes::from("foo"))
+            .map_err(|err| match err {})
+            .with_trailers(async { Some(Err(ErrorCode::ConnectionTerminated)) })
+            .boxed_unsync();
+        let (frames, rx) = ChannelBody::new();
+        let err = wasmtime_wasi::runtime::in_tokio(async {
+            tokio::spawn(forward_frames(body, frames));
+            rx.collect().await
+        })
+        .expect_err("error not forwarded");
+        assert!(matches!(err, ErrorCode::ConnectionTerminated));
+    }
+}
@@ -0,0 +1,603 @@
+//! `wasi:keyvalue` implementation backed by memory, a directory or a file
+//!
+//! This replaces the `wasmtime_wasi_keyvalue` crate, the in-memory store of which is
+//! private to the instance, so it can neither be inspected by native code nor persisted.
+//! `store`, `atomics` and `batch` interfaces are implemented on [`Ctx`].
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::Context as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use wasmtime::component::Resource;
+
+use crate::Ctx;
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "wasi:keyvalue/imports@0.2.0-draft",
+        imports: { default: trappable },
+        with: {
+            "wasi:keyvalue/store.bucket": super::Bucket,
+        },
+    });
+}
+
+pub(crate) use bindings::wasi::keyvalue::{atomics, batch, store};
+
+use store::{Error, KeyResponse};
+
+type Entries = BTreeMap<String, Vec<u8>>;
+
+/// Persistent storage of [`KeyValueStore`] buckets, values are base64-encoded in JSON
+#[derive(Debug)]
+enum Backend {
+    /// Each bucket is stored in a separate `<identifier>.json` file within the directory
+    Dir(PathBuf),
+    /// All buckets are stored in a single JSON file
+    File(PathBuf),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    buckets: BTreeMap<String, Entries>,
+    backend: Option<Backend>,
+}
+
+/// Characters not escaped in bucket file names
+fn is_name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-'
+}
+
+/// Returns the file name of `bucket` within a [`Backend::Dir`] directory.
+/// All characters except ASCII alphanumerics and `-` are percent-encoded and
+/// the empty identifier is represented by `_`.
+fn bucket_file_name(bucket: &str) -> String {
+    if bucket.is_empty() {
+        return "_.json".into();
+    }
+    let mut name = String::with_capacity(bucket.len() + 5);
+    for c in bucket.bytes() {
+        if is_name_char(c) {
+            name.push(char::from(c));
+        } else {
+            name.push_str(&format!("%{c:02X}"));
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
+/// Returns the identifier of the bucket stored in a file named `name`, if it is a bucket file
+fn bucket_identifier(name: &str) -> Option<String> {
+    let name = name.strip_suffix(".json")?;
+    if name == "_" {
+        return Some(String::default());
+    }
+    let mut buf = Vec::with_capacity(name.len());
+    let mut bytes = name.bytes();
+    while let Some(c) = bytes.next() {
+        match c {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                let hex = str::from_utf8(&hex).ok()?;
+                buf.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            c if is_name_char(c) => buf.push(c),
+            _ => return None,
+        }
+    }
+    String::from_utf8(buf).ok().filter(|name| !name.is_empty())
+}
+
+fn encode(entries: &Entries) -> BTreeMap<&str, String> {
+    entries
+        .iter()
+        .map(|(key, value)| (key.as_str(), BASE64.encode(value)))
+        .collect()
+}
+
+fn decode(entries: BTreeMap<String, String>) -> anyhow::Result<Entries> {
+    entries
+        .into_iter()
+        .map(|(key, value)| {
+            let value = BASE64
+                .decode(value)
+                .with_context(|| format!("failed to decode value of `{key}`"))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let buf = fs::read(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+    serde_json::from_slice(&buf).with_context(|| format!("failed to decode `{}`", path.display()))
+}
+
+/// Writes `value` to a temporary file next to `path`, which is then renamed to `path`,
+/// so that `path` is never observed partially written
+fn write_json(path: &Path, value: &impl serde::Serialize) -> anyhow::Result<()> {
+    let buf = serde_json::to_vec_pretty(value).context("failed to encode JSON")?;
+    let mut tmp = OsString::from(path);
+    tmp.push(".tmp");
+    fs::write(&tmp, buf).with_context(|| format!("failed to write `{}`", path.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to write `{}`", path.display()))
+}
+
+impl Backend {
+    /// Persists `entries` of `bucket`, `buckets` are the current contents of the store
+    fn write(
+        &self,
+        buckets: &BTreeMap<String, Entries>,
+        bucket: &str,
+        entries: &Entries,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Dir(path) => write_json(&path.join(bucket_file_name(bucket)), &encode(entries)),
+            Self::File(path) => {
+                let mut encoded: BTreeMap<_, _> = buckets
+                    .iter()
+                    .map(|(bucket, entries)| (bucket.as_str(), encode(entries)))
+                    .collect();
+                encoded.insert(bucket, encode(entries));
+                write_json(path, &encoded)
+            }
+        }
+    }
+}
+
+/// Key-value store backing guest `wasi:keyvalue`. Buckets are identified by
+/// the identifier passed to `wasi:keyvalue/store.open` and are created on first use.
+///
+/// By default the store is kept in memory, stores opened via [`KeyValueStore::open_dir`]
+/// or [`KeyValueStore::open_file`] persist every modification on disk.
+///
+/// Clones of [`KeyValueStore`] share the same underlying buckets, which allows
+/// native code to seed and inspect entries stored by the guest.
+#[derive(Clone, Debug, Default)]
+pub struct KeyValueStore(Arc<Mutex<State>>);
+
+impl KeyValueStore {
+    /// Constructs a new empty in-memory [`KeyValueStore`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a new in-memory [`KeyValueStore`] containing `buckets`, indexed by identifier
+    #[must_use]
+    pub fn from_buckets(
+        buckets: impl IntoIterator<Item = (String, BTreeMap<String, Vec<u8>>)>,
+    ) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            buckets: buckets.into_iter().collect(),
+            backend: None,
+        })))
+    }
+
+    /// Opens a [`KeyValueStore`] persisted in a directory at `path`, storing each bucket
+    /// in a separate JSON file. The directory is created if it does not exist.
+    pub fn open_dir(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        fs::create_dir_all(&path)
+            .with_context(|| format!("failed to create directory `{}`", path.display()))?;
+        let dir = fs::read_dir(&path)
+            .with_context(|| format!("failed to read directory `{}`", path.display()))?;
+        let mut buckets = BTreeMap::default();
+        for entry in dir {
+            let entry =
+                entry.with_context(|| format!("failed to read directory `{}`", path.display()))?;
+            let Some(bucket) = entry.file_name().to_str().and_then(bucket_identifier) else {
+                continue;
+            };
+            let entries = read_json(&entry.path()).and_then(decode)?;
+            buckets.insert(bucket, entries);
+        }
+        Ok(Self(Arc::new(Mutex::new(State {
+            buckets,
+            backend: Some(Backend::Dir(path)),
+        }))))
+    }
+
+    /// Opens a [`KeyValueStore`] persisted in a single JSON file at `path`.
+    /// The file is created if it does not exist.
+    pub fn open_file(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let buckets = match read_json::<BTreeMap<String, BTreeMap<String, String>>>(&path) {
+            Ok(buckets) => buckets
+                .into_iter()
+                .map(|(bucket, entries)| {
+                    let entries = decode(entries)
+                        .with_context(|| format!("failed to decode bucket `{bucket}`"))?;
+                    Ok((bucket, entries))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Err(err)
+                if err
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|err| err.kind() == io::ErrorKind::NotFound) =>
+            {
+                write_json(&path, &BTreeMap::<String, Entries>::default())?;
+                BTreeMap::default()
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Self(Arc::new(Mutex::new(State {
+            buckets,
+            backend: Some(Backend::File(path)),
+        }))))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Calls `f` with entries of `bucket`, which is empty if it does not exist
+    fn read<T>(&self, bucket: &str, f: impl FnOnce(&Entries) -> T) -> T {
+        let state = self.lock();
+        match state.buckets.get(bucket) {
+            Some(entries) => f(entries),
+            None => f(&Entries::default()),
+        }
+    }
+
+    /// Calls `f` with mutable entries of `bucket` creating it if it does not exist and
+    /// persists the result. The store is not modified if persisting fails.
+    fn update<T>(&self, bucket: &str, f: impl FnOnce(&mut Entries) -> T) -> anyhow::Result<T> {
+        let mut state = self.lock();
+        let State { buckets, backend } = &mut *state;
+        let Some(backend) = backend else {
+            return Ok(f(buckets.entry(bucket.into()).or_default()));
+        };
+        let mut entries = buckets.get(bucket).cloned().unwrap_or_default();
+        let ret = f(&mut entries);
+        backend
+            .write(buckets, bucket, &entries)
+            .with_context(|| format!("failed to persist bucket `{bucket}`"))?;
+        buckets.insert(bucket.into(), entries);
+        Ok(ret)
+    }
+
+    /// Creates `bucket` if it does not exist
+    fn create(&self, bucket: &str) -> anyhow::Result<()> {
+        if self.lock().buckets.contains_key(bucket) {
+            return Ok(());
+        }
+        self.update(bucket, |_| ())
+    }
+
+    /// Returns identifiers of all buckets in the store
+    #[must_use]
+    pub fn buckets(&self) -> Vec<String> {
+        self.lock().buckets.keys().cloned().collect()
+    }
+
+    /// Returns all keys in `bucket`
+    #[must_use]
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        self.read(bucket, |entries| entries.keys().cloned().collect())
+    }
+
+    /// Returns all entries in `bucket`, indexed by key
+    #[must_use]
+    pub fn entries(&self, bucket: &str) -> BTreeMap<String, Vec<u8>> {
+        self.read(bucket, Clone::clone)
+    }
+
+    /// Returns the value associated with `key` in `bucket`, if any
+    #[must_use]
+    pub fn get(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        self.read(bucket, |entries| entries.get(key).cloned())
+    }
+
+    /// Sets the value associated with `key` in `bucket`, creating the bucket if it does not exist
+    pub fn set(&self, bucket: &str, key: &str, value: impl Into<Vec<u8>>) -> anyhow::Result<()> {
+        self.update(bucket, |entries| {
+            entries.insert(key.into(), value.into());
+        })
+    }
+
+    /// Deletes `key` from `bucket` and returns the value associated with it, if any
+    pub fn delete(&self, bucket: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.get(bucket, key).is_none() {
+            return Ok(None);
+        }
+        self.update(bucket, |entries| entries.remove(key))
+    }
+}
+
+/// `wasi:keyvalue/store.bucket` referring to a [`KeyValueStore`] bucket
+pub struct Bucket {
+    store: KeyValueStore,
+    name: String,
+}
+
+impl Bucket {
+    fn read<T>(&self, f: impl FnOnce(&Entries) -> T) -> T {
+        self.store.read(&self.name, f)
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut Entries) -> T) -> Result<T, Error> {
+        self.store
+            .update(&self.name, f)
+            .map_err(|err| Error::Other(format!("{err:#}")))
+    }
+}
+
+impl Ctx {
+    fn bucket(&self, bucket: &Resource<Bucket>) -> wasmtime::Result<&Bucket> {
+        Ok(self.table.get(bucket)?)
+    }
+}
+
+impl store::Host for Ctx {
+    fn open(&mut self, identifier: String) -> wasmtime::Result<Result<Resource<Bucket>, Error>> {
+        if let Err(err) = self.keyvalue.create(&identifier) {
+            return Ok(Err(Error::Other(format!("{err:#}"))));
+        }
+        let bucket = self.table.push(Bucket {
+            store: self.keyvalue.clone(),
+            name: identifier,
+        })?;
+        Ok(Ok(bucket))
+    }
+}
+
+impl store::HostBucket for Ctx {
+    fn get(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+    ) -> wasmtime::Result<Result<Option<Vec<u8>>, Error>> {
+        let bucket = self.bucket(&bucket)?;
+        Ok(Ok(bucket.read(|entries| entries.get(&key).cloned())))
+    }
+
+    fn set(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        value: Vec<u8>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let bucket = self.bucket(&bucket)?;
+        Ok(bucket.update(|entries| {
+            entries.insert(key, value);
+        }))
+    }
+
+    fn delete(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let bucket = self.bucket(&bucket)?;
+        if !bucket.read(|entries| entries.contains_key(&key)) {
+            return Ok(Ok(()));
+        }
+        Ok(bucket.update(|entries| {
+            entries.remove(&key);
+        }))
+    }
+
+    fn exists(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+    ) -> wasmtime::Result<Result<bool, Error>> {
+        let bucket = self.bucket(&bucket)?;
+        Ok(Ok(bucket.read(|entries| entries.contains_key(&key))))
+    }
+
+    fn list_keys(
+        &mut self,
+        bucket: Resource<Bucket>,
+        cursor: Option<u64>,
+    ) -> wasmtime::Result<Result<KeyResponse, Error>> {
+        let bucket = self.bucket(&bucket)?;
+        let skip = cursor.map_or(0, |cursor| usize::try_from(cursor).unwrap_or(usize::MAX));
+        let keys = bucket.read(|entries| entries.keys().skip(skip).cloned().collect());
+        Ok(Ok(KeyResponse { keys, cursor: None }))
+    }
+
+    fn drop(&mut self, bucket: Resource<Bucket>) -> wasmtime::Result<()> {
+        self.table.delete(bucket)?;
+        Ok(())
+    }
+}
+
+impl atomics::Host for Ctx {
+    fn increment(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        delta: u64,
+    ) -> wasmtime::Result<Result<u64, Error>> {
+        let bucket = self.bucket(&bucket)?;
+        let value = bucket.update(|entries| {
+            // values are decimal strings, same as in `wasmtime-wasi-keyvalue`
+            let value = match entries.get(&key) {
+                Some(value) => str::from_utf8(value)
+                    .ok()
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .ok_or_else(|| Error::Other(format!("value of `{key}` is not a `u64`")))?,
+                None => 0,
+            };
+            let value = value
+                .checked_add(delta)
+                .ok_or_else(|| Error::Other(format!("value of `{key}` overflows a `u64`")))?;
+            entries.insert(key, value.to_string().into_bytes());
+            Ok(value)
+        });
+        Ok(value.and_then(|value| value))
+    }
+}
+
+impl batch::Host for Ctx {
+    fn get_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        keys: Vec<String>,
+    ) -> wasmtime::Result<Result<Vec<Option<(String, Vec<u8>)>>, Error>> {
+        let bucket = self.bucket(&bucket)?;
+        Ok(Ok(bucket.read(|entries| {
+            keys.into_iter()
+                .map(|key| {
+                    let value = entries.get(&key)?.clone();
+                    Some((key, value))
+                })
+                .collect()
+        })))
+    }
+
+    fn set_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key_values: Vec<(String, Vec<u8>)>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let bucket = self.bucket(&bucket)?;
+        Ok(bucket.update(|entries| entries.extend(key_values)))
+    }
+
+    fn delete_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        keys: Vec<String>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let bucket = self.bucket(&bucket)?;
+        Ok(bucket.update(|entries| {
+            for key in keys {
+                entries.remove(&key);
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory() {
+        let store = KeyValueStore::from_buckets([(
+            "seeded".to_string(),
+            BTreeMap::from([("foo".to_string(), b"bar".to_vec())]),
+        )]);
+        assert_eq!(store.buckets(), ["seeded"]);
+        assert_eq!(store.get("seeded", "foo").as_deref(), Some(&b"bar"[..]));
+        assert_eq!(store.get("seeded", "baz"), None);
+        assert_eq!(store.get("missing", "foo"), None);
+        assert!(store.keys("missing").is_empty());
+        assert_eq!(store.buckets(), ["seeded"], "reads must not create buckets");
+
+        let clone = store.clone();
+        clone.set("new", "a", "1").unwrap();
+        clone.set("new", "b", "2").unwrap();
+        assert_eq!(store.buckets(), ["new", "seeded"]);
+        assert_eq!(store.keys("new"), ["a", "b"]);
+        assert_eq!(
+            store.entries("new"),
+            BTreeMap::from([
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec())
+            ])
+        );
+
+        assert_eq!(
+            store.delete("new", "a").unwrap().as_deref(),
+            Some(&b"1"[..])
+        );
+        assert_eq!(store.delete("new", "a").unwrap(), None);
+        assert_eq!(store.delete("missing", "a").unwrap(), None);
+        assert_eq!(store.keys("new"), ["b"]);
+        assert_eq!(store.buckets(), ["new", "seeded"]);
+    }
+
+    #[test]
+    fn bucket_names() {
+        for (bucket, name) in [
+            ("", "_.json"),
+            ("_", "%5F.json"),
+            ("foo", "foo.json"),
+            ("Foo-1", "Foo-1.json"),
+            ("a/b", "a%2Fb.json"),
+            ("..", "%2E%2E.json"),
+            ("a b.json", "a%20b%2Ejson.json"),
+            ("%41", "%2541.json"),
+            ("ключ", "%D0%BA%D0%BB%D1%8E%D1%87.json"),
+        ] {
+            assert_eq!(bucket_file_name(bucket), name);
+            assert_eq!(bucket_identifier(name).as_deref(), Some(bucket), "{name}");
+        }
+        for name in [
+            "foo",
+            "foo.txt",
+            "foo.json.tmp",
+            "a b.json",
+            "a_b.json",
+            "%2.json",
+            "%ZZ.json",
+            "%FF.json",
+            ".json",
+        ] {
+            assert_eq!(bucket_identifier(name), None, "{name}");
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wadge-keyvalue-{name}-{}", std::process::id()))
+    }
+
+    fn persist(path: &Path, open: fn(PathBuf) -> anyhow::Result<KeyValueStore>) {
+        // remove leftovers of aborted runs
+        let _ = fs::remove_dir_all(path);
+        let _ = fs::remove_file(path);
+        let store = open(path.into()).expect("failed to open store");
+        assert!(store.buckets().is_empty());
+        store.set("a/b", "key", "value").unwrap();
+        store.set("", "empty", []).unwrap();
+        store.set("other", "foo", "bar").unwrap();
+        assert_eq!(
+            store.delete("other", "foo").unwrap().as_deref(),
+            Some(&b"bar"[..])
+        );
+        store.create("created").unwrap();
+        drop(store);
+
+        let store = open(path.into()).expect("failed to reopen store");
+        assert_eq!(store.buckets(), ["", "a/b", "created", "other"]);
+        assert_eq!(store.get("a/b", "key").as_deref(), Some(&b"value"[..]));
+        assert_eq!(store.get("", "empty").as_deref(), Some(&b""[..]));
+        assert!(store.keys("other").is_empty());
+        assert!(store.keys("created").is_empty());
+    }
+
+    #[test]
+    fn persist_dir() {
+        let path = temp_path("dir");
+        persist(&path, KeyValueStore::open_dir);
+        let mut names = fs::read_dir(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        fs::remove_dir_all(&path).unwrap();
+        assert_eq!(
+            names,
+            ["_.json", "a%2Fb.json", "created.json", "other.json"]
+        );
+    }
+
+    #[test]
+    fn persist_file() {
+        let path = temp_path("file.json");
+        persist(&path, KeyValueStore::open_file);
+        let buckets: BTreeMap<String, BTreeMap<String, String>> = read_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(buckets["a/b"]["key"], BASE64.encode("value"));
+    }
+}
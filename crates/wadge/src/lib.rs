@@ -1,19 +1,63 @@
+use core::fmt;
+use core::net::IpAddr;
 use core::time::Duration;
 
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
 use anyhow::Context as _;
+use rand_chacha::rand_core::{RngCore as _, SeedableRng as _};
+use rand_chacha::{ChaCha12Rng, ChaCha20Rng};
 use tracing::{info, instrument};
 use wasi_preview1_component_adapter_provider::{
     WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME, WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
 };
 use wasmtime::component::{
-    Component, HasSelf, Linker, Resource, ResourceTable, Type, TypedFunc, Val,
+    Component, HasSelf, Linker, Resource, ResourceAny, ResourceTable, Type, Val,
 };
 use wasmtime::{AsContextMut as _, Engine, Store};
 use wasmtime_cabish::CabishView;
-use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
-use wasmtime_wasi_http::types::HostIncomingRequest;
-use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx};
+use wasmtime_wasi::cli::WasiCliView as _;
+use wasmtime_wasi::{I32Exit, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::{
+    HostFutureIncomingResponse, HostIncomingRequest, IncomingResponse, OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::{HttpResult, WasiHttpCtx, WasiHttpView};
+
+use crate::cassette::CassetteState;
+use crate::har::Direction;
+use crate::outgoing::Outgoing;
+
+pub use blobstore::BlobStore;
+pub use cassette::{Cassette, CassetteMode};
+pub use clocks::VirtualClock;
+pub use fs::MemoryFs;
+pub use har::Har;
+pub use keyvalue::KeyValueStore;
+pub use messaging::{Message, MessageBroker, ReplyHandler};
+pub use net::{Cidr, NetworkPolicy, NetworkRule};
+pub use outgoing::{HttpPolicy, Intercept, OutgoingHttpHandler};
+pub use serve::serve;
+pub use stdio::{Input, InputPipe, Output, OutputCapture};
+pub use wasmtime_wasi::{DirPerms, FilePerms};
+pub use wasmtime_wasi_http::bindings::http::types::ErrorCode as HttpErrorCode;
+
+mod blobstore;
+mod cassette;
+mod clocks;
+mod config;
+mod fs;
+mod har;
+mod incoming;
+mod keyvalue;
+mod messaging;
+mod net;
+mod outgoing;
+mod serve;
+mod stdio;
 
 mod bindings {
     wasmtime::component::bindgen!({
@@ -23,16 +67,34 @@ mod bindings {
             "wasi:http/types@0.2.1.future-incoming-response": wasmtime_wasi_http::bindings::http::types::FutureIncomingResponse,
             "wasi:http/types@0.2.1.incoming-request": wasmtime_wasi_http::bindings::http::types::IncomingRequest,
             "wasi:http/types@0.2.1.outgoing-request": wasmtime_wasi_http::bindings::http::types::OutgoingRequest,
+            "wasi:http/types@0.2.1.outgoing-response": wasmtime_wasi_http::bindings::http::types::OutgoingResponse,
             "wasi:http/types@0.2.1.response-outparam": wasmtime_wasi_http::bindings::http::types::ResponseOutparam,
         },
     });
 }
 
+/// Prefix of `wasi:http/types` export names, versions are matched as `0.2.x`
+const HTTP_TYPES: &str = "wasi:http/types@0.2.";
+
 struct Ctx {
     wasi: WasiCtx,
     http: WasiHttpCtx,
-    kv: WasiKeyValueCtx,
+    keyvalue: KeyValueStore,
+    runtime_config: BTreeMap<String, String>,
+    blobstore: BlobStore,
+    messaging: MessageBroker,
     table: ResourceTable,
+    initial_cwd: Option<String>,
+    memory_preopens: Vec<(String, MemoryFs)>,
+    stdin: Option<InputPipe>,
+    stdout: Option<OutputCapture>,
+    stderr: Option<OutputCapture>,
+    clock: Option<VirtualClock>,
+    hosts: BTreeMap<String, Vec<IpAddr>>,
+    outgoing: Outgoing,
+    http_routes: BTreeMap<String, Arc<Mutex<Instance>>>,
+    http_ext: HttpExtConfig,
+    har: Option<Har>,
 }
 
 impl WasiView for Ctx {
@@ -52,6 +114,39 @@ impl WasiHttpView for Ctx {
     fn ctx(&mut self) -> &mut WasiHttpCtx {
         &mut self.http
     }
+
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        let target = self.target(&request, config.use_tls);
+        let outgoing = self.outgoing.clone();
+        let (request, exchange) = match &self.har {
+            Some(har) => {
+                let (request, exchange) = har.begin(Direction::Outgoing, request);
+                (request, Some(exchange))
+            }
+            None => (request, None),
+        };
+        let handle = wasmtime_wasi::runtime::spawn(async move {
+            let res = outgoing::send_request(request, config, outgoing, target).await;
+            let Some(exchange) = exchange else {
+                return Ok(res);
+            };
+            match res {
+                Ok(resp) => Ok(Ok(IncomingResponse {
+                    resp: exchange.respond(resp.resp),
+                    ..resp
+                })),
+                Err(err) => {
+                    exchange.fail(&err);
+                    Ok(Err(err))
+                }
+            }
+        });
+        Ok(HostFutureIncomingResponse::pending(handle))
+    }
 }
 
 impl CabishView for Ctx {
@@ -60,6 +155,20 @@ impl CabishView for Ctx {
     }
 }
 
+impl wasmtime_wasi::p2::bindings::cli::environment::Host for Ctx {
+    fn get_environment(&mut self) -> wasmtime::Result<Vec<(String, String)>> {
+        self.cli().get_environment()
+    }
+
+    fn get_arguments(&mut self) -> wasmtime::Result<Vec<String>> {
+        self.cli().get_arguments()
+    }
+
+    fn initial_cwd(&mut self) -> wasmtime::Result<Option<String>> {
+        Ok(self.initial_cwd.clone())
+    }
+}
+
 impl bindings::wasi::logging::logging::Host for Ctx {
     #[instrument(level = "trace", skip_all, ret(level = "trace"))]
     fn log(
@@ -81,6 +190,7 @@ impl bindings::wasiext::http::ext::Host for Ctx {
         Resource<wasmtime_wasi_http::types::HostResponseOutparam>,
         Resource<wasmtime_wasi_http::types::HostFutureIncomingResponse>,
     )> {
+        let between_bytes_timeout = self.http_ext.between_bytes_timeout;
         let (res_tx, res_rx) = tokio::sync::oneshot::channel();
         let out = WasiHttpView::new_response_outparam(self, res_tx)
             .context("failed to construct `response-outparam`")?;
@@ -88,12 +198,12 @@ impl bindings::wasiext::http::ext::Host for Ctx {
             .table
             .push(
                 wasmtime_wasi_http::types::HostFutureIncomingResponse::Pending(
-                    wasmtime_wasi::runtime::spawn(async {
+                    wasmtime_wasi::runtime::spawn(async move {
                         match res_rx.await.context("failed to receive response")? {
                             Ok(resp) => Ok(Ok(wasmtime_wasi_http::types::IncomingResponse {
                                 resp,
                                 worker: None,
-                                between_bytes_timeout: Duration::from_secs(1),
+                                between_bytes_timeout,
                             })),
                             Err(err) => Ok(Err(err)),
                         }
@@ -141,7 +251,7 @@ impl bindings::wasiext::http::ext::Host for Ctx {
         let uri = if let Some(authority) = authority {
             uri.authority(authority)
         } else {
-            uri.authority("wadge")
+            uri.authority(self.http_ext.default_authority.as_str())
         };
         let uri = uri.build().context("failed to build URI")?;
         let mut req = http::Request::builder();
@@ -182,7 +292,11 @@ impl bindings::wasiext::http::ext::Host for Ctx {
             .context("failed to build HTTP request")?;
         let (parts, ()) = req.into_parts();
 
-        let field_size_limit = 2 << 30; // match wasmtime-wasi-http default
+        let HttpExtConfig {
+            between_bytes_timeout,
+            field_size_limit,
+            ..
+        } = self.http_ext;
         let req = HostIncomingRequest::new(
             self,
             parts,
@@ -190,7 +304,7 @@ impl bindings::wasiext::http::ext::Host for Ctx {
             body.map(|body| {
                 wasmtime_wasi_http::body::HostIncomingBody::new(
                     body,
-                    Duration::from_secs(1),
+                    between_bytes_timeout,
                     field_size_limit,
                 )
             }),
@@ -201,13 +315,187 @@ impl bindings::wasiext::http::ext::Host for Ctx {
             .push(req)
             .context("failed to push `incoming-request` into resource table")
     }
+
+    #[instrument(level = "trace", skip_all, ret(level = "trace"))]
+    fn new_incoming_response(
+        &mut self,
+        resp: Resource<wasmtime_wasi_http::types::HostOutgoingResponse>,
+    ) -> wasmtime::Result<Resource<wasmtime_wasi_http::types::HostFutureIncomingResponse>> {
+        let resp = self
+            .table
+            .delete(resp)
+            .context("failed to delete outgoing response")?;
+        let resp = hyper::Response::try_from(resp).context("failed to build HTTP response")?;
+        self.table
+            .push(
+                wasmtime_wasi_http::types::HostFutureIncomingResponse::ready(Ok(Ok(
+                    wasmtime_wasi_http::types::IncomingResponse {
+                        resp,
+                        worker: None,
+                        between_bytes_timeout: self.http_ext.between_bytes_timeout,
+                    },
+                ))),
+            )
+            .context("failed to push `future-incoming-response` into resource table")
+    }
+}
+
+/// Configuration of HTTP messages constructed via `wasiext:http/ext`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpExtConfig {
+    /// Timeout between frames of request and response bodies, defaults to 1s
+    pub between_bytes_timeout: Duration,
+    /// Maximum size of request fields, defaults to 2GiB
+    pub field_size_limit: usize,
+    /// Authority of requests, which do not specify one, defaults to `wadge`
+    pub default_authority: String,
+}
+
+impl Default for HttpExtConfig {
+    fn default() -> Self {
+        Self {
+            between_bytes_timeout: Duration::from_secs(1),
+            field_size_limit: 2 << 30, // match wasmtime-wasi-http default
+            default_authority: "wadge".into(),
+        }
+    }
+}
+
+/// Host environment variable inheritance policy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InheritEnv {
+    /// Do not expose any host environment variables to the guest
+    None,
+    /// Expose all host environment variables to the guest
+    All,
+    /// Expose only the host environment variables with the listed names to the guest
+    Allow(Vec<String>),
+}
+
+/// Host directory exposed to the guest via `wasi:filesystem/preopens`
+#[derive(Clone, Debug)]
+pub struct Preopen {
+    /// Path to the directory on the host
+    pub host_path: PathBuf,
+    /// Path to the directory as seen by the guest
+    pub guest_path: String,
+    /// Permissions the guest has on the directory
+    pub dir_perms: DirPerms,
+    /// Maximum permissions the guest has on files within the directory
+    pub file_perms: FilePerms,
 }
 
 pub struct Config<'a> {
     pub engine: Engine,
     pub wasm: &'a [u8],
+    /// Environment variables exposed to the guest, these take precedence over
+    /// the inherited host environment variables
+    pub env: BTreeMap<String, String>,
+    /// Host environment variable inheritance policy
+    pub inherit_env: InheritEnv,
+    /// Arguments exposed to the guest via `wasi:cli/environment`
+    pub args: Vec<String>,
+    /// Initial working directory exposed to the guest via `wasi:cli/environment`
+    pub cwd: Option<String>,
+    /// Host directories exposed to the guest via `wasi:filesystem/preopens`
+    pub preopens: Vec<Preopen>,
+    /// In-memory filesystems exposed to the guest via `wasi:filesystem/preopens`,
+    /// indexed by guest path
+    pub memory_preopens: Vec<(String, MemoryFs)>,
+    /// Guest stdin handling
+    pub stdin: Input,
+    /// Guest stdout handling
+    pub stdout: Output,
+    /// Guest stderr handling
+    pub stderr: Output,
+    /// Virtual clock backing guest `wasi:clocks`, host clocks are used if `None`
+    pub clock: Option<VirtualClock>,
+    /// Seed making guest `wasi:random` interfaces deterministic, OS-backed
+    /// randomness is used if `None`
+    pub random_seed: Option<u64>,
+    /// Guest `wasi:sockets` network policy
+    pub network: NetworkPolicy,
+    /// Hostname overrides used by `wasi:sockets/ip-name-lookup` and outgoing `wasi:http` requests
+    pub hosts: BTreeMap<String, Vec<IpAddr>>,
+    /// Whether `wasi:sockets/ip-name-lookup` resolves hostnames not present
+    /// in `hosts` using the system resolver, disabled by default
+    pub resolve_fallthrough: bool,
+    /// Native handler intercepting outgoing guest `wasi:http` requests
+    pub outgoing_http_handler: Option<OutgoingHttpHandler>,
+    /// Outgoing `wasi:http` record/replay cassette, requests intercepted by
+    /// `outgoing_http_handler` are not recorded
+    pub cassette: Option<Cassette>,
+    /// In-process components handling outgoing `wasi:http` requests, indexed by authority
+    /// (`host` or `host:port`). Matching requests are dispatched to the
+    /// `wasi:http/incoming-handler` export of the component instead of the network.
+    /// Requests to an instance, which is busy handling a call, fail with `loop-detected`
+    pub http_routes: BTreeMap<String, Arc<Mutex<Instance>>>,
+    /// Configuration of HTTP messages constructed via `wasiext:http/ext`
+    pub http_ext: HttpExtConfig,
+    /// HTTP Archive (HAR) log of all incoming and outgoing `wasi:http` exchanges
+    pub har: Option<Har>,
+    /// Policy applied to outgoing `wasi:http` requests leaving the process
+    pub http_policy: HttpPolicy,
+    /// Store backing guest `wasi:keyvalue`, which may be seeded with initial buckets
+    pub keyvalue: KeyValueStore,
+    /// Configuration values exposed to the guest via `wasi:config/store`
+    pub runtime_config: BTreeMap<String, String>,
+    /// Store backing guest `wasi:blobstore`, which may be seeded with initial containers
+    pub blobstore: BlobStore,
+    /// Broker backing guest `wasi:messaging`, which records all messages published by the guest
+    pub messaging: MessageBroker,
+}
+
+impl Default for Config<'_> {
+    /// Returns a [`Config`] with an empty `wasm`, which inherits the host environment,
+    /// stdout, stderr and network and otherwise uses the defaults of the respective fields
+    fn default() -> Self {
+        Self {
+            engine: Engine::default(),
+            wasm: &[],
+            env: BTreeMap::default(),
+            inherit_env: InheritEnv::All,
+            args: Vec::default(),
+            cwd: None,
+            preopens: Vec::default(),
+            memory_preopens: Vec::default(),
+            stdin: Input::default(),
+            stdout: Output::Inherit,
+            stderr: Output::Inherit,
+            clock: None,
+            random_seed: None,
+            network: NetworkPolicy::Inherit,
+            hosts: BTreeMap::default(),
+            resolve_fallthrough: false,
+            outgoing_http_handler: None,
+            cassette: None,
+            http_routes: BTreeMap::default(),
+            http_ext: HttpExtConfig::default(),
+            har: None,
+            http_policy: HttpPolicy::AllowAll,
+            keyvalue: KeyValueStore::default(),
+            runtime_config: BTreeMap::default(),
+            blobstore: BlobStore::default(),
+            messaging: MessageBroker::default(),
+        }
+    }
+}
+
+/// Guest exit via `wasi:cli/exit`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Exit {
+    /// Exit code, `0` on success
+    pub code: i32,
 }
 
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "guest exited with code {}", self.code)
+    }
+}
+
+impl core::error::Error for Exit {}
+
 pub struct Func<'a> {
     func: wasmtime::component::Func,
     store: &'a mut Store<Ctx>,
@@ -228,10 +516,16 @@ impl Func<'_> {
         self.func.ty(&self.store).results().collect()
     }
 
+    /// Calls the function.
+    ///
+    /// If the guest exits via `wasi:cli/exit`, the returned error is [`Exit`]
     pub fn call(&mut self, params: &[Val], results: &mut [Val]) -> anyhow::Result<()> {
-        self.func
-            .call(self.store.as_context_mut(), params, results)
-            .context("failed to call function")?;
+        if let Err(err) = self.func.call(self.store.as_context_mut(), params, results) {
+            if let Some(I32Exit(code)) = err.downcast_ref() {
+                return Err(Exit { code: *code }.into());
+            }
+            return Err(err.context("failed to call function"));
+        }
         self.func
             .post_return(self.store.as_context_mut())
             .context("failed to invoke `post-return`")
@@ -243,6 +537,7 @@ impl Func<'_> {
 }
 
 pub struct Instance {
+    component: Component,
     instance: wasmtime::component::Instance,
     store: Store<Ctx>,
 }
@@ -278,82 +573,427 @@ impl Instance {
             .func(instance, name)
             .context("failed to lookup function")?;
         func.call(params, results)
-            .context("failed to call function")
     }
 
+    /// Returns the name of the first export with `prefix`, if any
+    pub(crate) fn find_export(&self, prefix: &str) -> Option<String> {
+        self.component
+            .component_type()
+            .exports(self.store.engine())
+            .find_map(|(name, _)| name.starts_with(prefix).then(|| name.to_string()))
+    }
+
+    /// Calls `[static]response-outparam.set` exported by the component
+    /// within `wasi:http/types@0.2.x`.
+    ///
+    /// The resources are typically defined by the component itself, e.g. by the
+    /// passthrough, so they are passed untyped and `err` is an `error-code` value.
+    /// Fails if the values do not match the function type.
     pub fn call_http_response_outparam_set(
         &mut self,
-        out: Resource<wasmtime_wasi_http::types::HostResponseOutparam>,
-        res: Result<
-            Resource<wasmtime_wasi_http::types::HostOutgoingResponse>,
-            wasmtime_wasi_http::bindings::http::types::ErrorCode,
-        >,
+        out: ResourceAny,
+        res: Result<ResourceAny, Val>,
     ) -> anyhow::Result<()> {
-        let func = self
-            .func("wasi:http/types@0.2.0", "[static]response-outparam.set")
+        let instance = self
+            .find_export(HTTP_TYPES)
+            .context("component does not export `wasi:http/types@0.2.x`")?;
+        let mut func = self
+            .func(&instance, "[static]response-outparam.set")
             .context("failed to lookup function")?;
-        let func = unsafe { TypedFunc::new_unchecked(func.func) };
-        func.call(&mut self.store, (out.rep(), res))
-            .context("failed to call function")
+        let res = match res {
+            Ok(resp) => Ok(Some(Box::new(Val::Resource(resp)))),
+            Err(err) => Err(Some(Box::new(err))),
+        };
+        func.call(&[Val::Resource(out), Val::Result(res)], &mut [])
+            .with_context(|| format!("failed to call `{instance}#[static]response-outparam.set`"))
     }
 
     pub fn store(&mut self) -> &mut Store<impl CabishView + WasiView + WasiHttpView> {
         &mut self.store
     }
+
+    /// Returns the in-memory filesystem preopened at `guest_path`, if any
+    #[must_use]
+    pub fn memory_fs(&self, guest_path: &str) -> Option<&MemoryFs> {
+        self.store
+            .data()
+            .memory_preopens
+            .iter()
+            .find_map(|(path, fs)| (path == guest_path).then_some(fs))
+    }
+
+    /// Returns the guest stdin buffer, if stdin is fed by native code
+    #[must_use]
+    pub fn stdin(&self) -> Option<&InputPipe> {
+        self.store.data().stdin.as_ref()
+    }
+
+    /// Returns the guest stdout buffer, if stdout is captured
+    #[must_use]
+    pub fn stdout(&self) -> Option<&OutputCapture> {
+        self.store.data().stdout.as_ref()
+    }
+
+    /// Returns the guest stderr buffer, if stderr is captured
+    #[must_use]
+    pub fn stderr(&self) -> Option<&OutputCapture> {
+        self.store.data().stderr.as_ref()
+    }
+
+    /// Takes all bytes captured from guest stdout
+    #[must_use]
+    pub fn take_stdout(&self) -> Vec<u8> {
+        self.stdout().map(OutputCapture::take).unwrap_or_default()
+    }
+
+    /// Takes all bytes captured from guest stderr
+    #[must_use]
+    pub fn take_stderr(&self) -> Vec<u8> {
+        self.stderr().map(OutputCapture::take).unwrap_or_default()
+    }
+
+    /// Returns the virtual clock backing guest `wasi:clocks`, if any
+    #[must_use]
+    pub fn clock(&self) -> Option<&VirtualClock> {
+        self.store.data().clock.as_ref()
+    }
+
+    /// Returns the store backing guest `wasi:keyvalue`
+    #[must_use]
+    pub fn keyvalue(&self) -> &KeyValueStore {
+        &self.store.data().keyvalue
+    }
+
+    /// Returns the store backing guest `wasi:blobstore`
+    #[must_use]
+    pub fn blobstore(&self) -> &BlobStore {
+        &self.store.data().blobstore
+    }
+
+    /// Returns the broker backing guest `wasi:messaging`
+    #[must_use]
+    pub fn messaging(&self) -> &MessageBroker {
+        &self.store.data().messaging
+    }
+
+    /// Writes outgoing `wasi:http` exchanges recorded since the last flush to the cassette, if any,
+    /// and syncs the HAR log, if any, to disk.
+    /// Recorded exchanges are also written once the instance is dropped.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let cx = self.store.data();
+        if let Some(cassette) = &cx.outgoing.cassette {
+            cassette.flush().context("failed to flush cassette")?;
+        }
+        if let Some(har) = &cx.har {
+            har.flush().context("failed to flush HAR log")?;
+        }
+        Ok(())
+    }
 }
 
-pub fn instantiate(Config { engine, wasm }: Config) -> anyhow::Result<Instance> {
-    let wasm = if wasmparser::Parser::is_core_wasm(wasm) {
-        let wasm = wit_component::ComponentEncoder::default()
-            .module(wasm)
-            .context("failed to set core component module")?
-            .adapter(
-                WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME,
-                WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
-            )
-            .context("failed to add WASI preview1 adapter")?
-            .encode()
-            .context("failed to encode a component from module")?;
-        let wasm = wasm.as_slice();
-        return instantiate(Config { engine, wasm });
-    } else {
-        wasm
+/// Compiled and linked component, which can be instantiated multiple times
+/// without recompiling
+pub struct InstancePre {
+    pre: wasmtime::component::InstancePre<Ctx>,
+}
+
+impl InstancePre {
+    /// Compiles and links `wasm`, which may either be a component or a core module
+    /// targeting WASI preview1, using `engine`
+    pub fn new(engine: &Engine, wasm: &[u8]) -> anyhow::Result<Self> {
+        let component = if wasmparser::Parser::is_core_wasm(wasm) {
+            let wasm = wit_component::ComponentEncoder::default()
+                .module(wasm)
+                .context("failed to set core component module")?
+                .adapter(
+                    WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME,
+                    WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+                )
+                .context("failed to add WASI preview1 adapter")?
+                .encode()
+                .context("failed to encode a component from module")?;
+            Component::new(engine, wasm)
+        } else {
+            Component::new(engine, wasm)
+        }
+        .context("failed to compile component")?;
+
+        let mut linker = Linker::<Ctx>::new(engine);
+        let mut options = wasmtime_wasi::p2::bindings::sync::LinkOptions::default();
+        options.cli_exit_with_code(true);
+        wasmtime_wasi::p2::add_to_linker_with_options_sync(&mut linker, &options)
+            .context("failed to link WASI")?;
+        linker.allow_shadowing(true);
+        wasmtime_wasi::p2::bindings::cli::environment::add_to_linker::<_, HasSelf<Ctx>>(
+            &mut linker,
+            |cx| cx,
+        )
+        .context("failed to link `wasi:cli/environment`")?;
+        wasmtime_wasi::p2::bindings::sync::filesystem::types::add_to_linker::<_, HasSelf<Ctx>>(
+            &mut linker,
+            |cx| cx,
+        )
+        .context("failed to link `wasi:filesystem/types`")?;
+        wasmtime_wasi::p2::bindings::filesystem::preopens::add_to_linker::<_, HasSelf<Ctx>>(
+            &mut linker,
+            |cx| cx,
+        )
+        .context("failed to link `wasi:filesystem/preopens`")?;
+        wasmtime_wasi::p2::bindings::clocks::monotonic_clock::add_to_linker::<_, HasSelf<Ctx>>(
+            &mut linker,
+            |cx| cx,
+        )
+        .context("failed to link `wasi:clocks/monotonic-clock`")?;
+        wasmtime_wasi::p2::bindings::sockets::ip_name_lookup::add_to_linker::<_, HasSelf<Ctx>>(
+            &mut linker,
+            |cx| cx,
+        )
+        .context("failed to link `wasi:sockets/ip-name-lookup`")?;
+        linker.allow_shadowing(false);
+        wasmtime_wasi_http::add_only_http_to_linker_sync(&mut linker)
+            .context("failed to link `wasi:http`")?;
+        keyvalue::store::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:keyvalue/store`")?;
+        keyvalue::atomics::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:keyvalue/atomics`")?;
+        keyvalue::batch::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:keyvalue/batch`")?;
+        config::store::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:config/store`")?;
+        blobstore::types::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:blobstore/types`")?;
+        blobstore::container::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:blobstore/container`")?;
+        blobstore::blobstore::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:blobstore/blobstore`")?;
+        messaging::types::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:messaging/types`")?;
+        messaging::producer::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:messaging/producer`")?;
+        messaging::request_reply::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:messaging/request-reply`")?;
+        bindings::wasiext::http::ext::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasiext:http/ext`")?;
+        bindings::wasi::logging::logging::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
+            .context("failed to link `wasi:logging/logging`")?;
+        let pre = linker
+            .instantiate_pre(&component)
+            .context("failed to pre-instantiate component")?;
+        Ok(Self { pre })
+    }
+
+    /// Instantiates the component using `config`, `engine` and `wasm` of which are ignored
+    pub fn instantiate(&self, config: Config) -> anyhow::Result<Instance> {
+        instantiate_pre(&self.pre, config)
+    }
+}
+
+/// Compiles, links and instantiates `config.wasm`, see [`InstancePre`] for
+/// instantiating the same component multiple times
+pub fn instantiate(config: Config) -> anyhow::Result<Instance> {
+    InstancePre::new(&config.engine, config.wasm)?.instantiate(config)
+}
+
+fn instantiate_pre(
+    pre: &wasmtime::component::InstancePre<Ctx>,
+    Config {
+        engine: _,
+        wasm: _,
+        env,
+        inherit_env,
+        args,
+        cwd,
+        preopens,
+        memory_preopens,
+        stdin,
+        stdout,
+        stderr,
+        clock,
+        random_seed,
+        network,
+        hosts,
+        resolve_fallthrough,
+        outgoing_http_handler,
+        cassette,
+        http_routes,
+        http_ext,
+        har,
+        http_policy,
+        keyvalue,
+        runtime_config,
+        blobstore,
+        messaging,
+    }: Config,
+) -> anyhow::Result<Instance> {
+    let mut wasi = WasiCtxBuilder::new();
+    match inherit_env {
+        InheritEnv::None => {}
+        InheritEnv::All => {
+            for (k, v) in env::vars() {
+                if !env.contains_key(&k) {
+                    wasi.env(k, v);
+                }
+            }
+        }
+        InheritEnv::Allow(names) => {
+            for k in names {
+                if env.contains_key(&k) {
+                    continue;
+                }
+                if let Ok(v) = env::var(&k) {
+                    wasi.env(k, v);
+                }
+            }
+        }
+    }
+    for Preopen {
+        host_path,
+        guest_path,
+        dir_perms,
+        file_perms,
+    } in preopens
+    {
+        wasi.preopened_dir(&host_path, &guest_path, dir_perms, file_perms)
+            .with_context(|| {
+                format!(
+                    "failed to preopen `{}` as `{guest_path}`",
+                    host_path.display()
+                )
+            })?;
+    }
+    let stdin = match stdin {
+        Input::Empty => None,
+        Input::Inherit => {
+            wasi.inherit_stdin();
+            None
+        }
+        Input::Bytes(buf) => {
+            let pipe = InputPipe::new(buf, true);
+            wasi.stdin(pipe.clone());
+            Some(pipe)
+        }
+        Input::Pipe(buf) => {
+            let pipe = InputPipe::new(buf, false);
+            wasi.stdin(pipe.clone());
+            Some(pipe)
+        }
     };
-    let wasm = Component::new(&engine, wasm).context("failed to compile component")?;
-
-    let mut linker = Linker::<Ctx>::new(&engine);
-    wasmtime_wasi::p2::add_to_linker_sync(&mut linker).context("failed to link WASI")?;
-    wasmtime_wasi_http::add_only_http_to_linker_sync(&mut linker)
-        .context("failed to link `wasi:http`")?;
-    wasmtime_wasi_keyvalue::add_to_linker(&mut linker, |cx| {
-        WasiKeyValue::new(&cx.kv, &mut cx.table)
-    })
-    .context("failed to link `wasi:keyvalue`")?;
-    bindings::wasiext::http::ext::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
-        .context("failed to link `wasiext:http/ext`")?;
-    bindings::wasi::logging::logging::add_to_linker::<_, HasSelf<Ctx>>(&mut linker, |cx| cx)
-        .context("failed to link `wasi:logging/logging`")?;
-
-    let wasi = WasiCtxBuilder::new()
-        .inherit_env()
-        .inherit_stdout()
-        .inherit_stderr()
-        .inherit_network()
+    let stdout = match stdout {
+        Output::Inherit => {
+            wasi.inherit_stdout();
+            None
+        }
+        Output::Capture => {
+            let capture = OutputCapture::default();
+            wasi.stdout(capture.clone());
+            Some(capture)
+        }
+        Output::Discard => None,
+    };
+    let stderr = match stderr {
+        Output::Inherit => {
+            wasi.inherit_stderr();
+            None
+        }
+        Output::Capture => {
+            let capture = OutputCapture::default();
+            wasi.stderr(capture.clone());
+            Some(capture)
+        }
+        Output::Discard => None,
+    };
+    if let Some(clock) = &clock {
+        wasi.wall_clock(clocks::WallClock(clock.clone()))
+            .monotonic_clock(clocks::MonotonicClock(clock.clone()));
+    }
+    if let Some(seed) = random_seed {
+        info!(seed, "using deterministic `wasi:random`");
+        let mut insecure = ChaCha12Rng::seed_from_u64(seed);
+        insecure.set_stream(1);
+        let insecure_seed = u128::from(insecure.next_u64()) << 64 | u128::from(insecure.next_u64());
+        wasi.secure_random(ChaCha20Rng::seed_from_u64(seed))
+            .insecure_random(insecure)
+            .insecure_random_seed(insecure_seed);
+    }
+    network.apply(&mut wasi);
+    wasi.allow_ip_name_lookup(resolve_fallthrough);
+    let hosts = hosts
+        .into_iter()
+        .map(|(name, ips)| (name.to_ascii_lowercase(), ips))
+        .collect();
+    let http_routes = http_routes
+        .into_iter()
+        .map(|(authority, instance)| (authority.to_ascii_lowercase(), instance))
+        .collect();
+    let wasi = wasi
+        .envs(&env.into_iter().collect::<Vec<_>>())
+        .args(&args)
         .build();
+    let cassette = cassette
+        .map(CassetteState::new)
+        .transpose()
+        .context("failed to load cassette")?
+        .map(Arc::new);
     let http = WasiHttpCtx::new();
-    let kv = WasiKeyValueCtx::builder().build();
     let table = ResourceTable::new();
     let mut store = Store::new(
-        &engine,
+        pre.engine(),
         Ctx {
             wasi,
             http,
-            kv,
+            keyvalue,
+            runtime_config,
+            blobstore,
+            messaging,
             table,
+            initial_cwd: cwd,
+            memory_preopens,
+            stdin,
+            stdout,
+            stderr,
+            clock,
+            hosts,
+            outgoing: Outgoing {
+                handler: outgoing_http_handler,
+                cassette,
+                policy: http_policy,
+            },
+            http_routes,
+            http_ext,
+            har,
         },
     );
-    let instance = linker
-        .instantiate(&mut store, &wasm)
+    let instance = pre
+        .instantiate(&mut store)
         .context("failed to instantiate component")?;
-    Ok(Instance { instance, store })
+    Ok(Instance {
+        component: pre.component().clone(),
+        instance,
+        store,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use wit_component::StringEncoding;
+    use wit_parser::Resolve;
+
+    /// Encodes core module `wat` as a component targeting `world` defined in `wit`,
+    /// which may use any package in the repository `wit` directory
+    pub(crate) fn component(wit: &str, world: &str, wat: &str) -> Vec<u8> {
+        let mut resolve = Resolve::default();
+        resolve
+            .push_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../../wit"))
+            .expect("failed to parse WIT directory");
+        let pkg = resolve
+            .push_str("test.wit", wit)
+            .expect("failed to parse test WIT");
+        let world = resolve
+            .select_world(pkg, Some(world))
+            .expect("failed to select world");
+        let mut wasm = wat::parse_str(wat).expect("failed to parse WAT");
+        wit_component::embed_component_metadata(&mut wasm, &resolve, world, StringEncoding::UTF8)
+            .expect("failed to embed component metadata");
+        wasm
+    }
 }
@@ -0,0 +1,503 @@
+//! In-process `wasi:messaging` broker
+
+use core::fmt;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Context as _};
+use tracing::debug;
+use wasmtime::component::Resource;
+
+use crate::{Ctx, Instance};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "wasi:messaging/imports@0.2.0-draft",
+        imports: { default: trappable },
+        with: {
+            "wasi:messaging/request-reply.request-options": super::RequestOptions,
+            "wasi:messaging/types.client": super::Client,
+            "wasi:messaging/types.message": super::Message,
+        },
+    });
+}
+
+pub(crate) use bindings::wasi::messaging::{producer, request_reply, types};
+
+use types::Error;
+
+/// Name of the `wasi:messaging/incoming-handler` export
+const INCOMING_HANDLER: &str = "wasi:messaging/incoming-handler@0.2.0-draft";
+
+/// Message exchanged via `wasi:messaging`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Message {
+    /// Topic the message was published or delivered on
+    pub topic: Option<String>,
+    /// Topic replies to the message are published on via `wasi:messaging/request-reply.reply`
+    pub reply_to: Option<String>,
+    /// Format of `data`
+    pub content_type: Option<String>,
+    /// Opaque payload
+    pub data: Vec<u8>,
+    /// Key-value pairs attached to the message, keys are unique
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Message {
+    /// Constructs a new [`Message`] on `topic` carrying `data`
+    #[must_use]
+    pub fn new(topic: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            topic: Some(topic.into()),
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Native handler responding to guest `wasi:messaging/request-reply.request` calls,
+/// returned messages are passed to the guest as replies
+pub type ReplyHandler = Arc<dyn Fn(&Message) -> Vec<Message> + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    published: Vec<Message>,
+    reply_handlers: BTreeMap<String, ReplyHandler>,
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("published", &self.published)
+            .field("reply_handlers", &self.reply_handlers.keys())
+            .finish()
+    }
+}
+
+/// In-process message broker backing guest `wasi:messaging`. Every message published by
+/// the guest, including requests and replies, is recorded. Requests are answered by
+/// [`ReplyHandler`]s registered per topic.
+///
+/// Clones of [`MessageBroker`] share the same underlying state, which allows
+/// native code to register reply handlers and inspect messages published by the guest.
+#[derive(Clone, Debug, Default)]
+pub struct MessageBroker(Arc<Mutex<State>>);
+
+impl MessageBroker {
+    /// Constructs a new [`MessageBroker`] without any recorded messages or reply handlers
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sets the handler responding to requests on `topic`, replacing the existing one, if any
+    pub fn set_reply_handler(&self, topic: impl Into<String>, handler: ReplyHandler) {
+        self.lock().reply_handlers.insert(topic.into(), handler);
+    }
+
+    /// Removes the handler responding to requests on `topic`
+    pub fn remove_reply_handler(&self, topic: &str) {
+        self.lock().reply_handlers.remove(topic);
+    }
+
+    /// Returns all recorded messages in order of publication
+    #[must_use]
+    pub fn published(&self) -> Vec<Message> {
+        self.lock().published.clone()
+    }
+
+    /// Returns all recorded messages published on `topic` in order of publication
+    #[must_use]
+    pub fn published_to(&self, topic: &str) -> Vec<Message> {
+        self.lock()
+            .published
+            .iter()
+            .filter(|message| message.topic.as_deref() == Some(topic))
+            .cloned()
+            .collect()
+    }
+
+    /// Takes all recorded messages in order of publication
+    #[must_use]
+    pub fn take_published(&self) -> Vec<Message> {
+        std::mem::take(&mut self.lock().published)
+    }
+
+    fn publish(&self, message: Message) {
+        debug!(topic = message.topic, "recording published message");
+        self.lock().published.push(message);
+    }
+
+    /// Records `request` and calls the reply handler registered for its topic, if any.
+    /// The handler is called without holding the lock, so it may use the broker.
+    fn request(&self, request: Message) -> Option<Vec<Message>> {
+        let handler = {
+            let mut state = self.lock();
+            let handler = request
+                .topic
+                .as_ref()
+                .and_then(|topic| state.reply_handlers.get(topic))
+                .cloned();
+            state.published.push(request.clone());
+            handler
+        };
+        handler.map(|handler| handler(&request))
+    }
+}
+
+/// `wasi:messaging/types.client` connected to the [`MessageBroker`]
+pub struct Client;
+
+/// `wasi:messaging/request-reply.request-options`
+#[derive(Default)]
+pub struct RequestOptions {
+    expected_replies: Option<u32>,
+}
+
+impl Instance {
+    /// Delivers `message` to the `wasi:messaging/incoming-handler` export of the component
+    pub fn handle_message(&mut self, message: Message) -> anyhow::Result<()> {
+        let name = self
+            .find_export(INCOMING_HANDLER)
+            .context("component does not export `wasi:messaging/incoming-handler@0.2.0-draft`")?;
+        let message = self
+            .store
+            .data_mut()
+            .table
+            .push(message)
+            .context("failed to push `message` into resource table")?;
+        let func = self
+            .func(&name, "handle")
+            .context("failed to lookup function")?;
+        let handle = func
+            .func
+            .typed::<(Resource<Message>,), (Result<(), Error>,)>(&*func.store)
+            .with_context(|| format!("`{name}#handle` has an unexpected type"))?;
+        debug!(name, "invoking `wasi:messaging/incoming-handler`");
+        let (res,) = handle
+            .call(&mut self.store, (message,))
+            .context("failed to call function")?;
+        handle
+            .post_return(&mut self.store)
+            .context("failed to invoke `post-return`")?;
+        if let Err(err) = res {
+            bail!("handler returned an error: {err:?}")
+        }
+        Ok(())
+    }
+}
+
+impl Ctx {
+    fn message(&self, message: &Resource<Message>) -> wasmtime::Result<&Message> {
+        Ok(self.table.get(message)?)
+    }
+
+    fn message_mut(&mut self, message: &Resource<Message>) -> wasmtime::Result<&mut Message> {
+        Ok(self.table.get_mut(message)?)
+    }
+}
+
+impl types::Host for Ctx {}
+
+impl types::HostClient for Ctx {
+    fn connect(&mut self, _name: String) -> wasmtime::Result<Result<Resource<Client>, Error>> {
+        let client = self.table.push(Client)?;
+        Ok(Ok(client))
+    }
+
+    fn disconnect(&mut self, _client: Resource<Client>) -> wasmtime::Result<Result<(), Error>> {
+        Ok(Ok(()))
+    }
+
+    fn drop(&mut self, client: Resource<Client>) -> wasmtime::Result<()> {
+        self.table.delete(client)?;
+        Ok(())
+    }
+}
+
+impl types::HostMessage for Ctx {
+    fn new(&mut self, data: Vec<u8>) -> wasmtime::Result<Resource<Message>> {
+        let message = self.table.push(Message {
+            data,
+            ..Message::default()
+        })?;
+        Ok(message)
+    }
+
+    fn topic(&mut self, message: Resource<Message>) -> wasmtime::Result<Option<String>> {
+        Ok(self.message(&message)?.topic.clone())
+    }
+
+    fn content_type(&mut self, message: Resource<Message>) -> wasmtime::Result<Option<String>> {
+        Ok(self.message(&message)?.content_type.clone())
+    }
+
+    fn set_content_type(
+        &mut self,
+        message: Resource<Message>,
+        content_type: String,
+    ) -> wasmtime::Result<()> {
+        self.message_mut(&message)?.content_type = Some(content_type);
+        Ok(())
+    }
+
+    fn data(&mut self, message: Resource<Message>) -> wasmtime::Result<Vec<u8>> {
+        Ok(self.message(&message)?.data.clone())
+    }
+
+    fn set_data(&mut self, message: Resource<Message>, data: Vec<u8>) -> wasmtime::Result<()> {
+        self.message_mut(&message)?.data = data;
+        Ok(())
+    }
+
+    fn metadata(
+        &mut self,
+        message: Resource<Message>,
+    ) -> wasmtime::Result<Option<Vec<(String, String)>>> {
+        let Message { metadata, .. } = self.message(&message)?;
+        Ok((!metadata.is_empty()).then(|| metadata.clone()))
+    }
+
+    fn add_metadata(
+        &mut self,
+        message: Resource<Message>,
+        key: String,
+        value: String,
+    ) -> wasmtime::Result<()> {
+        let Message { metadata, .. } = self.message_mut(&message)?;
+        if let Some((_, v)) = metadata.iter_mut().find(|(k, _)| *k == key) {
+            *v = value;
+        } else {
+            metadata.push((key, value));
+        }
+        Ok(())
+    }
+
+    fn set_metadata(
+        &mut self,
+        message: Resource<Message>,
+        meta: Vec<(String, String)>,
+    ) -> wasmtime::Result<()> {
+        self.message_mut(&message)?.metadata = meta;
+        Ok(())
+    }
+
+    fn remove_metadata(&mut self, message: Resource<Message>, key: String) -> wasmtime::Result<()> {
+        let Message { metadata, .. } = self.message_mut(&message)?;
+        metadata.retain(|(k, _)| *k != key);
+        Ok(())
+    }
+
+    fn drop(&mut self, message: Resource<Message>) -> wasmtime::Result<()> {
+        self.table.delete(message)?;
+        Ok(())
+    }
+}
+
+impl producer::Host for Ctx {
+    fn send(
+        &mut self,
+        _client: Resource<Client>,
+        topic: String,
+        message: Resource<Message>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let mut message = self.table.delete(message)?;
+        message.topic = Some(topic);
+        self.messaging.publish(message);
+        Ok(Ok(()))
+    }
+}
+
+impl request_reply::Host for Ctx {
+    fn request(
+        &mut self,
+        _client: Resource<Client>,
+        topic: String,
+        message: Resource<Message>,
+        options: Option<Resource<RequestOptions>>,
+    ) -> wasmtime::Result<Result<Vec<Resource<Message>>, Error>> {
+        let expected_replies = match options {
+            Some(options) => self.table.delete(options)?.expected_replies,
+            None => None,
+        };
+        let mut request = self.message(&message)?.clone();
+        request.topic = Some(topic.clone());
+        let Some(mut replies) = self.messaging.request(request) else {
+            return Ok(Err(Error::Other(format!(
+                "no reply handler registered for topic `{topic}`"
+            ))));
+        };
+        if replies.is_empty() {
+            // reply handlers are called synchronously, so no more replies can arrive
+            return Ok(Err(Error::Timeout));
+        }
+        if let Some(n) = expected_replies {
+            replies.truncate(usize::try_from(n).unwrap_or(usize::MAX));
+        }
+        let replies = replies
+            .into_iter()
+            .map(|reply| self.table.push(reply))
+            .collect::<Result<_, _>>()?;
+        Ok(Ok(replies))
+    }
+
+    fn reply(
+        &mut self,
+        reply_to: Resource<Message>,
+        message: Resource<Message>,
+    ) -> wasmtime::Result<Result<(), Error>> {
+        let mut message = self.table.delete(message)?;
+        let Some(topic) = self.message(&reply_to)?.reply_to.clone() else {
+            return Ok(Err(Error::Other(
+                "message does not have a reply-to topic".into(),
+            )));
+        };
+        message.topic = Some(topic);
+        self.messaging.publish(message);
+        Ok(Ok(()))
+    }
+}
+
+impl request_reply::HostRequestOptions for Ctx {
+    fn new(&mut self) -> wasmtime::Result<Resource<RequestOptions>> {
+        let options = self.table.push(RequestOptions::default())?;
+        Ok(options)
+    }
+
+    fn set_timeout_ms(
+        &mut self,
+        _options: Resource<RequestOptions>,
+        _timeout_ms: u32,
+    ) -> wasmtime::Result<()> {
+        // reply handlers are called synchronously, so the timeout never elapses
+        Ok(())
+    }
+
+    fn set_expected_replies(
+        &mut self,
+        options: Resource<RequestOptions>,
+        expected_replies: u32,
+    ) -> wasmtime::Result<()> {
+        self.table.get_mut(&options)?.expected_replies = Some(expected_replies);
+        Ok(())
+    }
+
+    fn drop(&mut self, options: Resource<RequestOptions>) -> wasmtime::Result<()> {
+        self.table.delete(options)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::component;
+    use crate::Config;
+
+    /// Handler replying to each message with `pong`, returning the result of the reply
+    const HANDLER: &str = r#"(module
+        (import "wasi:messaging/types@0.2.0-draft" "[constructor]message"
+            (func $message (param i32 i32) (result i32)))
+        (import "wasi:messaging/types@0.2.0-draft" "[resource-drop]message"
+            (func $drop-message (param i32)))
+        (import "wasi:messaging/request-reply@0.2.0-draft" "reply"
+            (func $reply (param i32 i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "pong")
+        (global $heap (mut i32) (i32.const 1024))
+        (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr
+                (i32.and
+                    (i32.add (global.get $heap) (i32.sub (local.get 2) (i32.const 1)))
+                    (i32.sub (i32.const 0) (local.get 2))))
+            (global.set $heap (i32.add (local.get $ptr) (local.get 3)))
+            (local.get $ptr))
+        (func (export "wasi:messaging/incoming-handler@0.2.0-draft#handle")
+            (param $msg i32) (result i32)
+            (call $reply
+                (local.get $msg)
+                (call $message (i32.const 0) (i32.const 4))
+                (i32.const 16))
+            (call $drop-message (local.get $msg))
+            (i32.const 16))
+    )"#;
+
+    #[test]
+    fn broker() {
+        let broker = MessageBroker::new();
+        broker.publish(Message::new("events", "a"));
+        assert_eq!(broker.request(Message::new("rpc", "ping")), None);
+
+        let clone = broker.clone();
+        broker.set_reply_handler(
+            "rpc",
+            Arc::new(move |request| {
+                // handlers are called without holding the lock
+                assert_eq!(clone.published_to("rpc").last(), Some(request));
+                vec![Message::new("reply", "pong")]
+            }),
+        );
+        assert_eq!(
+            broker.request(Message::new("rpc", "ping")),
+            Some(vec![Message::new("reply", "pong")])
+        );
+        assert_eq!(
+            broker.request(Message {
+                topic: None,
+                ..Message::default()
+            }),
+            None
+        );
+        assert_eq!(broker.published_to("rpc").len(), 2);
+        assert_eq!(broker.published_to("events"), [Message::new("events", "a")]);
+
+        broker.remove_reply_handler("rpc");
+        assert_eq!(broker.request(Message::new("rpc", "ping")), None);
+        assert_eq!(broker.take_published().len(), 5);
+        assert!(broker.published().is_empty());
+    }
+
+    #[test]
+    fn handle_message_reply() {
+        let wasm = component(
+            "package wadge:test; world handler {
+                import wasi:messaging/request-reply@0.2.0-draft;
+                export wasi:messaging/incoming-handler@0.2.0-draft;
+            }",
+            "handler",
+            HANDLER,
+        );
+        let broker = MessageBroker::new();
+        let mut instance = crate::instantiate(Config {
+            wasm: &wasm,
+            messaging: broker.clone(),
+            ..Config::default()
+        })
+        .expect("failed to instantiate component");
+
+        instance
+            .handle_message(Message {
+                reply_to: Some("inbox".into()),
+                ..Message::new("rpc", "ping")
+            })
+            .expect("failed to handle message");
+        assert_eq!(broker.take_published(), [Message::new("inbox", "pong")]);
+
+        let err = instance
+            .handle_message(Message::new("rpc", "ping"))
+            .expect_err("reply without a reply-to topic succeeded");
+        assert!(
+            format!("{err:#}").contains("reply-to"),
+            "unexpected error: {err:#}"
+        );
+        assert!(broker.published().is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+//! Guest `wasi:sockets` network policy
+
+use core::fmt;
+use core::net::{IpAddr, SocketAddr};
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+
+use anyhow::{ensure, Context as _};
+use tracing::{debug, warn};
+use wasmtime::component::Resource;
+use wasmtime_wasi::p2::bindings::io::error::Error;
+use wasmtime_wasi::p2::bindings::sockets::ip_name_lookup::{self, ResolveAddressStream};
+use wasmtime_wasi::p2::bindings::sockets::network::{self, ErrorCode, IpAddress, Network};
+use wasmtime_wasi::p2::{DynPollable, SocketError};
+use wasmtime_wasi::sockets::{SocketAddrUse, WasiSocketsView as _};
+use wasmtime_wasi::WasiCtxBuilder;
+
+use crate::Ctx;
+
+/// IP address range in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Constructs a new [`Cidr`], failing if `prefix_len` exceeds the address length
+    pub fn new(addr: IpAddr, prefix_len: u8) -> anyhow::Result<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        ensure!(
+            prefix_len <= max,
+            "prefix length `{prefix_len}` exceeds `{max}`"
+        );
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// Returns `true` if `addr` is contained in the range.
+    /// IPv4-mapped IPv6 addresses are matched as IPv4 addresses.
+    #[must_use]
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((addr, prefix_len)) = s.split_once('/') else {
+            let addr = s.parse().context("invalid IP address")?;
+            return Self::new(addr, if s.contains(':') { 128 } else { 32 });
+        };
+        let addr = addr.parse().context("invalid IP address")?;
+        let prefix_len = prefix_len.parse().context("invalid prefix length")?;
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Socket addresses the guest is allowed to connect to or bind
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkRule {
+    /// Allowed IP address range
+    pub cidr: Cidr,
+    /// Allowed ports
+    pub ports: RangeInclusive<u16>,
+}
+
+impl NetworkRule {
+    /// Returns `true` if `addr` matches the rule
+    #[must_use]
+    pub fn allows(&self, addr: SocketAddr) -> bool {
+        self.cidr.contains(addr.ip()) && self.ports.contains(&addr.port())
+    }
+}
+
+/// Guest `wasi:sockets` network policy, applied on each connect, bind and outgoing datagram.
+/// Denied operations fail with `access-denied` error code.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum NetworkPolicy {
+    /// Allow all socket addresses
+    #[default]
+    Inherit,
+    /// Allow only loopback socket addresses
+    Loopback,
+    /// Deny all socket addresses
+    DenyAll,
+    /// Allow only socket addresses matching at least one of the rules
+    Allow(Vec<NetworkRule>),
+}
+
+impl NetworkPolicy {
+    /// Returns `true` if the guest is allowed to use `addr`
+    #[must_use]
+    pub fn allows(&self, addr: SocketAddr) -> bool {
+        match self {
+            Self::Inherit => true,
+            Self::Loopback => addr.ip().to_canonical().is_loopback(),
+            Self::DenyAll => false,
+            Self::Allow(rules) => rules.iter().any(|rule| rule.allows(addr)),
+        }
+    }
+
+    pub(crate) fn apply(self, wasi: &mut WasiCtxBuilder) {
+        if self == Self::Inherit {
+            wasi.inherit_network();
+            return;
+        }
+        wasi.socket_addr_check(move |addr, reason: SocketAddrUse| {
+            let allowed = self.allows(addr);
+            if !allowed {
+                warn!(%addr, ?reason, "socket address denied by network policy");
+            }
+            Box::pin(async move { allowed })
+        });
+    }
+}
+
+impl network::Host for Ctx {
+    fn convert_error_code(&mut self, err: SocketError) -> wasmtime::Result<ErrorCode> {
+        network::Host::convert_error_code(&mut self.sockets(), err)
+    }
+
+    fn network_error_code(&mut self, err: Resource<Error>) -> wasmtime::Result<Option<ErrorCode>> {
+        network::Host::network_error_code(&mut self.sockets(), err)
+    }
+}
+
+impl network::HostNetwork for Ctx {
+    fn drop(&mut self, network: Resource<Network>) -> wasmtime::Result<()> {
+        network::HostNetwork::drop(&mut self.sockets(), network)
+    }
+}
+
+impl ip_name_lookup::Host for Ctx {
+    fn resolve_addresses(
+        &mut self,
+        network: Resource<Network>,
+        name: String,
+    ) -> Result<Resource<ResolveAddressStream>, SocketError> {
+        let Some(ips) = self.hosts.get(&name.to_ascii_lowercase()) else {
+            return ip_name_lookup::Host::resolve_addresses(&mut self.sockets(), network, name);
+        };
+        debug!(name, ?ips, "resolving hostname override");
+        let ips = ips.iter().copied().map(IpAddress::from).collect::<Vec<_>>();
+        let stream = self
+            .table
+            .push(ResolveAddressStream::Done(Ok(ips.into_iter())))?;
+        Ok(stream)
+    }
+}
+
+impl ip_name_lookup::HostResolveAddressStream for Ctx {
+    fn resolve_next_address(
+        &mut self,
+        stream: Resource<ResolveAddressStream>,
+    ) -> Result<Option<IpAddress>, SocketError> {
+        ip_name_lookup::HostResolveAddressStream::resolve_next_address(&mut self.sockets(), stream)
+    }
+
+    fn subscribe(
+        &mut self,
+        stream: Resource<ResolveAddressStream>,
+    ) -> wasmtime::Result<Resource<DynPollable>> {
+        ip_name_lookup::HostResolveAddressStream::subscribe(&mut self.sockets(), stream)
+    }
+
+    fn drop(&mut self, stream: Resource<ResolveAddressStream>) -> wasmtime::Result<()> {
+        ip_name_lookup::HostResolveAddressStream::drop(&mut self.sockets(), stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().expect("invalid socket address")
+    }
+
+    #[test]
+    fn cidr_parse() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert_eq!(cidr.to_string(), "10.0.0.0/8");
+        let cidr: Cidr = "fd00::/8".parse().unwrap();
+        assert_eq!(cidr.to_string(), "fd00::/8");
+        let cidr: Cidr = "127.0.0.1".parse().unwrap();
+        assert_eq!(cidr.to_string(), "127.0.0.1/32");
+        let cidr: Cidr = "::1".parse().unwrap();
+        assert_eq!(cidr.to_string(), "::1/128");
+        let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert_eq!(cidr.to_string(), "0.0.0.0/0");
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/-1".parse::<Cidr>().is_err());
+        assert!("example.com/8".parse::<Cidr>().is_err());
+        assert!("".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains("10.0.0.0".parse().unwrap()));
+        assert!(cidr.contains("10.255.255.255".parse().unwrap()));
+        assert!(!cidr.contains("11.0.0.0".parse().unwrap()));
+        assert!(!cidr.contains("9.255.255.255".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("::a01:203".parse().unwrap()));
+
+        let cidr: Cidr = "192.168.1.1".parse().unwrap();
+        assert!(cidr.contains("192.168.1.1".parse().unwrap()));
+        assert!(!cidr.contains("192.168.1.2".parse().unwrap()));
+
+        let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(cidr.contains("1.2.3.4".parse().unwrap()));
+        assert!(cidr.contains("255.255.255.255".parse().unwrap()));
+        assert!(!cidr.contains("::1".parse().unwrap()));
+
+        let cidr: Cidr = "fd00::/8".parse().unwrap();
+        assert!(cidr.contains("fd12:3456::1".parse().unwrap()));
+        assert!(!cidr.contains("fe80::1".parse().unwrap()));
+        assert!(!cidr.contains("10.0.0.1".parse().unwrap()));
+
+        let cidr: Cidr = "::/0".parse().unwrap();
+        assert!(cidr.contains("2001:db8::1".parse().unwrap()));
+        assert!(!cidr.contains("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn policy() {
+        assert!(NetworkPolicy::Inherit.allows(addr("1.2.3.4:80")));
+
+        assert!(!NetworkPolicy::DenyAll.allows(addr("127.0.0.1:80")));
+        assert!(!NetworkPolicy::DenyAll.allows(addr("[::1]:80")));
+
+        assert!(NetworkPolicy::Loopback.allows(addr("127.0.0.1:80")));
+        assert!(NetworkPolicy::Loopback.allows(addr("127.1.2.3:80")));
+        assert!(NetworkPolicy::Loopback.allows(addr("[::1]:80")));
+        assert!(NetworkPolicy::Loopback.allows(addr("[::ffff:127.0.0.1]:80")));
+        assert!(!NetworkPolicy::Loopback.allows(addr("10.0.0.1:80")));
+
+        let policy = NetworkPolicy::Allow(vec![
+            NetworkRule {
+                cidr: "10.0.0.0/8".parse().unwrap(),
+                ports: 80..=80,
+            },
+            NetworkRule {
+                cidr: "fd00::/8".parse().unwrap(),
+                ports: 8000..=8999,
+            },
+        ]);
+        assert!(policy.allows(addr("10.1.2.3:80")));
+        assert!(!policy.allows(addr("10.1.2.3:81")));
+        assert!(!policy.allows(addr("11.1.2.3:80")));
+        assert!(policy.allows(addr("[fd00::1]:8000")));
+        assert!(policy.allows(addr("[fd00::1]:8999")));
+        assert!(!policy.allows(addr("[fd00::1]:9000")));
+        assert!(!policy.allows(addr("[fe80::1]:8000")));
+
+        assert!(!NetworkPolicy::Allow(Vec::default()).allows(addr("10.1.2.3:80")));
+    }
+}
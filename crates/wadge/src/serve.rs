@@ -0,0 +1,258 @@
+//! Serving `wasi:http/incoming-handler` over HTTP
+
+use core::convert::Infallible;
+use core::num::NonZeroUsize;
+
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use http_body_util::BodyExt as _;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::{debug, info, warn};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::hyper_request_error;
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::outgoing::full;
+use crate::{Config, Instance, InstancePre};
+
+/// Pool of instance slots, an empty slot is filled by a new instance on use
+struct Pool {
+    pre: InstancePre,
+    config: Box<dyn Fn() -> Config<'static> + Send + Sync>,
+    tx: mpsc::UnboundedSender<Option<Instance>>,
+    rx: Mutex<mpsc::UnboundedReceiver<Option<Instance>>>,
+}
+
+impl Pool {
+    async fn acquire(&self) -> Option<Instance> {
+        self.rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .expect("pool sender is owned by the pool")
+    }
+
+    fn instantiate(&self) -> anyhow::Result<Instance> {
+        self.pre
+            .instantiate((self.config)())
+            .context("failed to instantiate component")
+    }
+
+    fn release(&self, instance: Option<Instance>) {
+        // receiver is owned by the pool
+        let _ = self.tx.send(instance);
+    }
+}
+
+fn error_response(status: http::StatusCode, message: String) -> hyper::Response<HyperOutgoingBody> {
+    let mut resp = hyper::Response::new(full(Bytes::from(message)));
+    *resp.status_mut() = status;
+    resp
+}
+
+/// Returns the absolute request URI with authority taken from the `host` header, if any
+fn absolute_uri<B>(request: &hyper::Request<B>) -> Option<http::Uri> {
+    if request.uri().authority().is_some() {
+        return None;
+    }
+    let host = request.headers().get(http::header::HOST)?.to_str().ok()?;
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map_or("/", http::uri::PathAndQuery::as_str);
+    http::Uri::builder()
+        .scheme(http::uri::Scheme::HTTP)
+        .authority(host)
+        .path_and_query(path_and_query)
+        .build()
+        .ok()
+}
+
+async fn handle(
+    pool: Arc<Pool>,
+    request: hyper::Request<Incoming>,
+) -> Result<hyper::Response<HyperOutgoingBody>, Infallible> {
+    let mut request = request.map(|body| body.map_err(hyper_request_error).boxed_unsync());
+    if let Some(uri) = absolute_uri(&request) {
+        *request.uri_mut() = uri;
+    }
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    debug!(%method, %uri, "handling HTTP request");
+    let instance = pool.acquire().await;
+    let (tx, rx) = oneshot::channel();
+    let task = tokio::task::spawn_blocking(move || {
+        let mut instance = match instance.map_or_else(|| pool.instantiate(), Ok) {
+            Ok(instance) => instance,
+            Err(err) => {
+                pool.release(None);
+                return Err(err);
+            }
+        };
+        if let Err(err) = instance.handle_outgoing_request(request, tx) {
+            warn!(?err, %method, %uri, "`wasi:http/incoming-handler` failed");
+            // the instance may be left in a broken state after a failure, e.g. a trap,
+            // so it is replaced by a new one
+            pool.release(None);
+            return Err(err);
+        }
+        pool.release(Some(instance));
+        Ok(())
+    });
+    match rx.await {
+        Ok(Ok(resp)) => Ok(resp),
+        Ok(Err(err)) => Ok(error_response(
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("handler responded with an error: {err}"),
+        )),
+        Err(..) => match task.await {
+            Ok(Err(err)) => Ok(error_response(
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("handler failed: {err:#}"),
+            )),
+            _ => Ok(error_response(
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                "handler did not set a response".into(),
+            )),
+        },
+    }
+}
+
+/// Serves the `wasi:http/incoming-handler` export of component `pre` on `listener`.
+///
+/// `concurrency` instances are created using configuration returned by `config`,
+/// each handling a single request at a time. An instance, which fails to handle a request,
+/// is replaced by a new one.
+/// This function only returns on error.
+pub async fn serve(
+    listener: TcpListener,
+    pre: InstancePre,
+    concurrency: NonZeroUsize,
+    config: impl Fn() -> Config<'static> + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let pool = Arc::new(Pool {
+        pre,
+        config: Box::new(config),
+        tx,
+        rx: Mutex::new(rx),
+    });
+    for _ in 0..concurrency.get() {
+        let instance = pool.instantiate()?;
+        instance.incoming_handler()?;
+        pool.release(Some(instance));
+    }
+    let addr = listener
+        .local_addr()
+        .context("failed to lookup listener address")?;
+    info!(%addr, concurrency = concurrency.get(), "serving `wasi:http/incoming-handler`");
+    loop {
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .context("failed to accept connection")?;
+        let pool = Arc::clone(&pool);
+        tokio::spawn(async move {
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(
+                    TokioIo::new(stream),
+                    service_fn(move |request| handle(Arc::clone(&pool), request)),
+                )
+                .await
+            {
+                warn!(?err, %peer, "failed to serve connection");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::time::Duration;
+
+    use std::io::{Read as _, Write as _};
+    use std::thread;
+
+    use crate::tests::component;
+
+    /// Handler responding with `200 OK` to all requests except `POST`, on which it traps
+    const HANDLER: &str = r#"(module
+        (import "wasi:http/types@0.2.1" "[constructor]fields" (func $fields (result i32)))
+        (import "wasi:http/types@0.2.1" "[constructor]outgoing-response"
+            (func $response (param i32) (result i32)))
+        (import "wasi:http/types@0.2.1" "[static]response-outparam.set"
+            (func $set (param i32 i32 i32 i32 i64 i32 i32 i32 i32)))
+        (import "wasi:http/types@0.2.1" "[method]incoming-request.method"
+            (func $method (param i32 i32)))
+        (import "wasi:http/types@0.2.1" "[resource-drop]incoming-request"
+            (func $drop-request (param i32)))
+        (memory (export "memory") 1)
+        (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+            i32.const 1024)
+        (func (export "wasi:http/incoming-handler@0.2.1#handle")
+            (param $request i32) (param $out i32)
+            (call $method (local.get $request) (i32.const 0))
+            (if (i32.eq (i32.load8_u (i32.const 0)) (i32.const 2)) (then unreachable))
+            (call $drop-request (local.get $request))
+            (call $set
+                (local.get $out)
+                (i32.const 0)
+                (call $response (call $fields))
+                (i32.const 0) (i64.const 0)
+                (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)))
+    )"#;
+
+    /// Sends a `method` request to `addr` and returns the response status line
+    fn send(addr: core::net::SocketAddr, method: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(addr).expect("failed to connect");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .expect("failed to set read timeout");
+        write!(
+            stream,
+            "{method} / HTTP/1.1\r\nhost: {addr}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+        )
+        .expect("failed to send request");
+        let mut resp = String::new();
+        stream
+            .read_to_string(&mut resp)
+            .expect("failed to read response");
+        resp.lines().next().unwrap_or_default().into()
+    }
+
+    #[test]
+    fn serve_replaces_failed_instance() {
+        let wasm = component(
+            "package wadge:test; world handler { export wasi:http/incoming-handler@0.2.1; }",
+            "handler",
+            HANDLER,
+        );
+        let pre = InstancePre::new(&wasmtime::Engine::default(), &wasm)
+            .expect("failed to compile component");
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build tokio runtime");
+        let listener = rt
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().expect("failed to get address");
+        thread::spawn(move || {
+            rt.block_on(serve(listener, pre, NonZeroUsize::MIN, Config::default))
+        });
+
+        assert_eq!(send(addr, "GET"), "HTTP/1.1 200 OK");
+        // the only instance traps
+        assert_eq!(send(addr, "POST"), "HTTP/1.1 500 Internal Server Error");
+        assert_eq!(send(addr, "GET"), "HTTP/1.1 200 OK");
+        assert_eq!(send(addr, "GET"), "HTTP/1.1 200 OK");
+    }
+}
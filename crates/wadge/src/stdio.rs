@@ -0,0 +1,360 @@
+//! In-memory guest standard I/O streams
+
+use core::future::poll_fn;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::ensure;
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use wasmtime_wasi::cli::{IsTerminal, StdinStream, StdoutStream};
+use wasmtime_wasi::p2::{InputStream, OutputStream, Pollable, StreamError, StreamResult};
+
+/// Maximum number of bytes accepted by a single write to a capture stream
+const WRITE_BUDGET: usize = 1 << 20;
+
+/// Guest input stream handling
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Input {
+    /// Empty input, reads return end-of-stream immediately
+    #[default]
+    Empty,
+    /// Read input from the host process
+    Inherit,
+    /// Read the specified bytes followed by end-of-stream
+    Bytes(Vec<u8>),
+    /// Read the specified bytes followed by bytes pushed by native code
+    /// via [`InputPipe`] until the pipe is closed
+    Pipe(Vec<u8>),
+}
+
+#[derive(Default)]
+struct InputState {
+    buf: VecDeque<u8>,
+    closed: bool,
+    /// Waker of the pending guest read, if any
+    waker: Option<Waker>,
+}
+
+/// In-memory buffer feeding a guest input stream.
+///
+/// Clones of [`InputPipe`] share the same underlying buffer.
+#[derive(Clone, Default)]
+pub struct InputPipe(Arc<Mutex<InputState>>);
+
+impl InputPipe {
+    pub(crate) fn new(buf: Vec<u8>, closed: bool) -> Self {
+        Self(Arc::new(Mutex::new(InputState {
+            buf: buf.into(),
+            closed,
+            waker: None,
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, InputState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Appends `buf` to the input, waking up any pending guest reads
+    pub fn push(&self, buf: &[u8]) -> anyhow::Result<()> {
+        let mut state = self.lock();
+        ensure!(!state.closed, "input stream is closed");
+        state.buf.extend(buf);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Closes the input, guest will observe end-of-stream once all buffered bytes are read
+    pub fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns `true` if the input is closed
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock();
+        if !state.buf.is_empty() || state.closed {
+            Poll::Ready(())
+        } else {
+            match &mut state.waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                waker => *waker = Some(cx.waker().clone()),
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl IsTerminal for InputPipe {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdinStream for InputPipe {
+    fn p2_stream(&self) -> Box<dyn InputStream> {
+        Box::new(self.clone())
+    }
+
+    fn async_stream(&self) -> Box<dyn AsyncRead + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for InputPipe {
+    async fn ready(&mut self) {
+        poll_fn(|cx| self.poll_ready(cx)).await;
+    }
+}
+
+impl InputStream for InputPipe {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        let mut state = self.lock();
+        if state.buf.is_empty() && state.closed {
+            return Err(StreamError::Closed);
+        }
+        let n = size.min(state.buf.len());
+        Ok(state.buf.drain(..n).collect())
+    }
+}
+
+impl AsyncRead for InputPipe {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.poll_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let mut state = self.lock();
+        let n = buf.remaining().min(state.buf.len());
+        let (a, b) = state.buf.as_slices();
+        if n <= a.len() {
+            buf.put_slice(&a[..n]);
+        } else {
+            buf.put_slice(a);
+            buf.put_slice(&b[..n - a.len()]);
+        }
+        state.buf.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Guest output stream handling
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Output {
+    /// Forward output to the host process
+    #[default]
+    Inherit,
+    /// Capture output in memory, see [`OutputCapture`]
+    Capture,
+    /// Discard output
+    Discard,
+}
+
+/// In-memory buffer capturing a guest output stream.
+///
+/// Clones of [`OutputCapture`] share the same underlying buffer.
+#[derive(Clone, Default)]
+pub struct OutputCapture(Arc<Mutex<Vec<u8>>>);
+
+impl OutputCapture {
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the number of captured bytes
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no bytes are captured
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Takes all captured bytes, leaving the buffer empty
+    #[must_use]
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Takes at most `n` captured bytes from the front of the buffer
+    #[must_use]
+    pub fn take_n(&self, n: usize) -> Vec<u8> {
+        let mut buf = self.lock();
+        let n = n.min(buf.len());
+        buf.drain(..n).collect()
+    }
+}
+
+impl IsTerminal for OutputCapture {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for OutputCapture {
+    fn p2_stream(&self) -> Box<dyn OutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for OutputCapture {
+    async fn ready(&mut self) {}
+}
+
+impl OutputStream for OutputCapture {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        self.lock().extend_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(WRITE_BUDGET)
+    }
+}
+
+impl AsyncWrite for OutputCapture {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.lock().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl CountingWaker {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn pipe() {
+        let pipe = InputPipe::new(b"foo".to_vec(), false);
+        let mut stream = pipe.clone();
+        assert_eq!(stream.read(2).unwrap(), "fo");
+        assert_eq!(stream.read(2).unwrap(), "o");
+        assert_eq!(stream.read(2).unwrap(), "", "open pipe must not report EOF");
+
+        pipe.push(b"bar").unwrap();
+        pipe.close();
+        assert!(pipe.is_closed());
+        assert!(pipe.push(b"baz").is_err());
+        assert_eq!(stream.read(8).unwrap(), "bar", "buffered bytes precede EOF");
+        assert!(matches!(stream.read(8), Err(StreamError::Closed)));
+    }
+
+    #[test]
+    fn pipe_wakers() {
+        let pipe = InputPipe::new(Vec::default(), false);
+        let wakes = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        for _ in 0..3 {
+            assert!(pipe.poll_ready(&mut cx).is_pending());
+        }
+        assert!(pipe.lock().waker.is_some());
+        pipe.push(b"foo").unwrap();
+        assert_eq!(wakes.count(), 1);
+        assert!(pipe.lock().waker.is_none());
+        assert!(pipe.poll_ready(&mut cx).is_ready());
+
+        // a different waker replaces the stored one
+        let mut stream = pipe.clone();
+        assert_eq!(stream.read(8).unwrap(), "foo");
+        assert!(pipe.poll_ready(&mut cx).is_pending());
+        let other = Arc::new(CountingWaker::default());
+        let other_waker = Waker::from(Arc::clone(&other));
+        assert!(pipe
+            .poll_ready(&mut Context::from_waker(&other_waker))
+            .is_pending());
+        pipe.close();
+        assert_eq!((wakes.count(), other.count()), (1, 1));
+        assert!(pipe.poll_ready(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn bytes() {
+        let mut stream = InputPipe::new(b"foo".to_vec(), true);
+        assert_eq!(stream.read(8).unwrap(), "foo");
+        assert!(matches!(stream.read(8), Err(StreamError::Closed)));
+    }
+
+    #[test]
+    fn capture() {
+        let capture = OutputCapture::default();
+        assert!(capture.is_empty());
+        let mut stream = capture.clone();
+        stream.write(Bytes::from_static(b"foo")).unwrap();
+        stream.write(Bytes::from_static(b"bar")).unwrap();
+        assert_eq!(capture.len(), 6);
+        assert_eq!(capture.take_n(2), b"fo");
+        assert_eq!(capture.take_n(0), b"");
+        assert_eq!(capture.take_n(8), b"obar");
+        assert!(capture.take_n(8).is_empty());
+
+        stream.write(Bytes::from_static(b"baz")).unwrap();
+        assert_eq!(capture.take(), b"baz");
+        assert!(capture.is_empty());
+    }
+}
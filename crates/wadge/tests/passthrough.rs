@@ -0,0 +1,553 @@
+//! Tests against the passthrough component at `lib/passthrough.wasm`, which is built by
+//! `go generate` or `wasm-tools component new` from the `wadge-passthrough` crate.
+//! A missing component fails the tests, unless `WADGE_SKIP_PASSTHROUGH_TESTS` is set,
+//! in which case they are skipped.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use wasmtime::component::{ResourceAny, Val};
+
+const HTTP_TYPES: &str = "wasi:http/types@0.2.1";
+const BLOBSTORE: &str = "wasi:blobstore/blobstore@0.2.0-draft";
+const BLOBSTORE_CONTAINER: &str = "wasi:blobstore/container@0.2.0-draft";
+const BLOBSTORE_TYPES: &str = "wasi:blobstore/types@0.2.0-draft";
+const MESSAGING_TYPES: &str = "wasi:messaging/types@0.2.0-draft";
+
+fn instantiate(config: wadge::Config) -> Option<wadge::Instance> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../lib/passthrough.wasm");
+    if env::var_os("WADGE_SKIP_PASSTHROUGH_TESTS").is_some_and(|v| !v.is_empty()) {
+        eprintln!("`WADGE_SKIP_PASSTHROUGH_TESTS` is set, skipping test");
+        return None;
+    }
+    let wasm = fs::read(&path).unwrap_or_else(|err| {
+        panic!(
+            "failed to read `{}`, build it with `go generate` \
+             or set `WADGE_SKIP_PASSTHROUGH_TESTS` to skip: {err}",
+            path.display()
+        )
+    });
+    let instance = wadge::instantiate(wadge::Config {
+        wasm: &wasm,
+        ..config
+    })
+    .expect("failed to instantiate passthrough");
+    Some(instance)
+}
+
+fn call(
+    instance: &mut wadge::Instance,
+    interface: &str,
+    name: &str,
+    params: &[Val],
+) -> anyhow::Result<Option<Val>> {
+    let mut func = instance.func(interface, name)?;
+    let mut results = vec![Val::Bool(false); func.results().len()];
+    func.call(params, &mut results)?;
+    Ok(results.pop())
+}
+
+fn resource(val: Option<Val>) -> ResourceAny {
+    match val {
+        Some(Val::Resource(res)) => res,
+        val => panic!("expected a resource, got {val:?}"),
+    }
+}
+
+/// Unwraps the `ok` payload of a `result`
+fn ok(val: Option<Val>) -> Option<Val> {
+    match val {
+        Some(Val::Result(Ok(val))) => val.map(|val| *val),
+        val => panic!("expected `ok`, got {val:?}"),
+    }
+}
+
+fn bytes(buf: &[u8]) -> Val {
+    Val::List(buf.iter().copied().map(Val::U8).collect())
+}
+
+/// Creates a new `response-outparam` via `wasiext:http/ext` returning it along with
+/// the associated `future-incoming-response`
+fn new_response_outparam(instance: &mut wadge::Instance) -> (ResourceAny, ResourceAny) {
+    match call(
+        instance,
+        "wasiext:http/ext@0.1.0",
+        "new-response-outparam",
+        &[],
+    )
+    .expect("failed to call `new-response-outparam`")
+    {
+        Some(Val::Tuple(vals)) => match <[Val; 2]>::try_from(vals) {
+            Ok([Val::Resource(out), Val::Resource(fut)]) => (out, fut),
+            vals => panic!("unexpected `new-response-outparam` result {vals:?}"),
+        },
+        val => panic!("unexpected `new-response-outparam` result {val:?}"),
+    }
+}
+
+/// Blocks on `fut` and returns the `incoming-response` result
+fn await_response(instance: &mut wadge::Instance, fut: ResourceAny) -> Result<ResourceAny, Val> {
+    let pollable = resource(
+        call(
+            instance,
+            HTTP_TYPES,
+            "[method]future-incoming-response.subscribe",
+            &[Val::Resource(fut)],
+        )
+        .expect("failed to subscribe"),
+    );
+    call(
+        instance,
+        "wasi:io/poll@0.2.1",
+        "[method]pollable.block",
+        &[Val::Resource(pollable)],
+    )
+    .expect("failed to block on pollable");
+    let res = call(
+        instance,
+        HTTP_TYPES,
+        "[method]future-incoming-response.get",
+        &[Val::Resource(fut)],
+    )
+    .expect("failed to get response");
+    let Some(Val::Option(Some(res))) = res else {
+        panic!("response not ready: {res:?}");
+    };
+    let Val::Result(Ok(Some(res))) = *res else {
+        panic!("response already taken: {res:?}");
+    };
+    match *res {
+        Val::Result(Ok(Some(resp))) => Ok(resource(Some(*resp))),
+        Val::Result(Err(Some(err))) => Err(*err),
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+#[test]
+fn response_outparam_set() -> anyhow::Result<()> {
+    let Some(mut instance) = instantiate(wadge::Config::default()) else {
+        return Ok(());
+    };
+
+    let (out, fut) = new_response_outparam(&mut instance);
+    let fields = resource(call(&mut instance, HTTP_TYPES, "[constructor]fields", &[])?);
+    let resp = resource(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[constructor]outgoing-response",
+        &[Val::Resource(fields)],
+    )?);
+    call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]outgoing-response.set-status-code",
+        &[Val::Resource(resp), Val::U16(418)],
+    )?;
+    instance
+        .call_http_response_outparam_set(out, Ok(resp))
+        .context("failed to set response")?;
+    let resp = await_response(&mut instance, fut).expect("response is an error");
+    let status = call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]incoming-response.status",
+        &[Val::Resource(resp)],
+    )?;
+    assert_eq!(status, Some(Val::U16(418)));
+
+    let (out, fut) = new_response_outparam(&mut instance);
+    let denied = Val::Variant("HTTP-request-denied".into(), None);
+    instance
+        .call_http_response_outparam_set(out, Err(denied.clone()))
+        .context("failed to set error")?;
+    let err = await_response(&mut instance, fut).expect_err("response is not an error");
+    assert_eq!(err, denied);
+
+    let (out, _) = new_response_outparam(&mut instance);
+    let err = instance
+        .call_http_response_outparam_set(out, Err(Val::U32(0)))
+        .expect_err("type mismatch not detected");
+    assert!(
+        format!("{err:#}").contains("response-outparam.set"),
+        "unexpected error: {err:#}"
+    );
+    Ok(())
+}
+
+#[test]
+fn new_incoming_response() -> anyhow::Result<()> {
+    let Some(mut instance) = instantiate(wadge::Config::default()) else {
+        return Ok(());
+    };
+
+    let fields = resource(ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[static]fields.from-list",
+        &[Val::List(vec![Val::Tuple(vec![
+            Val::String("x-foo".into()),
+            bytes(b"bar"),
+        ])])],
+    )?));
+    let resp = resource(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[constructor]outgoing-response",
+        &[Val::Resource(fields)],
+    )?);
+    ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]outgoing-response.set-status-code",
+        &[Val::Resource(resp), Val::U16(201)],
+    )?);
+    let body = resource(ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]outgoing-response.body",
+        &[Val::Resource(resp)],
+    )?));
+    let fut = resource(call(
+        &mut instance,
+        "wasiext:http/ext@0.1.0",
+        "new-incoming-response",
+        &[Val::Resource(resp)],
+    )?);
+    let stream = resource(ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]outgoing-body.write",
+        &[Val::Resource(body)],
+    )?));
+    ok(call(
+        &mut instance,
+        "wasi:io/streams@0.2.1",
+        "[method]output-stream.blocking-write-and-flush",
+        &[Val::Resource(stream), bytes(b"hello")],
+    )?);
+    stream.resource_drop(instance.store())?;
+    ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[static]outgoing-body.finish",
+        &[Val::Resource(body), Val::Option(None)],
+    )?);
+
+    let resp = await_response(&mut instance, fut).expect("response is an error");
+    let status = call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]incoming-response.status",
+        &[Val::Resource(resp)],
+    )?;
+    assert_eq!(status, Some(Val::U16(201)));
+    let headers = resource(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]incoming-response.headers",
+        &[Val::Resource(resp)],
+    )?);
+    let values = call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]fields.get",
+        &[Val::Resource(headers), Val::String("x-foo".into())],
+    )?;
+    assert_eq!(values, Some(Val::List(vec![bytes(b"bar")])));
+
+    let body = resource(ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]incoming-response.consume",
+        &[Val::Resource(resp)],
+    )?));
+    let stream = resource(ok(call(
+        &mut instance,
+        HTTP_TYPES,
+        "[method]incoming-body.stream",
+        &[Val::Resource(body)],
+    )?));
+    let mut buf = vec![];
+    loop {
+        match call(
+            &mut instance,
+            "wasi:io/streams@0.2.1",
+            "[method]input-stream.blocking-read",
+            &[Val::Resource(stream), Val::U64(1024)],
+        )? {
+            Some(Val::Result(Ok(Some(chunk)))) => {
+                let Val::List(chunk) = *chunk else {
+                    panic!("unexpected chunk {chunk:?}");
+                };
+                buf.extend(chunk.into_iter().map(|b| match b {
+                    Val::U8(b) => b,
+                    b => panic!("unexpected byte {b:?}"),
+                }));
+            }
+            Some(Val::Result(Err(Some(err)))) => {
+                assert_eq!(*err, Val::Variant("closed".into(), None));
+                break;
+            }
+            res => panic!("unexpected read result {res:?}"),
+        }
+    }
+    assert_eq!(buf, b"hello");
+    Ok(())
+}
+
+#[test]
+fn blobstore_write_get_data() -> anyhow::Result<()> {
+    let blobstore = wadge::BlobStore::new();
+    blobstore.create_container("existing")?;
+    blobstore.write("existing", "foo", "bar")?;
+    let Some(mut instance) = instantiate(wadge::Config {
+        blobstore: blobstore.clone(),
+        ..wadge::Config::default()
+    }) else {
+        return Ok(());
+    };
+
+    let container = resource(ok(call(
+        &mut instance,
+        BLOBSTORE,
+        "create-container",
+        &[Val::String("test".into())],
+    )?));
+    let value = resource(call(
+        &mut instance,
+        BLOBSTORE_TYPES,
+        "[static]outgoing-value.new-outgoing-value",
+        &[],
+    )?);
+    let stream = resource(ok(call(
+        &mut instance,
+        BLOBSTORE_TYPES,
+        "[method]outgoing-value.outgoing-value-write-body",
+        &[Val::Resource(value)],
+    )?));
+    ok(call(
+        &mut instance,
+        "wasi:io/streams@0.2.1",
+        "[method]output-stream.blocking-write-and-flush",
+        &[Val::Resource(stream), bytes(b"hello")],
+    )?);
+    stream.resource_drop(instance.store())?;
+    ok(call(
+        &mut instance,
+        BLOBSTORE_CONTAINER,
+        "[method]container.write-data",
+        &[
+            Val::Resource(container),
+            Val::String("greeting".into()),
+            Val::Resource(value),
+        ],
+    )?);
+    ok(call(
+        &mut instance,
+        BLOBSTORE_TYPES,
+        "[static]outgoing-value.finish",
+        &[Val::Resource(value)],
+    )?);
+    assert_eq!(blobstore.read("test", "greeting")?, b"hello");
+
+    // `start` and `end` are inclusive
+    let data = resource(ok(call(
+        &mut instance,
+        BLOBSTORE_CONTAINER,
+        "[method]container.get-data",
+        &[
+            Val::Resource(container),
+            Val::String("greeting".into()),
+            Val::U64(1),
+            Val::U64(3),
+        ],
+    )?));
+    let data = ok(call(
+        &mut instance,
+        BLOBSTORE_TYPES,
+        "[static]incoming-value.incoming-value-consume-sync",
+        &[Val::Resource(data)],
+    )?);
+    assert_eq!(data, Some(bytes(b"ell")));
+
+    let container = resource(ok(call(
+        &mut instance,
+        BLOBSTORE,
+        "get-container",
+        &[Val::String("existing".into())],
+    )?));
+    let data = resource(ok(call(
+        &mut instance,
+        BLOBSTORE_CONTAINER,
+        "[method]container.get-data",
+        &[
+            Val::Resource(container),
+            Val::String("foo".into()),
+            Val::U64(0),
+            Val::U64(u64::MAX),
+        ],
+    )?));
+    let data = ok(call(
+        &mut instance,
+        BLOBSTORE_TYPES,
+        "[static]incoming-value.incoming-value-consume-sync",
+        &[Val::Resource(data)],
+    )?);
+    assert_eq!(data, Some(bytes(b"bar")));
+
+    let err = call(
+        &mut instance,
+        BLOBSTORE_CONTAINER,
+        "[method]container.get-data",
+        &[
+            Val::Resource(container),
+            Val::String("missing".into()),
+            Val::U64(0),
+            Val::U64(0),
+        ],
+    )?;
+    let Some(Val::Result(Err(Some(err)))) = err else {
+        panic!("unexpected result {err:?}");
+    };
+    assert!(
+        matches!(*err, Val::String(ref err) if err.contains("missing")),
+        "unexpected error {err:?}"
+    );
+    Ok(())
+}
+
+/// Constructs a new guest `message` carrying `data`
+fn new_message(instance: &mut wadge::Instance, data: &[u8]) -> ResourceAny {
+    resource(
+        call(
+            instance,
+            MESSAGING_TYPES,
+            "[constructor]message",
+            &[bytes(data)],
+        )
+        .expect("failed to construct message"),
+    )
+}
+
+#[test]
+fn messaging_send_request() -> anyhow::Result<()> {
+    let broker = wadge::MessageBroker::new();
+    broker.set_reply_handler(
+        "rpc",
+        Arc::new(|request| {
+            vec![
+                wadge::Message::new("reply", request.data.clone()),
+                wadge::Message::new("reply", "done"),
+            ]
+        }),
+    );
+    let Some(mut instance) = instantiate(wadge::Config {
+        messaging: broker.clone(),
+        ..wadge::Config::default()
+    }) else {
+        return Ok(());
+    };
+
+    let client = resource(ok(call(
+        &mut instance,
+        MESSAGING_TYPES,
+        "[static]client.connect",
+        &[Val::String("test".into())],
+    )?));
+    let message = new_message(&mut instance, b"hello");
+    call(
+        &mut instance,
+        MESSAGING_TYPES,
+        "[method]message.set-content-type",
+        &[Val::Resource(message), Val::String("text/plain".into())],
+    )?;
+    call(
+        &mut instance,
+        MESSAGING_TYPES,
+        "[method]message.add-metadata",
+        &[
+            Val::Resource(message),
+            Val::String("foo".into()),
+            Val::String("bar".into()),
+        ],
+    )?;
+    ok(call(
+        &mut instance,
+        "wasi:messaging/producer@0.2.0-draft",
+        "send",
+        &[
+            Val::Resource(client),
+            Val::String("events".into()),
+            Val::Resource(message),
+        ],
+    )?);
+    assert_eq!(
+        broker.take_published(),
+        [wadge::Message {
+            content_type: Some("text/plain".into()),
+            metadata: vec![("foo".into(), "bar".into())],
+            ..wadge::Message::new("events", "hello")
+        }]
+    );
+
+    let request = new_message(&mut instance, b"ping");
+    let replies = ok(call(
+        &mut instance,
+        "wasi:messaging/request-reply@0.2.0-draft",
+        "request",
+        &[
+            Val::Resource(client),
+            Val::String("rpc".into()),
+            Val::Resource(request),
+            Val::Option(None),
+        ],
+    )?);
+    let Some(Val::List(replies)) = replies else {
+        panic!("unexpected replies {replies:?}");
+    };
+    let mut data = vec![];
+    for reply in replies {
+        let reply = resource(Some(reply));
+        let topic = call(
+            &mut instance,
+            MESSAGING_TYPES,
+            "[method]message.topic",
+            &[Val::Resource(reply)],
+        )?;
+        assert_eq!(
+            topic,
+            Some(Val::Option(Some(Box::new(Val::String("reply".into())))))
+        );
+        data.push(call(
+            &mut instance,
+            MESSAGING_TYPES,
+            "[method]message.data",
+            &[Val::Resource(reply)],
+        )?);
+    }
+    assert_eq!(data, [Some(bytes(b"ping")), Some(bytes(b"done"))]);
+    assert_eq!(broker.published(), [wadge::Message::new("rpc", "ping")]);
+
+    let err = call(
+        &mut instance,
+        "wasi:messaging/request-reply@0.2.0-draft",
+        "request",
+        &[
+            Val::Resource(client),
+            Val::String("unhandled".into()),
+            Val::Resource(request),
+            Val::Option(None),
+        ],
+    )?;
+    let Some(Val::Result(Err(Some(err)))) = err else {
+        panic!("unexpected result {err:?}");
+    };
+    assert!(
+        matches!(*err, Val::Variant(ref case, _) if case == "other"),
+        "unexpected error {err:?}"
+    );
+    Ok(())
+}
@@ -110,6 +110,8 @@
               export GOMODCACHE=$TMPDIR/gomod
               export GOPATH=$TMPDIR/go
               export HOME=$TMPDIR/home
+              # `lib/passthrough.wasm` is not built for checks
+              export WADGE_SKIP_PASSTHROUGH_TESTS=1
             ''
             + preCheck;
 
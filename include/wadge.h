@@ -3,15 +3,605 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+/**
+ * Outcome of an `instance_call`
+ */
+enum CallStatus {
+  /**
+   * The call returned successfully
+   */
+  CallStatus_Ok,
+  /**
+   * The call failed, the error can be retrieved via `error_take`
+   */
+  CallStatus_Error,
+  /**
+   * The guest exited via `wasi:cli/exit` during the call
+   */
+  CallStatus_Exit,
+};
+typedef uint32_t CallStatus;
+
+/**
+ * Outgoing `wasi:http` cassette mode
+ */
+enum CassetteMode {
+  /**
+   * Send requests to their destinations and write all exchanges to `cassette`
+   * on `instance_flush` and once the instance is freed
+   */
+  CassetteMode_Record,
+  /**
+   * Serve requests from `cassette`, unmatched requests fail
+   */
+  CassetteMode_Replay,
+};
+typedef uint32_t CassetteMode;
+
+/**
+ * Guest clock handling
+ */
+enum Clock {
+  /**
+   * Use host clocks
+   */
+  Clock_Host,
+  /**
+   * Use a virtual clock starting at `clock_wall_ns`, controlled via `instance_clock_*`
+   */
+  Clock_Virtual,
+};
+typedef uint32_t Clock;
+
+/**
+ * Action taken on an outgoing guest `wasi:http` request
+ */
+enum HttpAction {
+  /**
+   * Send the request to its destination
+   */
+  HttpAction_PassThrough,
+  /**
+   * Respond to the guest with the response populated via `http_response_*` functions
+   */
+  HttpAction_Respond,
+  /**
+   * Fail the request with the error code set via `http_response_set_error`
+   */
+  HttpAction_Fail,
+};
+typedef uint32_t HttpAction;
+
+/**
+ * `wasi:http` error code
+ */
+enum HttpErrorCode {
+  HttpErrorCode_InternalError,
+  HttpErrorCode_DnsTimeout,
+  HttpErrorCode_DestinationNotFound,
+  HttpErrorCode_DestinationUnavailable,
+  HttpErrorCode_DestinationIpProhibited,
+  HttpErrorCode_DestinationIpUnroutable,
+  HttpErrorCode_ConnectionRefused,
+  HttpErrorCode_ConnectionTerminated,
+  HttpErrorCode_ConnectionTimeout,
+  HttpErrorCode_ConnectionReadTimeout,
+  HttpErrorCode_ConnectionWriteTimeout,
+  HttpErrorCode_ConnectionLimitReached,
+  HttpErrorCode_TlsProtocolError,
+  HttpErrorCode_TlsCertificateError,
+  HttpErrorCode_HttpRequestDenied,
+  HttpErrorCode_HttpRequestLengthRequired,
+  HttpErrorCode_HttpRequestMethodInvalid,
+  HttpErrorCode_HttpRequestUriInvalid,
+  HttpErrorCode_HttpRequestUriTooLong,
+  HttpErrorCode_HttpResponseIncomplete,
+  HttpErrorCode_HttpResponseTimeout,
+  HttpErrorCode_HttpUpgradeFailed,
+  HttpErrorCode_HttpProtocolError,
+  HttpErrorCode_LoopDetected,
+  HttpErrorCode_ConfigurationError,
+};
+typedef uint32_t HttpErrorCode;
+
+/**
+ * Outgoing `wasi:http` policy
+ */
+enum HttpPolicy {
+  /**
+   * Allow all requests
+   */
+  HttpPolicy_AllowAll,
+  /**
+   * Deny all requests
+   */
+  HttpPolicy_Offline,
+  /**
+   * Allow only requests matching at least one of `http_allow` authorities or URL prefixes
+   */
+  HttpPolicy_Allow,
+};
+typedef uint32_t HttpPolicy;
+
+enum InheritEnv {
+  InheritEnv_All,
+  InheritEnv_None,
+  InheritEnv_Allow,
+};
+typedef uint32_t InheritEnv;
+
+/**
+ * Guest input stream handling
+ */
+enum Input {
+  /**
+   * Empty input
+   */
+  Input_Empty,
+  /**
+   * Read input from the host process
+   */
+  Input_Inherit,
+  /**
+   * Read `stdin_bytes` followed by end-of-stream
+   */
+  Input_Bytes,
+  /**
+   * Read `stdin_bytes` followed by bytes pushed via `instance_stdin_push`
+   * until `instance_stdin_close` is called
+   */
+  Input_Pipe,
+};
+typedef uint32_t Input;
+
+/**
+ * Guest `wasi:keyvalue` store backend
+ */
+enum KeyValueBackend {
+  /**
+   * Keep buckets in memory
+   */
+  KeyValueBackend_Memory,
+  /**
+   * Persist each bucket in a separate JSON file within `keyvalue_path` directory
+   */
+  KeyValueBackend_Dir,
+  /**
+   * Persist all buckets in a single JSON file at `keyvalue_path`
+   */
+  KeyValueBackend_File,
+};
+typedef uint32_t KeyValueBackend;
+
+/**
+ * Guest `wasi:sockets` network policy
+ */
+enum NetworkPolicy {
+  /**
+   * Allow all socket addresses
+   */
+  NetworkPolicy_Inherit,
+  /**
+   * Allow only loopback socket addresses
+   */
+  NetworkPolicy_Loopback,
+  /**
+   * Deny all socket addresses
+   */
+  NetworkPolicy_DenyAll,
+  /**
+   * Allow only socket addresses matching at least one of `network_allow` rules
+   */
+  NetworkPolicy_Allow,
+};
+typedef uint32_t NetworkPolicy;
+
+/**
+ * Guest output stream handling
+ */
+enum Output {
+  Output_Inherit,
+  Output_Capture,
+  Output_Discard,
+};
+typedef uint32_t Output;
+
 typedef struct List_u8 {
   const uint8_t *ptr;
   uintptr_t len;
 } List_u8;
 
+typedef struct KeyValue {
+  struct List_u8 key;
+  struct List_u8 value;
+} KeyValue;
+
+typedef struct List_KeyValue {
+  const struct KeyValue *ptr;
+  uintptr_t len;
+} List_KeyValue;
+
+typedef struct List_List_u8 {
+  const struct List_u8 *ptr;
+  uintptr_t len;
+} List_List_u8;
+
+/**
+ * Host directory exposed to the guest via `wasi:filesystem/preopens`
+ */
+typedef struct Preopen {
+  struct List_u8 host_path;
+  struct List_u8 guest_path;
+  /**
+   * Directory permission bits: `1` - read, `2` - mutate
+   */
+  uintptr_t dir_perms;
+  /**
+   * File permission bits: `1` - read, `2` - write
+   */
+  uintptr_t file_perms;
+} Preopen;
+
+typedef struct List_Preopen {
+  const struct Preopen *ptr;
+  uintptr_t len;
+} List_Preopen;
+
+/**
+ * File contained in an in-memory filesystem
+ */
+typedef struct MemoryFile {
+  struct List_u8 path;
+  struct List_u8 contents;
+} MemoryFile;
+
+typedef struct List_MemoryFile {
+  const struct MemoryFile *ptr;
+  uintptr_t len;
+} List_MemoryFile;
+
+/**
+ * In-memory filesystem exposed to the guest via `wasi:filesystem/preopens`
+ */
+typedef struct MemoryPreopen {
+  struct List_u8 guest_path;
+  /**
+   * Files to seed the filesystem with
+   */
+  struct List_MemoryFile files;
+  /**
+   * Tar archive to seed the filesystem with, null `ptr` means none
+   */
+  struct List_u8 tar;
+} MemoryPreopen;
+
+typedef struct List_MemoryPreopen {
+  const struct MemoryPreopen *ptr;
+  uintptr_t len;
+} List_MemoryPreopen;
+
+/**
+ * Socket addresses the guest is allowed to connect to or bind
+ */
+typedef struct NetworkRule {
+  /**
+   * Allowed IP address range in CIDR notation, e.g. `10.0.0.0/8`
+   */
+  struct List_u8 cidr;
+  /**
+   * Lowest allowed port
+   */
+  uint16_t port_min;
+  /**
+   * Highest allowed port
+   */
+  uint16_t port_max;
+} NetworkRule;
+
+typedef struct List_NetworkRule {
+  const struct NetworkRule *ptr;
+  uintptr_t len;
+} List_NetworkRule;
+
+/**
+ * Hostname override used by `wasi:sockets/ip-name-lookup` and outgoing `wasi:http` requests
+ */
+typedef struct HostOverride {
+  struct List_u8 name;
+  /**
+   * IP addresses the hostname resolves to
+   */
+  struct List_List_u8 addrs;
+} HostOverride;
+
+typedef struct List_HostOverride {
+  const struct HostOverride *ptr;
+  uintptr_t len;
+} List_HostOverride;
+
+/**
+ * Outgoing guest `wasi:http` request passed to `OutgoingHttpHandler`
+ */
+typedef struct HttpRequest {
+  struct List_u8 method;
+  struct List_u8 uri;
+  struct List_KeyValue headers;
+  struct List_u8 body;
+} HttpRequest;
+
+/**
+ * Native handler intercepting outgoing guest `wasi:http` requests.
+ * `data` is the `outgoing_http_handler_data` pointer, `request` is valid for
+ * the duration of the call and `response` must only be passed to `http_response_*` functions.
+ * The handler returns one of `HttpAction` and may be called concurrently from multiple threads.
+ */
+typedef uint32_t (*OutgoingHttpHandler)(void *data,
+                                        const struct HttpRequest *request,
+                                        void *response);
+
+/**
+ * Route dispatching outgoing `wasi:http` requests to an in-process component
+ */
+typedef struct HttpRoute {
+  /**
+   * Request authority, `host` or `host:port`
+   */
+  struct List_u8 authority;
+  /**
+   * Instance returned by `instance_new`, which handles the requests via its
+   * `wasi:http/incoming-handler` export. It may be freed independently.
+   */
+  void *instance;
+} HttpRoute;
+
+typedef struct List_HttpRoute {
+  const struct HttpRoute *ptr;
+  uintptr_t len;
+} List_HttpRoute;
+
+/**
+ * Entry to seed a guest `wasi:keyvalue` bucket with
+ */
+typedef struct KeyValueEntry {
+  struct List_u8 bucket;
+  struct List_u8 key;
+  struct List_u8 value;
+} KeyValueEntry;
+
+typedef struct List_KeyValueEntry {
+  const struct KeyValueEntry *ptr;
+  uintptr_t len;
+} List_KeyValueEntry;
+
+/**
+ * Object to seed a guest `wasi:blobstore` container with
+ */
+typedef struct BlobObject {
+  struct List_u8 name;
+  struct List_u8 data;
+} BlobObject;
+
+typedef struct List_BlobObject {
+  const struct BlobObject *ptr;
+  uintptr_t len;
+} List_BlobObject;
+
+/**
+ * Guest `wasi:blobstore` container
+ */
+typedef struct BlobContainer {
+  struct List_u8 name;
+  /**
+   * Host directory backing the container, null `ptr` means the container is kept in memory
+   */
+  struct List_u8 dir;
+  /**
+   * Objects to seed the container with
+   */
+  struct List_BlobObject objects;
+} BlobContainer;
+
+typedef struct List_BlobContainer {
+  const struct BlobContainer *ptr;
+  uintptr_t len;
+} List_BlobContainer;
+
+/**
+ * Guest `wasi:messaging` message, null `ptr` of `topic`, `reply_to` and `content_type`
+ * means the field is not set
+ */
+typedef struct Message {
+  struct List_u8 topic;
+  /**
+   * Topic replies to the message are published on
+   */
+  struct List_u8 reply_to;
+  struct List_u8 content_type;
+  struct List_u8 data;
+  struct List_KeyValue metadata;
+} Message;
+
+/**
+ * Native handler responding to guest `wasi:messaging` requests on `messaging_reply_topics`.
+ * `data` is the `messaging_reply_handler_data` pointer, `request` is valid for
+ * the duration of the call and `replies` must only be passed to `messaging_replies_push`.
+ * The handler may be called concurrently from multiple threads.
+ */
+typedef void (*MessagingReplyHandler)(void *data, const struct Message *request, void *replies);
+
 typedef struct Config {
   struct List_u8 wasm;
+  /**
+   * Environment variables exposed to the guest
+   */
+  struct List_KeyValue env;
+  /**
+   * Host environment variable inheritance policy, one of `InheritEnv`
+   */
+  uint32_t inherit_env;
+  /**
+   * Names of host environment variables to inherit if `inherit_env` is `Allow`
+   */
+  struct List_List_u8 inherit_env_allow;
+  /**
+   * Arguments exposed to the guest
+   */
+  struct List_List_u8 args;
+  /**
+   * Initial working directory exposed to the guest, null `ptr` means none
+   */
+  struct List_u8 cwd;
+  /**
+   * Host directories exposed to the guest
+   */
+  struct List_Preopen preopens;
+  /**
+   * In-memory filesystems exposed to the guest
+   */
+  struct List_MemoryPreopen memory_preopens;
+  /**
+   * Guest stdin handling, one of `Input`
+   */
+  uint32_t stdin;
+  /**
+   * Initial guest stdin contents
+   */
+  struct List_u8 stdin_bytes;
+  /**
+   * Guest stdout handling, one of `Output`
+   */
+  uint32_t stdout;
+  /**
+   * Guest stderr handling, one of `Output`
+   */
+  uint32_t stderr;
+  /**
+   * Guest clock handling, one of `Clock`
+   */
+  uint32_t clock;
+  /**
+   * Initial virtual wall clock time in nanoseconds since Unix epoch
+   */
+  uint64_t clock_wall_ns;
+  /**
+   * Whether guest waiting on `monotonic-clock` pollables advances the virtual clock
+   */
+  bool clock_auto_advance;
+  /**
+   * Whether guest `wasi:random` interfaces are seeded with `random_seed`
+   * and therefore deterministic
+   */
+  bool random_seeded;
+  /**
+   * Seed for guest `wasi:random` interfaces if `random_seeded` is set
+   */
+  uint64_t random_seed;
+  /**
+   * Guest `wasi:sockets` network policy, one of `NetworkPolicy`
+   */
+  uint32_t network;
+  /**
+   * Network rules used if `network` is `Allow`
+   */
+  struct List_NetworkRule network_allow;
+  /**
+   * Hostname overrides
+   */
+  struct List_HostOverride hosts;
+  /**
+   * Whether `wasi:sockets/ip-name-lookup` resolves hostnames not present
+   * in `hosts` using the system resolver, disabled by default
+   */
+  bool resolve_fallthrough;
+  /**
+   * Native handler intercepting outgoing guest `wasi:http` requests, null means none
+   */
+  OutgoingHttpHandler outgoing_http_handler;
+  /**
+   * Pointer passed to `outgoing_http_handler` on each call
+   */
+  void *outgoing_http_handler_data;
+  /**
+   * Path to outgoing `wasi:http` JSON cassette file, null `ptr` means none
+   */
+  struct List_u8 cassette;
+  /**
+   * Outgoing `wasi:http` cassette mode, one of `CassetteMode`
+   */
+  uint32_t cassette_mode;
+  /**
+   * Names of request headers, which must match in addition to method and URI
+   * for a request to be served from `cassette` in `CassetteMode_Replay`
+   */
+  struct List_List_u8 cassette_match_headers;
+  /**
+   * Routes dispatching outgoing `wasi:http` requests to in-process components
+   */
+  struct List_HttpRoute http_routes;
+  /**
+   * Timeout in nanoseconds between frames of HTTP bodies constructed via
+   * `wasiext:http/ext`, `0` means the default of 1s
+   */
+  uint64_t http_between_bytes_timeout_ns;
+  /**
+   * Maximum size of HTTP request fields constructed via `wasiext:http/ext`,
+   * `0` means the default of 2GiB
+   */
+  uintptr_t http_field_size_limit;
+  /**
+   * Authority of HTTP requests constructed via `wasiext:http/ext`, which
+   * do not specify one, null `ptr` means the default of `wadge`
+   */
+  struct List_u8 http_default_authority;
+  /**
+   * Policy applied to outgoing `wasi:http` requests leaving the process, one of `HttpPolicy`
+   */
+  uint32_t http_policy;
+  /**
+   * Authorities (`host` or `host:port`) or URL prefixes used if `http_policy` is `Allow`.
+   * URL prefixes match scheme, host and port exactly and the path on `/` boundaries
+   */
+  struct List_List_u8 http_allow;
+  /**
+   * Entries to seed guest `wasi:keyvalue` buckets with
+   */
+  struct List_KeyValueEntry keyvalue;
+  /**
+   * Guest `wasi:keyvalue` store backend, one of `KeyValueBackend`
+   */
+  uint32_t keyvalue_backend;
+  /**
+   * Path used by `keyvalue_backend`
+   */
+  struct List_u8 keyvalue_path;
+  /**
+   * Configuration values exposed to the guest via `wasi:config/store`
+   */
+  struct List_KeyValue runtime_config;
+  /**
+   * Containers of guest `wasi:blobstore`
+   */
+  struct List_BlobContainer blobstore;
+  /**
+   * Topics of guest `wasi:messaging` requests handled by `messaging_reply_handler`
+   */
+  struct List_List_u8 messaging_reply_topics;
+  /**
+   * Native handler responding to guest `wasi:messaging` requests
+   */
+  MessagingReplyHandler messaging_reply_handler;
+  /**
+   * Pointer passed to `messaging_reply_handler` on each call
+   */
+  void *messaging_reply_handler_data;
 } Config;
 
+/**
+ * Callback receiving messages recorded by the guest `wasi:messaging` broker.
+ * `data` is the pointer passed along with the callback, `message` is valid for the duration of the call.
+ */
+typedef void (*MessageVisitor)(void *data, const struct Message *message);
+
 uintptr_t error_take(char *buf, uintptr_t len);
 
 uintptr_t error_len(void);
@@ -20,4 +610,237 @@ void *instance_new(struct Config config);
 
 void instance_free(void *instance);
 
-bool instance_call(void *instance_ptr, const char *instance, const char *name, void *const *args);
+/**
+ * Calls function `name` within `instance`.
+ * If the guest exits via `wasi:cli/exit` during the call, the exit code is written to
+ * `exit_code` and `CallStatus::Exit` is returned.
+ */
+CallStatus instance_call(void *instance_ptr,
+                         const char *instance,
+                         const char *name,
+                         void *const *args,
+                         int32_t *exit_code);
+
+/**
+ * Sets status code of the response populated by `OutgoingHttpHandler`, defaults to `200`
+ */
+bool http_response_set_status(void *response, uint16_t status);
+
+/**
+ * Appends a header to the response populated by `OutgoingHttpHandler`
+ */
+bool http_response_append_header(void *response, struct List_u8 name, struct List_u8 value);
+
+/**
+ * Sets body of the response populated by `OutgoingHttpHandler`, `body` is copied
+ */
+bool http_response_set_body(void *response, struct List_u8 body);
+
+/**
+ * Sets the error code, one of `HttpErrorCode`, returned to the guest if `OutgoingHttpHandler`
+ * returns `HttpAction_Fail`.
+ * `message` is only used for `HttpErrorCode_InternalError`, null `ptr` means none.
+ */
+bool http_response_set_error(void *response, uint32_t code, struct List_u8 message);
+
+/**
+ * Reads file at `path` from in-memory filesystem preopened at `guest_path`.
+ * At most `len` bytes are copied into `buf` and the file size is written to `n`.
+ */
+bool instance_fs_read(void *instance_ptr,
+                      const char *guest_path,
+                      const char *path,
+                      uint8_t *buf,
+                      uintptr_t len,
+                      uintptr_t *n);
+
+/**
+ * Writes `contents` to file at `path` in in-memory filesystem preopened at `guest_path`.
+ */
+bool instance_fs_write(void *instance_ptr,
+                       const char *guest_path,
+                       const char *path,
+                       struct List_u8 contents);
+
+/**
+ * Writes identifiers of all `wasi:keyvalue` buckets to `buf`, each terminated by a NUL byte.
+ * At most `len` bytes are copied into `buf` and the total size is written to `n`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_keyvalue_buckets(void *instance_ptr, uint8_t *buf, uintptr_t len, uintptr_t *n);
+
+/**
+ * Writes all keys in `wasi:keyvalue` bucket `bucket` to `buf`, each terminated by a NUL byte.
+ * At most `len` bytes are copied into `buf` and the total size is written to `n`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_keyvalue_keys(void *instance_ptr,
+                            const char *bucket,
+                            uint8_t *buf,
+                            uintptr_t len,
+                            uintptr_t *n);
+
+/**
+ * Reads value of `key` in `wasi:keyvalue` bucket `bucket`, fails if the key does not exist.
+ * At most `len` bytes are copied into `buf` and the value size is written to `n`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_keyvalue_get(void *instance_ptr,
+                           const char *bucket,
+                           const char *key,
+                           uint8_t *buf,
+                           uintptr_t len,
+                           uintptr_t *n);
+
+/**
+ * Sets value of `key` in `wasi:keyvalue` bucket `bucket` to `value`, `value` is copied.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_keyvalue_set(void *instance_ptr,
+                           const char *bucket,
+                           const char *key,
+                           struct List_u8 value);
+
+/**
+ * Deletes `key` from `wasi:keyvalue` bucket `bucket`, deleting a missing key is not an error.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_keyvalue_delete(void *instance_ptr, const char *bucket, const char *key);
+
+/**
+ * Writes names of all `wasi:blobstore` containers to `buf`, each terminated by a NUL byte.
+ * At most `len` bytes are copied into `buf` and the total size is written to `n`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_blobstore_containers(void *instance_ptr, uint8_t *buf, uintptr_t len, uintptr_t *n);
+
+/**
+ * Writes names of all objects in `wasi:blobstore` container `container` to `buf`,
+ * each terminated by a NUL byte, fails if the container does not exist.
+ * At most `len` bytes are copied into `buf` and the total size is written to `n`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_blobstore_objects(void *instance_ptr,
+                                const char *container,
+                                uint8_t *buf,
+                                uintptr_t len,
+                                uintptr_t *n);
+
+/**
+ * Reads object `name` in `wasi:blobstore` container `container`, fails if it does not exist.
+ * At most `len` bytes are copied into `buf` and the object size is written to `n`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_blobstore_read(void *instance_ptr,
+                             const char *container,
+                             const char *name,
+                             uint8_t *buf,
+                             uintptr_t len,
+                             uintptr_t *n);
+
+/**
+ * Creates or replaces object `name` in `wasi:blobstore` container `container`, `data` is copied.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_blobstore_write(void *instance_ptr,
+                              const char *container,
+                              const char *name,
+                              struct List_u8 data);
+
+/**
+ * Deletes object `name` from `wasi:blobstore` container `container`,
+ * deleting a missing object is not an error.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_blobstore_delete(void *instance_ptr, const char *container, const char *name);
+
+/**
+ * Calls `visitor` with `data` for each message recorded by the `wasi:messaging` broker
+ * in order of publication. This can be called concurrently with `instance_call`.
+ */
+bool instance_messaging_published(void *instance_ptr, MessageVisitor visitor, void *data);
+
+/**
+ * Calls `visitor` with `data` for each message recorded by the `wasi:messaging` broker
+ * in order of publication and removes them from the broker.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_messaging_take_published(void *instance_ptr, MessageVisitor visitor, void *data);
+
+/**
+ * Delivers `message` to the `wasi:messaging/incoming-handler` export of the component,
+ * `message` is copied
+ */
+bool instance_messaging_handle(void *instance_ptr, struct Message message);
+
+/**
+ * Appends a reply to the replies populated by `MessagingReplyHandler`, `reply` is copied
+ */
+bool messaging_replies_push(void *replies, struct Message reply);
+
+/**
+ * Appends `buf` to guest stdin, which must be configured with `Input_Pipe`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_stdin_push(void *instance, struct List_u8 buf);
+
+/**
+ * Closes guest stdin, guest will observe end-of-stream once all buffered bytes are read.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_stdin_close(void *instance);
+
+/**
+ * Writes outgoing `wasi:http` exchanges recorded since the last flush to the cassette, if any,
+ * and syncs the HAR log, if any, to disk.
+ * Recorded exchanges are also written once the instance is freed.
+ * This blocks until any in-progress `instance_call` returns.
+ */
+bool instance_flush(void *instance);
+
+/**
+ * Sets virtual wall clock time to `wall_ns` nanoseconds since Unix epoch,
+ * monotonic clock is not affected. Instance must be configured with `Clock_Virtual`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_clock_set(void *instance, uint64_t wall_ns);
+
+/**
+ * Advances virtual wall and monotonic clocks by `ns` nanoseconds, resolving guest pollables,
+ * which deadlines are reached. Instance must be configured with `Clock_Virtual`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_clock_advance(void *instance, uint64_t ns);
+
+/**
+ * Configures whether guest waiting on `monotonic-clock` pollables advances the virtual clock.
+ * Instance must be configured with `Clock_Virtual`.
+ * This can be called concurrently with `instance_call`.
+ */
+bool instance_clock_set_auto_advance(void *instance, bool auto_advance);
+
+/**
+ * Returns the number of bytes captured from guest stdout
+ */
+uintptr_t instance_stdout_len(void *instance);
+
+/**
+ * Copies at most `len` bytes captured from guest stdout into `buf`, removing them from the capture buffer.
+ * Returns the number of bytes copied.
+ */
+uintptr_t instance_stdout_take(void *instance,
+                               uint8_t *buf,
+                               uintptr_t len);
+
+/**
+ * Returns the number of bytes captured from guest stderr
+ */
+uintptr_t instance_stderr_len(void *instance);
+
+/**
+ * Copies at most `len` bytes captured from guest stderr into `buf`, removing them from the capture buffer.
+ * Returns the number of bytes copied.
+ */
+uintptr_t instance_stderr_take(void *instance,
+                               uint8_t *buf,
+                               uintptr_t len);
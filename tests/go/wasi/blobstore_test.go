@@ -0,0 +1,104 @@
+package wasi_test
+
+import (
+	"os"
+	"path/filepath"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+)
+
+// blobRead returns the contents of object `name` in `container` failing the test on error
+func blobRead(t *testing.T, instance *wadge.Instance, container, name string) []byte {
+	t.Helper()
+
+	data, err := instance.BlobRead(container, name)
+	if err != nil {
+		t.Fatalf("failed to read `%s` from container `%s`: %s", name, container, err)
+	}
+	return data
+}
+
+func TestBlobstore(t *testing.T) {
+	dir := t.TempDir()
+	if err := os.MkdirAll(filepath.Join(dir, "nested"), 0o755); err != nil {
+		t.Fatalf("failed to create directory: %s", err)
+	}
+	if err := os.WriteFile(filepath.Join(dir, "nested", "existing"), []byte("existing"), 0o644); err != nil {
+		t.Fatalf("failed to write file: %s", err)
+	}
+
+	runInstance(t, &wadge.Config{
+		BlobContainers: []wadge.BlobContainer{
+			{
+				Name: "memory",
+				Objects: map[string][]byte{
+					"foo":   []byte("bar"),
+					"a/b/c": []byte("abc"),
+				},
+			},
+			{
+				Name: "dir",
+				Dir:  dir,
+				Objects: map[string][]byte{
+					"seeded": []byte("seeded"),
+				},
+			},
+		},
+	}, func(instance *wadge.Instance) {
+		containers, err := instance.BlobContainers()
+		if err != nil {
+			t.Fatalf("failed to list containers: %s", err)
+		}
+		assert.Equal(t, []string{"dir", "memory"}, containers)
+
+		objects, err := instance.BlobObjects("memory")
+		if err != nil {
+			t.Fatalf("failed to list objects: %s", err)
+		}
+		assert.Equal(t, []string{"a/b/c", "foo"}, objects)
+		assert.Equal(t, []byte("bar"), blobRead(t, instance, "memory", "foo"))
+		assert.Equal(t, []byte("abc"), blobRead(t, instance, "memory", "a/b/c"))
+
+		_, err = instance.BlobRead("memory", "missing")
+		assert.Error(t, err)
+		_, err = instance.BlobObjects("missing")
+		assert.Error(t, err)
+
+		if err := instance.BlobWrite("memory", "foo", []byte("baz")); err != nil {
+			t.Fatalf("failed to write object: %s", err)
+		}
+		assert.Equal(t, []byte("baz"), blobRead(t, instance, "memory", "foo"))
+		if err := instance.BlobDelete("memory", "foo"); err != nil {
+			t.Fatalf("failed to delete object: %s", err)
+		}
+		objects, err = instance.BlobObjects("memory")
+		if err != nil {
+			t.Fatalf("failed to list objects: %s", err)
+		}
+		assert.Equal(t, []string{"a/b/c"}, objects)
+
+		objects, err = instance.BlobObjects("dir")
+		if err != nil {
+			t.Fatalf("failed to list objects: %s", err)
+		}
+		assert.Equal(t, []string{"nested/existing", "seeded"}, objects)
+		assert.Equal(t, []byte("existing"), blobRead(t, instance, "dir", "nested/existing"))
+
+		if err := instance.BlobWrite("dir", "new/object", []byte("value")); err != nil {
+			t.Fatalf("failed to write object: %s", err)
+		}
+		// directory-backed objects are stored as files
+		buf, err := os.ReadFile(filepath.Join(dir, "new", "object"))
+		if err != nil {
+			t.Fatalf("failed to read object file: %s", err)
+		}
+		assert.Equal(t, []byte("value"), buf)
+		assert.Equal(t, []byte("seeded"), blobRead(t, instance, "dir", "seeded"))
+
+		assert.Error(t, instance.BlobWrite("dir", "../escape", []byte("value")))
+		_, err = os.Stat(filepath.Join(filepath.Dir(dir), "escape"))
+		assert.True(t, os.IsNotExist(err))
+	})
+}
@@ -0,0 +1,55 @@
+package wasi_test
+
+import (
+	"net/http"
+	"os"
+	"path/filepath"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+)
+
+func TestCassette(t *testing.T) {
+	path := filepath.Join(t.TempDir(), "cassette.json")
+	srv := newServer(t, "recorded")
+	url := srv.URL + "/foo"
+
+	runInstance(t, &wadge.Config{
+		Cassette: &wadge.Cassette{
+			Path: path,
+			Mode: wadge.CassetteRecord,
+		},
+	}, func(instance *wadge.Instance) {
+		resp, code := sendRequest(t, url, nil)
+		if assert.Nil(t, code) {
+			assert.Equal(t, []byte("recorded"), resp.body)
+		}
+		_, err := os.Stat(path)
+		assert.ErrorIs(t, err, os.ErrNotExist)
+		assert.NoError(t, instance.Flush())
+	})
+	srv.Close()
+
+	runInstance(t, &wadge.Config{
+		Cassette: &wadge.Cassette{
+			Path:         path,
+			Mode:         wadge.CassetteReplay,
+			MatchHeaders: []string{"X-Id"},
+		},
+	}, func(*wadge.Instance) {
+		resp, code := sendRequest(t, url, nil)
+		if assert.Nil(t, code) {
+			assert.Equal(t, uint16(http.StatusOK), resp.status)
+			assert.Equal(t, "/foo", resp.header.Get("X-Path"))
+			assert.Equal(t, []byte("recorded"), resp.body)
+		}
+
+		_, code = sendRequest(t, url, http.Header{"X-Id": {"1"}})
+		if assert.NotNil(t, code) {
+			assert.NotNil(t, code.InternalError(), code.String())
+		}
+		_, code = sendRequest(t, srv.URL+"/bar", nil)
+		assert.NotNil(t, code)
+	})
+}
@@ -0,0 +1,64 @@
+package wasi_test
+
+import (
+	"testing"
+	"time"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	monotonicclock "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/clocks/monotonic-clock"
+	wallclock "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/clocks/wall-clock"
+)
+
+func TestVirtualClock(t *testing.T) {
+	start := time.Date(2024, time.February, 29, 12, 0, 0, 0, time.UTC)
+	runInstance(t, &wadge.Config{
+		Clock: &wadge.VirtualClock{
+			Start: start,
+		},
+	}, func(instance *wadge.Instance) {
+		now := wallclock.Now()
+		assert.Equal(t, uint64(start.Unix()), now.Seconds)
+		assert.Equal(t, uint32(0), now.Nanoseconds)
+		assert.Equal(t, monotonicclock.Instant(0), monotonicclock.Now())
+
+		pollable := monotonicclock.SubscribeDuration(monotonicclock.Duration(time.Second))
+		defer pollable.ResourceDrop()
+		assert.False(t, pollable.Ready())
+
+		assert.NoError(t, instance.AdvanceClock(500*time.Millisecond))
+		assert.False(t, pollable.Ready())
+		assert.NoError(t, instance.AdvanceClock(500*time.Millisecond))
+		assert.True(t, pollable.Ready())
+		assert.Equal(t, monotonicclock.Instant(time.Second), monotonicclock.Now())
+		assert.Equal(t, uint64(start.Unix()+1), wallclock.Now().Seconds)
+
+		assert.NoError(t, instance.SetClock(start))
+		assert.Equal(t, uint64(start.Unix()), wallclock.Now().Seconds)
+		assert.Equal(t, monotonicclock.Instant(time.Second), monotonicclock.Now())
+	})
+}
+
+func TestVirtualClockAutoAdvance(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		Clock: &wadge.VirtualClock{
+			Start:       time.Unix(0, 0),
+			AutoAdvance: true,
+		},
+	}, func(instance *wadge.Instance) {
+		pollable := monotonicclock.SubscribeDuration(monotonicclock.Duration(time.Minute))
+		defer pollable.ResourceDrop()
+		assert.Equal(t, monotonicclock.Instant(0), monotonicclock.Now())
+		assert.False(t, pollable.Ready())
+		assert.Equal(t, monotonicclock.Instant(0), monotonicclock.Now())
+
+		pollable.Block()
+		assert.Equal(t, monotonicclock.Instant(time.Minute), monotonicclock.Now())
+		assert.Equal(t, uint64(60), wallclock.Now().Seconds)
+
+		assert.NoError(t, instance.SetClockAutoAdvance(false))
+		pollable = monotonicclock.SubscribeDuration(monotonicclock.Duration(time.Minute))
+		defer pollable.ResourceDrop()
+		assert.False(t, pollable.Ready())
+	})
+}
@@ -0,0 +1,49 @@
+package wasi_test
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/cli/environment"
+)
+
+func TestEnvironment(t *testing.T) {
+	t.Setenv("WADGE_TEST_INHERITED", "inherited")
+	t.Setenv("WADGE_TEST_OVERRIDDEN", "host")
+	t.Setenv("WADGE_TEST_HIDDEN", "hidden")
+
+	runInstance(t, &wadge.Config{
+		Env: map[string]string{
+			"WADGE_TEST_OVERRIDDEN": "guest",
+			"WADGE_TEST_GUEST":      "guest",
+		},
+		InheritEnv:      wadge.InheritEnvAllow,
+		InheritEnvAllow: []string{"WADGE_TEST_INHERITED", "WADGE_TEST_OVERRIDDEN"},
+		Args:            []string{"main.wasm", "--foo", "bar"},
+		Cwd:             "/home/wadge",
+	}, func(*wadge.Instance) {
+		env := map[string]string{}
+		for _, kv := range environment.GetEnvironment().Slice() {
+			env[kv[0]] = kv[1]
+		}
+		assert.Equal(t, map[string]string{
+			"WADGE_TEST_GUEST":      "guest",
+			"WADGE_TEST_INHERITED":  "inherited",
+			"WADGE_TEST_OVERRIDDEN": "guest",
+		}, env)
+		assert.Equal(t, []string{"main.wasm", "--foo", "bar"}, environment.GetArguments().Slice())
+
+		cwd := environment.InitialCWD()
+		if assert.False(t, cwd.None()) {
+			assert.Equal(t, "/home/wadge", *cwd.Some())
+		}
+	})
+	runInstance(t, &wadge.Config{
+		InheritEnv: wadge.InheritEnvNone,
+	}, func(*wadge.Instance) {
+		assert.Empty(t, environment.GetEnvironment().Slice())
+		assert.Empty(t, environment.GetArguments().Slice())
+		assert.True(t, environment.InitialCWD().None())
+	})
+}
@@ -0,0 +1,47 @@
+package wasi_test
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/cli/environment"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/cli/exit"
+)
+
+// callErr returns the error reported by guest calls made in `f`
+func callErr(t *testing.T, f func()) error {
+	t.Helper()
+
+	instance, err := wadge.NewInstance(&wadge.Config{})
+	if err != nil {
+		t.Fatalf("failed to construct new instance: %s", err)
+	}
+	prevInstance := wadge.SetInstance(instance)
+	defer wadge.SetInstance(prevInstance)
+
+	var callErr error
+	prevHandler := wadge.SetErrorHandler(func(err error) {
+		callErr = err
+	})
+	defer wadge.SetErrorHandler(prevHandler)
+	f()
+	return callErr
+}
+
+func TestExit(t *testing.T) {
+	var exitErr *wadge.ExitError
+
+	err := callErr(t, func() { exit.Exit(false) })
+	if assert.ErrorAs(t, err, &exitErr) {
+		assert.Equal(t, int32(0), exitErr.Code)
+	}
+
+	err = callErr(t, func() { exit.Exit(true) })
+	if assert.ErrorAs(t, err, &exitErr) {
+		assert.Equal(t, int32(1), exitErr.Code)
+	}
+
+	err = callErr(t, func() { environment.GetArguments() })
+	assert.NoError(t, err)
+}
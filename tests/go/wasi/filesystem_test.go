@@ -0,0 +1,145 @@
+package wasi_test
+
+import (
+	"archive/tar"
+	"bytes"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.bytecodealliance.org/cm"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/filesystem/preopens"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/filesystem/types"
+)
+
+// preopen returns the guest descriptor of directory preopened at `path`
+func preopen(t *testing.T, path string) types.Descriptor {
+	t.Helper()
+
+	var dir *types.Descriptor
+	for _, d := range preopens.GetDirectories().Slice() {
+		if d.F1 == path {
+			dir = &d.F0
+		} else {
+			d.F0.ResourceDrop()
+		}
+	}
+	if dir == nil {
+		t.Fatalf("`%s` is not preopened", path)
+	}
+	return *dir
+}
+
+// readAt reads the whole file at `path` within `dir` using guest descriptors
+func readAt(t *testing.T, dir types.Descriptor, path string) ([]byte, types.ErrorCode, bool) {
+	t.Helper()
+
+	res := dir.OpenAt(0, path, 0, types.DescriptorFlagsRead)
+	if res.IsErr() {
+		return nil, *res.Err(), false
+	}
+	fd := *res.OK()
+	defer fd.ResourceDrop()
+
+	readRes := fd.Read(1<<20, 0)
+	if readRes.IsErr() {
+		return nil, *readRes.Err(), false
+	}
+	return readRes.OK().F0.Slice(), 0, true
+}
+
+// writeAt writes `buf` to the file at `path` within `dir` using guest descriptors
+func writeAt(dir types.Descriptor, path string, buf []byte) (types.ErrorCode, bool) {
+	res := dir.OpenAt(0, path, types.OpenFlagsCreate|types.OpenFlagsTruncate, types.DescriptorFlagsWrite)
+	if res.IsErr() {
+		return *res.Err(), false
+	}
+	fd := *res.OK()
+	defer fd.ResourceDrop()
+
+	writeRes := fd.Write(cm.ToList(buf), 0)
+	if writeRes.IsErr() {
+		return *writeRes.Err(), false
+	}
+	return 0, true
+}
+
+func TestMemoryFilesystem(t *testing.T) {
+	var archive bytes.Buffer
+	tw := tar.NewWriter(&archive)
+	if err := tw.WriteHeader(&tar.Header{Name: "archive/", Typeflag: tar.TypeDir, Mode: 0o755}); err != nil {
+		t.Fatalf("failed to write tar header: %s", err)
+	}
+	if err := tw.WriteHeader(&tar.Header{Name: "archive/tar.txt", Typeflag: tar.TypeReg, Mode: 0o644, Size: 3}); err != nil {
+		t.Fatalf("failed to write tar header: %s", err)
+	}
+	if _, err := tw.Write([]byte("tar")); err != nil {
+		t.Fatalf("failed to write tar entry: %s", err)
+	}
+	if err := tw.Close(); err != nil {
+		t.Fatalf("failed to close tar writer: %s", err)
+	}
+
+	runInstance(t, &wadge.Config{
+		MemoryPreopens: []wadge.MemoryPreopen{
+			{
+				GuestPath: "/files",
+				Files: map[string][]byte{
+					"dir/seed.txt": []byte("seed"),
+				},
+			},
+			{
+				GuestPath: "/tar",
+				Tar:       archive.Bytes(),
+			},
+		},
+	}, func(instance *wadge.Instance) {
+		files := preopen(t, "/files")
+		defer files.ResourceDrop()
+
+		buf, _, ok := readAt(t, files, "dir/seed.txt")
+		if assert.True(t, ok) {
+			assert.Equal(t, []byte("seed"), buf)
+		}
+
+		// paths escaping the preopened directory are rejected
+		_, code, ok := readAt(t, files, "../tar/archive/tar.txt")
+		if assert.False(t, ok) {
+			assert.Equal(t, types.ErrorCodeNotPermitted, code)
+		}
+		_, code, ok = readAt(t, files, "missing.txt")
+		if assert.False(t, ok) {
+			assert.Equal(t, types.ErrorCodeNoEntry, code)
+		}
+
+		// files written by the guest are visible to native code and vice versa
+		_, ok = writeAt(files, "dir/guest.txt", []byte("guest"))
+		assert.True(t, ok)
+		buf, err := instance.ReadFile("/files", "dir/guest.txt")
+		if assert.NoError(t, err) {
+			assert.Equal(t, []byte("guest"), buf)
+		}
+		assert.NoError(t, instance.WriteFile("/files", "native.txt", []byte("native")))
+		buf, _, ok = readAt(t, files, "native.txt")
+		if assert.True(t, ok) {
+			assert.Equal(t, []byte("native"), buf)
+		}
+
+		_, err = instance.ReadFile("/files", "missing.txt")
+		assert.Error(t, err)
+		_, err = instance.ReadFile("/missing", "dir/seed.txt")
+		assert.Error(t, err)
+
+		archive := preopen(t, "/tar")
+		defer archive.ResourceDrop()
+
+		buf, _, ok = readAt(t, archive, "archive/tar.txt")
+		if assert.True(t, ok) {
+			assert.Equal(t, []byte("tar"), buf)
+		}
+		buf, err = instance.ReadFile("/tar", "archive/tar.txt")
+		if assert.NoError(t, err) {
+			assert.Equal(t, []byte("tar"), buf)
+		}
+	})
+}
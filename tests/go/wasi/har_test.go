@@ -0,0 +1,62 @@
+package wasi_test
+
+import (
+	"encoding/json"
+	"net/http"
+	"os"
+	"path/filepath"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+)
+
+func TestHAR(t *testing.T) {
+	srv := newServer(t, "upstream")
+
+	path := filepath.Join(t.TempDir(), "log.har")
+	t.Setenv("WADGE_HAR", path)
+
+	runInstance(t, &wadge.Config{}, func(instance *wadge.Instance) {
+		resp, code := sendRequest(t, srv.URL+"/har?foo=bar", nil)
+		if assert.Nil(t, code) {
+			assert.Equal(t, uint16(http.StatusOK), resp.status)
+		}
+		if err := instance.Flush(); err != nil {
+			t.Fatalf("failed to flush instance: %s", err)
+		}
+	})
+
+	buf, err := os.ReadFile(path)
+	if err != nil {
+		t.Fatalf("failed to read HAR log: %s", err)
+	}
+	var har struct {
+		Log struct {
+			Version string `json:"version"`
+			Entries []struct {
+				Request struct {
+					Method string `json:"method"`
+					URL    string `json:"url"`
+				} `json:"request"`
+				Response struct {
+					Status  int `json:"status"`
+					Content struct {
+						Text string `json:"text"`
+					} `json:"content"`
+				} `json:"response"`
+			} `json:"entries"`
+		} `json:"log"`
+	}
+	if err := json.Unmarshal(buf, &har); err != nil {
+		t.Fatalf("failed to decode HAR log: %s", err)
+	}
+	assert.Equal(t, "1.2", har.Log.Version)
+	if assert.Len(t, har.Log.Entries, 1) {
+		entry := har.Log.Entries[0]
+		assert.Equal(t, http.MethodGet, entry.Request.Method)
+		assert.Equal(t, srv.URL+"/har?foo=bar", entry.Request.URL)
+		assert.Equal(t, http.StatusOK, entry.Response.Status)
+		assert.Equal(t, "upstream", entry.Response.Content.Text)
+	}
+}
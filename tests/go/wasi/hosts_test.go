@@ -0,0 +1,83 @@
+package wasi_test
+
+import (
+	"net/netip"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	instancenetwork "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/sockets/instance-network"
+	ipnamelookup "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/sockets/ip-name-lookup"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/sockets/network"
+)
+
+// resolve resolves `name` using guest `wasi:sockets/ip-name-lookup`
+func resolve(t *testing.T, name string) ([]netip.Addr, network.ErrorCode, bool) {
+	t.Helper()
+
+	nw := instancenetwork.InstanceNetwork()
+	defer nw.ResourceDrop()
+
+	res := ipnamelookup.ResolveAddresses(nw, name)
+	if res.IsErr() {
+		return nil, *res.Err(), true
+	}
+	stream := *res.OK()
+	defer stream.ResourceDrop()
+
+	var addrs []netip.Addr
+	for {
+		res := stream.ResolveNextAddress()
+		if res.IsErr() {
+			if *res.Err() == network.ErrorCodeWouldBlock {
+				pollable := stream.Subscribe()
+				pollable.Block()
+				pollable.ResourceDrop()
+				continue
+			}
+			return nil, *res.Err(), true
+		}
+		addr := res.OK().Some()
+		if addr == nil {
+			return addrs, 0, false
+		}
+		if ip := addr.IPv4(); ip != nil {
+			addrs = append(addrs, netip.AddrFrom4(*ip))
+		} else if ip := addr.IPv6(); ip != nil {
+			var buf [16]byte
+			for i, v := range ip {
+				buf[2*i] = byte(v >> 8)
+				buf[2*i+1] = byte(v)
+			}
+			addrs = append(addrs, netip.AddrFrom16(buf))
+		}
+	}
+}
+
+func TestHosts(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		Hosts: map[string][]netip.Addr{
+			"example.test": {
+				netip.MustParseAddr("10.1.2.3"),
+				netip.MustParseAddr("fd00::1"),
+			},
+		},
+		ResolveFallthrough: false,
+	}, func(*wadge.Instance) {
+		addrs, _, failed := resolve(t, "example.test")
+		if assert.False(t, failed) {
+			assert.Equal(t, []netip.Addr{
+				netip.MustParseAddr("10.1.2.3"),
+				netip.MustParseAddr("fd00::1"),
+			}, addrs)
+		}
+
+		addrs, _, failed = resolve(t, "EXAMPLE.test")
+		if assert.False(t, failed) {
+			assert.Len(t, addrs, 2)
+		}
+
+		_, _, failed = resolve(t, "unknown.test")
+		assert.True(t, failed)
+	})
+}
@@ -0,0 +1,58 @@
+package wasi_test
+
+import (
+	"net/http"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.bytecodealliance.org/cm"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/http/types"
+	"go.wasmcloud.dev/wadge/wadgehttp"
+)
+
+// handleAuthority is a `wasi:http/incoming-handler.handle` implementation responding
+// with the request authority in the `X-Authority` header and an empty body
+func handleAuthority(req types.IncomingRequest, out types.ResponseOutparam) {
+	defer req.ResourceDrop()
+
+	headers := types.NewFields()
+	if authority := req.Authority().Some(); authority != nil {
+		headers.Append(types.FieldKey("X-Authority"), types.FieldValue(cm.ToList([]byte(*authority))))
+	}
+	resp := types.NewOutgoingResponse(headers)
+	body := resp.Body().OK()
+	types.ResponseOutparamSet(out, cm.OK[cm.Result[types.ErrorCodeShape, types.OutgoingResponse, types.ErrorCode]](resp))
+	types.OutgoingBodyFinish(*body, cm.None[types.Fields]())
+}
+
+func TestHTTPDefaultAuthority(t *testing.T) {
+	for _, tc := range []struct {
+		name      string
+		authority string
+		url       string
+		expected  string
+	}{
+		{name: "default", url: "/", expected: "wadge"},
+		{name: "configured", authority: "example.com", url: "/", expected: "example.com"},
+		{name: "explicit", authority: "example.com", url: "http://localhost/", expected: "localhost"},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			runInstance(t, &wadge.Config{
+				HTTPDefaultAuthority: tc.authority,
+			}, func(*wadge.Instance) {
+				req, err := http.NewRequest(http.MethodGet, tc.url, nil)
+				if err != nil {
+					t.Fatalf("failed to create new HTTP request: %s", err)
+				}
+				resp, err := wadgehttp.HandleIncomingRequest(handleAuthority, req)
+				if err != nil {
+					t.Fatalf("failed to handle incoming HTTP request: %s", err)
+				}
+				assert.Equal(t, http.StatusOK, resp.StatusCode)
+				// header names are not canonicalized by `wadgehttp`
+				assert.Equal(t, []string{tc.expected}, resp.Header["x-authority"])
+			})
+		})
+	}
+}
@@ -0,0 +1,90 @@
+package wasi_test
+
+import (
+	"net/http"
+	"net/url"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+)
+
+func TestHTTPPolicy(t *testing.T) {
+	srv := newServer(t, "upstream")
+	u, err := url.Parse(srv.URL)
+	if err != nil {
+		t.Fatalf("failed to parse server URL: %s", err)
+	}
+
+	for _, tc := range []struct {
+		name    string
+		policy  wadge.HTTPPolicy
+		allow   []string
+		allowed map[string]bool
+	}{
+		{
+			name:   "AllowAll",
+			policy: wadge.HTTPAllowAll,
+			allowed: map[string]bool{
+				"/":    true,
+				"/api": true,
+			},
+		},
+		{
+			name:   "Offline",
+			policy: wadge.HTTPOffline,
+			allowed: map[string]bool{
+				"/":    false,
+				"/api": false,
+			},
+		},
+		{
+			name:   "Authority",
+			policy: wadge.HTTPAllow,
+			allow:  []string{u.Host},
+			allowed: map[string]bool{
+				"/":    true,
+				"/api": true,
+			},
+		},
+		{
+			name:   "Prefix",
+			policy: wadge.HTTPAllow,
+			allow:  []string{srv.URL + "/api/"},
+			allowed: map[string]bool{
+				"/":           false,
+				"/api":        true,
+				"/api/v1":     true,
+				"/apiv1":      false,
+				"/api/../foo": false,
+			},
+		},
+		{
+			name:   "Other",
+			policy: wadge.HTTPAllow,
+			allow:  []string{"example.com", "https://" + u.Host},
+			allowed: map[string]bool{
+				"/": false,
+			},
+		},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			runInstance(t, &wadge.Config{
+				HTTPPolicy: tc.policy,
+				HTTPAllow:  tc.allow,
+			}, func(*wadge.Instance) {
+				for path, allowed := range tc.allowed {
+					resp, code := sendRequest(t, srv.URL+path, nil)
+					if allowed {
+						if assert.Nil(t, code, path) {
+							assert.Equal(t, uint16(http.StatusOK), resp.status, path)
+							assert.Equal(t, []byte("upstream"), resp.body, path)
+						}
+					} else if assert.NotNil(t, code, path) {
+						assert.True(t, code.HTTPRequestDenied(), code.String())
+					}
+				}
+			})
+		})
+	}
+}
@@ -0,0 +1,160 @@
+package wasi_test
+
+import (
+	"io"
+	"net/http"
+	"net/http/httptest"
+	"net/url"
+	"strings"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.bytecodealliance.org/cm"
+	"go.wasmcloud.dev/wadge"
+	outgoinghandler "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/http/outgoing-handler"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/http/types"
+)
+
+// httpResponse is the response received by a guest outgoing `wasi:http` request
+type httpResponse struct {
+	status uint16
+	header http.Header
+	body   []byte
+}
+
+// sendRequest sends a guest outgoing `wasi:http` GET request to `rawURL` and returns
+// either the response or the error code the request failed with
+func sendRequest(t *testing.T, rawURL string, header http.Header) (*httpResponse, *types.ErrorCode) {
+	t.Helper()
+
+	u, err := url.Parse(rawURL)
+	if err != nil {
+		t.Fatalf("failed to parse URL: %s", err)
+	}
+	headers := types.NewFields()
+	for name, values := range header {
+		for _, value := range values {
+			if res := headers.Append(types.FieldKey(name), types.FieldValue(cm.ToList([]byte(value)))); res.IsErr() {
+				t.Fatalf("failed to append header `%s`", name)
+			}
+		}
+	}
+	req := types.NewOutgoingRequest(headers)
+	scheme := types.SchemeHTTP()
+	if u.Scheme == "https" {
+		scheme = types.SchemeHTTPS()
+	}
+	if req.SetMethod(types.MethodGet()) == cm.ResultErr ||
+		req.SetScheme(cm.Some(scheme)) == cm.ResultErr ||
+		req.SetAuthority(cm.Some(u.Host)) == cm.ResultErr ||
+		req.SetPathWithQuery(cm.Some(u.RequestURI())) == cm.ResultErr {
+		t.Fatalf("failed to construct request to `%s`", rawURL)
+	}
+
+	res := outgoinghandler.Handle(req, cm.None[types.RequestOptions]())
+	if res.IsErr() {
+		return nil, res.Err()
+	}
+	fut := *res.OK()
+	defer fut.ResourceDrop()
+
+	pollable := fut.Subscribe()
+	pollable.Block()
+	pollable.ResourceDrop()
+
+	respResRes := fut.Get().Some()
+	if respResRes == nil || respResRes.IsErr() {
+		t.Fatal("response missing")
+	}
+	respRes := respResRes.OK()
+	if respRes.IsErr() {
+		return nil, respRes.Err()
+	}
+	resp := *respRes.OK()
+	defer resp.ResourceDrop()
+
+	headers = resp.Headers()
+	defer headers.ResourceDrop()
+	header = http.Header{}
+	for _, field := range headers.Entries().Slice() {
+		header.Add(string(field.F0), string(field.F1.Slice()))
+	}
+
+	bodyRes := resp.Consume()
+	if bodyRes.IsErr() {
+		t.Fatal("failed to consume response body")
+	}
+	body := *bodyRes.OK()
+	streamRes := body.Stream()
+	if streamRes.IsErr() {
+		t.Fatal("failed to get response body stream")
+	}
+	stream := *streamRes.OK()
+	buf := readAll(t, stream)
+	stream.ResourceDrop()
+	types.IncomingBodyFinish(body).ResourceDrop()
+
+	return &httpResponse{
+		status: uint16(resp.Status()),
+		header: header,
+		body:   buf,
+	}, nil
+}
+
+// newServer starts an HTTP server responding with `body` and the request path in `X-Path` header
+func newServer(t *testing.T, body string) *httptest.Server {
+	t.Helper()
+
+	srv := httptest.NewServer(http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		w.Header().Set("X-Path", r.URL.Path)
+		io.WriteString(w, body)
+	}))
+	t.Cleanup(srv.Close)
+	return srv
+}
+
+func TestOutgoingHTTPHandler(t *testing.T) {
+	srv := newServer(t, "upstream")
+
+	var requests []string
+	runInstance(t, &wadge.Config{
+		OutgoingHTTPHandler: func(r *http.Request) (*http.Response, error) {
+			requests = append(requests, r.URL.Path)
+			switch r.URL.Path {
+			case "/respond":
+				assert.Equal(t, "bar", r.Header.Get("Foo"))
+				return &http.Response{
+					StatusCode: http.StatusTeapot,
+					Header: http.Header{
+						"X-Handler": {"wadge"},
+					},
+					Body: io.NopCloser(strings.NewReader("intercepted")),
+				}, nil
+			case "/deny":
+				return nil, &wadge.HTTPError{Code: wadge.HTTPErrorHTTPRequestDenied}
+			default:
+				return nil, nil
+			}
+		},
+	}, func(*wadge.Instance) {
+		resp, code := sendRequest(t, srv.URL+"/respond", http.Header{"Foo": {"bar"}})
+		if assert.Nil(t, code) {
+			assert.Equal(t, uint16(http.StatusTeapot), resp.status)
+			assert.Equal(t, "wadge", resp.header.Get("X-Handler"))
+			assert.Equal(t, []byte("intercepted"), resp.body)
+		}
+
+		_, code = sendRequest(t, srv.URL+"/deny", nil)
+		if assert.NotNil(t, code) {
+			assert.True(t, code.HTTPRequestDenied(), code.String())
+		}
+
+		resp, code = sendRequest(t, srv.URL+"/pass", nil)
+		if assert.Nil(t, code) {
+			assert.Equal(t, uint16(http.StatusOK), resp.status)
+			assert.Equal(t, "/pass", resp.header.Get("X-Path"))
+			assert.Equal(t, []byte("upstream"), resp.body)
+		}
+	})
+	assert.Equal(t, []string{"/respond", "/deny", "/pass"}, requests)
+}
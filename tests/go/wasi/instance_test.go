@@ -0,0 +1,24 @@
+package wasi_test
+
+import (
+	"testing"
+
+	"go.wasmcloud.dev/wadge"
+)
+
+// runInstance executes `f` with a new instance constructed from `conf` as the global `wadge` Instance,
+// `wadge.Passthrough` is instantiated if `conf.Wasm` is empty
+func runInstance(t *testing.T, conf *wadge.Config, f func(*wadge.Instance)) {
+	t.Helper()
+
+	instance, err := wadge.NewInstance(conf)
+	if err != nil {
+		t.Fatalf("failed to construct new instance: %s", err)
+	}
+	prev := wadge.SetInstance(instance)
+	defer wadge.SetInstance(prev)
+
+	wadge.RunTest(t, func() {
+		f(instance)
+	})
+}
@@ -0,0 +1,94 @@
+package wasi_test
+
+import (
+	"path/filepath"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+)
+
+// keyValueGet returns the value of `key` in `bucket` failing the test on error
+func keyValueGet(t *testing.T, instance *wadge.Instance, bucket, key string) []byte {
+	t.Helper()
+
+	value, err := instance.KeyValueGet(bucket, key)
+	if err != nil {
+		t.Fatalf("failed to get `%s` from bucket `%s`: %s", key, bucket, err)
+	}
+	return value
+}
+
+func TestKeyValue(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		KeyValue: map[string]map[string][]byte{
+			"seeded": {
+				"foo":   []byte("bar"),
+				"empty": {},
+			},
+		},
+	}, func(instance *wadge.Instance) {
+		buckets, err := instance.KeyValueBuckets()
+		if err != nil {
+			t.Fatalf("failed to list buckets: %s", err)
+		}
+		assert.Equal(t, []string{"seeded"}, buckets)
+
+		keys, err := instance.KeyValueKeys("seeded")
+		if err != nil {
+			t.Fatalf("failed to list keys: %s", err)
+		}
+		assert.Equal(t, []string{"empty", "foo"}, keys)
+		assert.Equal(t, []byte("bar"), keyValueGet(t, instance, "seeded", "foo"))
+		assert.Empty(t, keyValueGet(t, instance, "seeded", "empty"))
+
+		_, err = instance.KeyValueGet("seeded", "missing")
+		assert.Error(t, err)
+
+		if err := instance.KeyValueSet("new", "key", []byte("value")); err != nil {
+			t.Fatalf("failed to set value: %s", err)
+		}
+		assert.Equal(t, []byte("value"), keyValueGet(t, instance, "new", "key"))
+		buckets, err = instance.KeyValueBuckets()
+		if err != nil {
+			t.Fatalf("failed to list buckets: %s", err)
+		}
+		assert.Equal(t, []string{"new", "seeded"}, buckets)
+
+		if err := instance.KeyValueDelete("seeded", "foo"); err != nil {
+			t.Fatalf("failed to delete key: %s", err)
+		}
+		keys, err = instance.KeyValueKeys("seeded")
+		if err != nil {
+			t.Fatalf("failed to list keys: %s", err)
+		}
+		assert.Equal(t, []string{"empty"}, keys)
+	})
+}
+
+func TestKeyValuePersistent(t *testing.T) {
+	for _, tc := range []struct {
+		name    string
+		backend wadge.KeyValueBackend
+		path    string
+	}{
+		{name: "Dir", backend: wadge.KeyValueDir, path: "buckets"},
+		{name: "File", backend: wadge.KeyValueFile, path: "buckets.json"},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			conf := &wadge.Config{
+				KeyValueBackend: tc.backend,
+				KeyValuePath:    filepath.Join(t.TempDir(), tc.path),
+			}
+			runInstance(t, conf, func(instance *wadge.Instance) {
+				if err := instance.KeyValueSet("bucket", "key", []byte("value")); err != nil {
+					t.Fatalf("failed to set value: %s", err)
+				}
+			})
+			// every modification is persisted, so a new instance observes it
+			runInstance(t, conf, func(instance *wadge.Instance) {
+				assert.Equal(t, []byte("value"), keyValueGet(t, instance, "bucket", "key"))
+			})
+		})
+	}
+}
@@ -0,0 +1,41 @@
+package wasi_test
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+)
+
+func TestMessaging(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		MessagingReplyHandlers: map[string]wadge.MessagingReplyHandler{
+			"rpc": func(req wadge.Message) []wadge.Message {
+				return []wadge.Message{{Topic: "reply", Data: req.Data}}
+			},
+		},
+	}, func(instance *wadge.Instance) {
+		messages, err := instance.PublishedMessages()
+		if err != nil {
+			t.Fatalf("failed to list published messages: %s", err)
+		}
+		assert.Empty(t, messages)
+
+		messages, err = instance.TakePublishedMessages()
+		if err != nil {
+			t.Fatalf("failed to take published messages: %s", err)
+		}
+		assert.Empty(t, messages)
+
+		// the passthrough component does not export `wasi:messaging/incoming-handler`
+		err = instance.HandleMessage(wadge.Message{
+			Topic:       "events",
+			ContentType: "text/plain",
+			Data:        []byte("hello"),
+			Metadata:    map[string]string{"foo": "bar"},
+		})
+		if assert.Error(t, err) {
+			assert.Contains(t, err.Error(), "wasi:messaging/incoming-handler")
+		}
+	})
+}
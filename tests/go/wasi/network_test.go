@@ -0,0 +1,100 @@
+package wasi_test
+
+import (
+	"net"
+	"net/netip"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	instancenetwork "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/sockets/instance-network"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/sockets/network"
+	tcpcreatesocket "go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/sockets/tcp-create-socket"
+)
+
+// connect connects a guest TCP socket to IPv4 `addr`
+func connect(t *testing.T, addr netip.AddrPort) (network.ErrorCode, bool) {
+	t.Helper()
+
+	nw := instancenetwork.InstanceNetwork()
+	defer nw.ResourceDrop()
+
+	res := tcpcreatesocket.CreateTCPSocket(network.IPAddressFamilyIPv4)
+	if res.IsErr() {
+		t.Fatalf("failed to create TCP socket: %s", res.Err().String())
+	}
+	sock := *res.OK()
+	defer sock.ResourceDrop()
+
+	remote := network.IPSocketAddressIPv4(network.IPv4SocketAddress{
+		Port:    addr.Port(),
+		Address: network.IPv4Address(addr.Addr().As4()),
+	})
+	if res := sock.StartConnect(nw, remote); res.IsErr() {
+		return *res.Err(), true
+	}
+	pollable := sock.Subscribe()
+	defer pollable.ResourceDrop()
+	pollable.Block()
+
+	conn := sock.FinishConnect()
+	if conn.IsErr() {
+		return *conn.Err(), true
+	}
+	conn.OK().F0.ResourceDrop()
+	conn.OK().F1.ResourceDrop()
+	return 0, false
+}
+
+func TestNetworkPolicy(t *testing.T) {
+	ln, err := net.Listen("tcp4", "127.0.0.1:0")
+	if err != nil {
+		t.Fatalf("failed to listen: %s", err)
+	}
+	defer ln.Close()
+	go func() {
+		for {
+			conn, err := ln.Accept()
+			if err != nil {
+				return
+			}
+			conn.Close()
+		}
+	}()
+	addr := netip.MustParseAddrPort(ln.Addr().String())
+
+	runInstance(t, &wadge.Config{
+		Network: wadge.NetworkDenyAll,
+	}, func(*wadge.Instance) {
+		code, failed := connect(t, addr)
+		if assert.True(t, failed) {
+			assert.Equal(t, network.ErrorCodeAccessDenied, code)
+		}
+	})
+	runInstance(t, &wadge.Config{
+		Network: wadge.NetworkLoopback,
+	}, func(*wadge.Instance) {
+		_, failed := connect(t, addr)
+		assert.False(t, failed)
+	})
+	runInstance(t, &wadge.Config{
+		Network: wadge.NetworkAllow,
+		NetworkAllow: []wadge.NetworkRule{
+			{CIDR: "127.0.0.0/8", PortMin: addr.Port(), PortMax: addr.Port()},
+		},
+	}, func(*wadge.Instance) {
+		_, failed := connect(t, addr)
+		assert.False(t, failed)
+	})
+	runInstance(t, &wadge.Config{
+		Network: wadge.NetworkAllow,
+		NetworkAllow: []wadge.NetworkRule{
+			{CIDR: "10.0.0.0/8", PortMin: 0, PortMax: 65535},
+		},
+	}, func(*wadge.Instance) {
+		code, failed := connect(t, addr)
+		if assert.True(t, failed) {
+			assert.Equal(t, network.ErrorCodeAccessDenied, code)
+		}
+	})
+}
@@ -0,0 +1,65 @@
+package wasi_test
+
+import (
+	"os"
+	"path/filepath"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/filesystem/types"
+)
+
+func TestPreopens(t *testing.T) {
+	ro := t.TempDir()
+	if err := os.WriteFile(filepath.Join(ro, "foo.txt"), []byte("foo"), 0o644); err != nil {
+		t.Fatalf("failed to write file: %s", err)
+	}
+	rw := t.TempDir()
+
+	runInstance(t, &wadge.Config{
+		Preopens: []wadge.Preopen{
+			{
+				HostPath:  ro,
+				GuestPath: "/ro",
+				DirPerms:  wadge.DirPermsRead,
+				FilePerms: wadge.FilePermsRead,
+			},
+			{
+				HostPath:  rw,
+				GuestPath: "/rw",
+				DirPerms:  wadge.DirPermsRead | wadge.DirPermsMutate,
+				FilePerms: wadge.FilePermsRead | wadge.FilePermsWrite,
+			},
+		},
+	}, func(*wadge.Instance) {
+		roDir := preopen(t, "/ro")
+		defer roDir.ResourceDrop()
+
+		buf, _, ok := readAt(t, roDir, "foo.txt")
+		if assert.True(t, ok) {
+			assert.Equal(t, []byte("foo"), buf)
+		}
+		code, ok := writeAt(roDir, "foo.txt", []byte("bar"))
+		if assert.False(t, ok) {
+			assert.Equal(t, types.ErrorCodeNotPermitted, code)
+		}
+		code, ok = writeAt(roDir, "new.txt", []byte("bar"))
+		if assert.False(t, ok) {
+			assert.Equal(t, types.ErrorCodeNotPermitted, code)
+		}
+		_, err := os.Stat(filepath.Join(ro, "new.txt"))
+		assert.ErrorIs(t, err, os.ErrNotExist)
+
+		rwDir := preopen(t, "/rw")
+		defer rwDir.ResourceDrop()
+
+		_, ok = writeAt(rwDir, "bar.txt", []byte("bar"))
+		if assert.True(t, ok) {
+			buf, err := os.ReadFile(filepath.Join(rw, "bar.txt"))
+			if assert.NoError(t, err) {
+				assert.Equal(t, []byte("bar"), buf)
+			}
+		}
+	})
+}
@@ -0,0 +1,37 @@
+package wasi_test
+
+import (
+	"bytes"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/random/insecure"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/random/random"
+)
+
+// seededRandom returns random bytes produced by an instance seeded with `seed`
+func seededRandom(t *testing.T, seed uint64) (secure, insecureBytes []byte) {
+	t.Helper()
+
+	runInstance(t, &wadge.Config{
+		RandomSeed: &seed,
+	}, func(*wadge.Instance) {
+		secure = bytes.Clone(random.GetRandomBytes(32).Slice())
+		insecureBytes = bytes.Clone(insecure.GetInsecureRandomBytes(32).Slice())
+	})
+	return secure, insecureBytes
+}
+
+func TestRandomSeed(t *testing.T) {
+	secure, insecureBytes := seededRandom(t, 42)
+	assert.Len(t, secure, 32)
+	assert.Len(t, insecureBytes, 32)
+
+	secureAgain, insecureAgain := seededRandom(t, 42)
+	assert.Equal(t, secure, secureAgain)
+	assert.Equal(t, insecureBytes, insecureAgain)
+
+	secureOther, _ := seededRandom(t, 43)
+	assert.NotEqual(t, secure, secureOther)
+}
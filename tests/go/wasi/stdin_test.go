@@ -0,0 +1,69 @@
+package wasi_test
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/cli/stdin"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/io/streams"
+)
+
+// readAll reads `in` until end-of-stream
+func readAll(t *testing.T, in streams.InputStream) []byte {
+	t.Helper()
+
+	var buf []byte
+	for {
+		res := in.BlockingRead(4096)
+		if res.IsErr() {
+			if !res.Err().Closed() {
+				t.Fatalf("failed to read stream: %s", res.Err().LastOperationFailed().ToDebugString())
+			}
+			return buf
+		}
+		buf = append(buf, res.OK().Slice()...)
+	}
+}
+
+func TestStdinBytes(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		Stdin:      wadge.InputBytes,
+		StdinBytes: []byte("foo"),
+	}, func(*wadge.Instance) {
+		in := stdin.GetStdin()
+		defer in.ResourceDrop()
+		assert.Equal(t, []byte("foo"), readAll(t, in))
+	})
+	runInstance(t, &wadge.Config{
+		Stdin: wadge.InputEmpty,
+	}, func(*wadge.Instance) {
+		in := stdin.GetStdin()
+		defer in.ResourceDrop()
+		assert.Empty(t, readAll(t, in))
+	})
+}
+
+func TestStdinPipe(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		Stdin:      wadge.InputPipe,
+		StdinBytes: []byte("foo"),
+	}, func(instance *wadge.Instance) {
+		in := stdin.GetStdin()
+		defer in.ResourceDrop()
+
+		res := in.BlockingRead(4096)
+		if assert.False(t, res.IsErr()) {
+			assert.Equal(t, []byte("foo"), res.OK().Slice())
+		}
+		assert.NoError(t, instance.PushStdin([]byte("bar")))
+		res = in.BlockingRead(4096)
+		if assert.False(t, res.IsErr()) {
+			assert.Equal(t, []byte("bar"), res.OK().Slice())
+		}
+		assert.NoError(t, instance.PushStdin([]byte("baz")))
+		assert.NoError(t, instance.CloseStdin())
+		assert.Equal(t, []byte("baz"), readAll(t, in))
+		assert.Error(t, instance.PushStdin([]byte("closed")))
+	})
+}
@@ -0,0 +1,39 @@
+package wasi_test
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"go.bytecodealliance.org/cm"
+	"go.wasmcloud.dev/wadge"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/cli/stderr"
+	"go.wasmcloud.dev/wadge/tests/go/wasi/bindings/wasi/cli/stdout"
+)
+
+func TestOutputCapture(t *testing.T) {
+	runInstance(t, &wadge.Config{
+		Stdout: wadge.OutputCapture,
+		Stderr: wadge.OutputCapture,
+	}, func(instance *wadge.Instance) {
+		out := stdout.GetStdout()
+		defer out.ResourceDrop()
+		assert.False(t, out.BlockingWriteAndFlush(cm.ToList([]byte("foo"))).IsErr())
+		assert.False(t, out.BlockingWriteAndFlush(cm.ToList([]byte("bar"))).IsErr())
+
+		errOut := stderr.GetStderr()
+		defer errOut.ResourceDrop()
+		assert.False(t, errOut.BlockingWriteAndFlush(cm.ToList([]byte("baz"))).IsErr())
+
+		assert.Equal(t, []byte("foobar"), instance.TakeStdout())
+		assert.Empty(t, instance.TakeStdout())
+		assert.Equal(t, []byte("baz"), instance.TakeStderr())
+	})
+	runInstance(t, &wadge.Config{
+		Stdout: wadge.OutputDiscard,
+	}, func(instance *wadge.Instance) {
+		out := stdout.GetStdout()
+		defer out.ResourceDrop()
+		assert.False(t, out.BlockingWriteAndFlush(cm.ToList([]byte("foo"))).IsErr())
+		assert.Empty(t, instance.TakeStdout())
+	})
+}
@@ -15,17 +15,27 @@ package wadge
 // #cgo windows       LDFLAGS: -lws2_32 -lole32 -loleaut32 -lntdll -lbcrypt -luserenv
 // #include "./include/wadge.h"
 // #include <stdlib.h>
+// extern uint32_t wadgeOutgoingHTTPHandler(void *data, HttpRequest *request, void *response);
+// extern void wadgeMessagingReplyHandler(void *data, Message *request, void *replies);
+// extern void wadgeMessageVisitor(void *data, Message *message);
 import "C"
 
 import (
+	"bytes"
 	_ "embed"
 	"errors"
 	"fmt"
+	"io"
 	"log"
+	"net/http"
+	"net/netip"
 	"runtime"
+	"runtime/cgo"
+	"strings"
 	"sync"
 	"sync/atomic"
 	"testing"
+	"time"
 	"unsafe"
 )
 
@@ -177,6 +187,389 @@ func RunTest(t *testing.T, f func()) {
 // Instance is an instantiated Wasm component in `wadge` runtime
 type Instance struct {
 	ptr unsafe.Pointer
+
+	outgoingHTTPHandler     cgo.Handle
+	outgoingHTTPHandlerData unsafe.Pointer
+
+	messagingReplyHandler     cgo.Handle
+	messagingReplyHandlerData unsafe.Pointer
+}
+
+func (i *Instance) freeOutgoingHTTPHandler() {
+	if i.outgoingHTTPHandlerData != nil {
+		i.outgoingHTTPHandler.Delete()
+		C.free(i.outgoingHTTPHandlerData)
+	}
+}
+
+func (i *Instance) freeMessagingReplyHandler() {
+	if i.messagingReplyHandlerData != nil {
+		i.messagingReplyHandler.Delete()
+		C.free(i.messagingReplyHandlerData)
+	}
+}
+
+// InheritEnv is the host environment variable inheritance policy
+type InheritEnv int
+
+const (
+	// InheritEnvAll exposes all host environment variables to the guest
+	InheritEnvAll InheritEnv = iota
+	// InheritEnvNone does not expose any host environment variables to the guest
+	InheritEnvNone
+	// InheritEnvAllow exposes only the host environment variables listed in
+	// `Config.InheritEnvAllow` to the guest
+	InheritEnvAllow
+)
+
+// DirPerms are the permissions a guest has on a preopened directory
+type DirPerms uint
+
+const (
+	// DirPermsRead allows the guest to read the directory
+	DirPermsRead DirPerms = 1 << iota
+	// DirPermsMutate allows the guest to mutate the directory
+	DirPermsMutate
+)
+
+// FilePerms are the maximum permissions a guest has on files within a preopened directory
+type FilePerms uint
+
+const (
+	// FilePermsRead allows the guest to read files
+	FilePermsRead FilePerms = 1 << iota
+	// FilePermsWrite allows the guest to write files
+	FilePermsWrite
+)
+
+// Preopen is a host directory exposed to the guest via `wasi:filesystem/preopens`
+type Preopen struct {
+	// HostPath is the path to the directory on the host
+	HostPath string
+	// GuestPath is the path to the directory as seen by the guest
+	GuestPath string
+	// DirPerms are the permissions the guest has on the directory
+	DirPerms DirPerms
+	// FilePerms are the maximum permissions the guest has on files within the directory
+	FilePerms FilePerms
+}
+
+// MemoryPreopen is an in-memory filesystem exposed to the guest via `wasi:filesystem/preopens`
+type MemoryPreopen struct {
+	// GuestPath is the path to the filesystem root as seen by the guest
+	GuestPath string
+	// Files is the set of files to seed the filesystem with, indexed by path
+	Files map[string][]byte
+	// Tar is a tar archive to seed the filesystem with
+	Tar []byte
+}
+
+// BlobContainer is a guest `wasi:blobstore` container
+type BlobContainer struct {
+	// Name is the name of the container
+	Name string
+	// Dir is the host directory backing the container, empty means the container is kept in memory.
+	// Objects are stored as files and object names are paths relative to `Dir`.
+	Dir string
+	// Objects is the set of objects to seed the container with, indexed by name
+	Objects map[string][]byte
+}
+
+// Input is the guest input stream handling mode
+type Input int
+
+const (
+	// InputEmpty provides empty guest input
+	InputEmpty Input = iota
+	// InputInherit forwards input of the host process to the guest
+	InputInherit
+	// InputBytes provides `Config.StdinBytes` followed by end-of-stream to the guest
+	InputBytes
+	// InputPipe provides `Config.StdinBytes` followed by bytes pushed via
+	// `Instance.PushStdin` to the guest until `Instance.CloseStdin` is called
+	InputPipe
+)
+
+// Output is the guest output stream handling mode
+type Output int
+
+const (
+	// OutputInherit forwards guest output to the host process
+	OutputInherit Output = iota
+	// OutputCapture captures guest output in memory, see `Instance.TakeStdout` and `Instance.TakeStderr`
+	OutputCapture
+	// OutputDiscard discards guest output
+	OutputDiscard
+)
+
+// NetworkPolicy is the guest `wasi:sockets` network policy.
+// Denied socket operations fail with `access-denied` error code.
+type NetworkPolicy int
+
+const (
+	// NetworkInherit allows all socket addresses
+	NetworkInherit NetworkPolicy = iota
+	// NetworkLoopback allows only loopback socket addresses
+	NetworkLoopback
+	// NetworkDenyAll denies all socket addresses
+	NetworkDenyAll
+	// NetworkAllow allows only socket addresses matching at least one of
+	// `Config.NetworkAllow` rules
+	NetworkAllow
+)
+
+// NetworkRule describes socket addresses the guest is allowed to connect to or bind
+type NetworkRule struct {
+	// CIDR is the allowed IP address range in CIDR notation, e.g. `10.0.0.0/8`
+	CIDR string
+	// PortMin is the lowest allowed port
+	PortMin uint16
+	// PortMax is the highest allowed port
+	PortMax uint16
+}
+
+// HTTPPolicy is the policy applied to outgoing `wasi:http` requests leaving the process,
+// i.e. requests not intercepted, replayed from a cassette or routed to an in-process instance.
+// Denied requests fail with `HTTP-request-denied` error code.
+type HTTPPolicy int
+
+const (
+	// HTTPAllowAll allows all requests
+	HTTPAllowAll HTTPPolicy = iota
+	// HTTPOffline denies all requests
+	HTTPOffline
+	// HTTPAllow allows only requests matching at least one of
+	// `Config.HTTPAllow` authorities or URL prefixes
+	HTTPAllow
+)
+
+// KeyValueBackend is the guest `wasi:keyvalue` store backend
+type KeyValueBackend int
+
+const (
+	// KeyValueMemory keeps buckets in memory
+	KeyValueMemory KeyValueBackend = iota
+	// KeyValueDir persists each bucket in a separate JSON file
+	// within `Config.KeyValuePath` directory
+	KeyValueDir
+	// KeyValueFile persists all buckets in a single JSON file at `Config.KeyValuePath`
+	KeyValueFile
+)
+
+// CassetteMode is the outgoing `wasi:http` cassette mode
+type CassetteMode int
+
+const (
+	// CassetteRecord sends requests to their destinations and writes all exchanges to the cassette
+	// on `Instance.Flush` and once the instance is freed
+	CassetteRecord CassetteMode = iota
+	// CassetteReplay serves requests from the cassette, unmatched requests fail
+	CassetteReplay
+)
+
+// Cassette is the outgoing `wasi:http` record/replay cassette configuration
+type Cassette struct {
+	// Path is the path to the JSON cassette file
+	Path string
+	// Mode is the cassette mode
+	Mode CassetteMode
+	// MatchHeaders is the list of request header names, which must match in addition
+	// to method and URI for a request to be served from the cassette in `CassetteReplay` mode
+	MatchHeaders []string
+}
+
+// HTTPErrorCode is the `wasi:http` error code
+type HTTPErrorCode int
+
+const (
+	HTTPErrorInternalError HTTPErrorCode = iota
+	HTTPErrorDNSTimeout
+	HTTPErrorDestinationNotFound
+	HTTPErrorDestinationUnavailable
+	HTTPErrorDestinationIPProhibited
+	HTTPErrorDestinationIPUnroutable
+	HTTPErrorConnectionRefused
+	HTTPErrorConnectionTerminated
+	HTTPErrorConnectionTimeout
+	HTTPErrorConnectionReadTimeout
+	HTTPErrorConnectionWriteTimeout
+	HTTPErrorConnectionLimitReached
+	HTTPErrorTLSProtocolError
+	HTTPErrorTLSCertificateError
+	HTTPErrorHTTPRequestDenied
+	HTTPErrorHTTPRequestLengthRequired
+	HTTPErrorHTTPRequestMethodInvalid
+	HTTPErrorHTTPRequestURIInvalid
+	HTTPErrorHTTPRequestURITooLong
+	HTTPErrorHTTPResponseIncomplete
+	HTTPErrorHTTPResponseTimeout
+	HTTPErrorHTTPUpgradeFailed
+	HTTPErrorHTTPProtocolError
+	HTTPErrorLoopDetected
+	HTTPErrorConfigurationError
+)
+
+// HTTPError is a `wasi:http` error returned to the guest
+type HTTPError struct {
+	// Code is the error code
+	Code HTTPErrorCode
+	// Message is the error message, it is only used for `HTTPErrorInternalError`
+	Message string
+}
+
+func (e *HTTPError) Error() string {
+	if e.Message != "" {
+		return fmt.Sprintf("HTTP error %d: %s", e.Code, e.Message)
+	}
+	return fmt.Sprintf("HTTP error %d", e.Code)
+}
+
+// OutgoingHTTPHandler intercepts outgoing guest `wasi:http` requests.
+// Returning a nil response and a nil error sends the request to its destination.
+// Returning `*HTTPError` fails the request with the specified error code,
+// other errors fail the request with `HTTPErrorInternalError`.
+// OutgoingHTTPHandler may be called concurrently.
+type OutgoingHTTPHandler func(*http.Request) (*http.Response, error)
+
+//export wadgeOutgoingHTTPHandler
+func wadgeOutgoingHTTPHandler(data unsafe.Pointer, request *C.HttpRequest, response unsafe.Pointer) C.uint32_t {
+	var pinner runtime.Pinner
+	defer pinner.Unpin()
+
+	fail := func(err error) C.uint32_t {
+		if err == nil {
+			err = errors.New("unknown error")
+		}
+		var httpErr *HTTPError
+		if !errors.As(err, &httpErr) {
+			httpErr = &HTTPError{Code: HTTPErrorInternalError, Message: err.Error()}
+		}
+		var message C.List_u8
+		if httpErr.Message != "" {
+			message = pinString(&pinner, httpErr.Message)
+		}
+		C.http_response_set_error(response, C.uint32_t(httpErr.Code), message)
+		return C.HttpAction_Fail
+	}
+
+	handler := (*(*cgo.Handle)(data)).Value().(OutgoingHTTPHandler)
+	req, err := http.NewRequest(
+		C.GoStringN((*C.char)(unsafe.Pointer(request.method.ptr)), C.int(request.method.len)),
+		C.GoStringN((*C.char)(unsafe.Pointer(request.uri.ptr)), C.int(request.uri.len)),
+		bytes.NewReader(C.GoBytes(unsafe.Pointer(request.body.ptr), C.int(request.body.len))),
+	)
+	if err != nil {
+		return fail(&HTTPError{Code: HTTPErrorHTTPRequestURIInvalid})
+	}
+	for _, kv := range unsafe.Slice(request.headers.ptr, request.headers.len) {
+		req.Header.Add(
+			C.GoStringN((*C.char)(unsafe.Pointer(kv.key.ptr)), C.int(kv.key.len)),
+			C.GoStringN((*C.char)(unsafe.Pointer(kv.value.ptr)), C.int(kv.value.len)),
+		)
+	}
+	resp, err := handler(req)
+	if err != nil {
+		return fail(err)
+	}
+	if resp == nil {
+		return C.HttpAction_PassThrough
+	}
+	var body []byte
+	if resp.Body != nil {
+		defer resp.Body.Close()
+		body, err = io.ReadAll(resp.Body)
+		if err != nil {
+			return fail(fmt.Errorf("failed to read response body: %w", err))
+		}
+	}
+	if !C.http_response_set_status(response, C.uint16_t(resp.StatusCode)) {
+		return fail(takeError())
+	}
+	for name, values := range resp.Header {
+		for _, value := range values {
+			if !C.http_response_append_header(response, pinString(&pinner, name), pinString(&pinner, value)) {
+				return fail(takeError())
+			}
+		}
+	}
+	if len(body) > 0 {
+		if !C.http_response_set_body(response, pinBytes(&pinner, body)) {
+			return fail(takeError())
+		}
+	}
+	return C.HttpAction_Respond
+}
+
+// Message is a guest `wasi:messaging` message
+type Message struct {
+	// Topic is the topic the message was published or delivered on
+	Topic string
+	// ReplyTo is the topic guest replies to the message are published on
+	ReplyTo string
+	// ContentType is the format of `Data`
+	ContentType string
+	// Data is the opaque message payload
+	Data []byte
+	// Metadata is the set of key-value pairs attached to the message
+	Metadata map[string]string
+}
+
+// MessagingReplyHandler responds to guest `wasi:messaging` requests, returned messages
+// are passed to the guest as replies. Returning no replies fails the request with a timeout.
+// MessagingReplyHandler may be called concurrently.
+type MessagingReplyHandler func(Message) []Message
+
+func goString(s C.List_u8) string {
+	return C.GoStringN((*C.char)(unsafe.Pointer(s.ptr)), C.int(s.len))
+}
+
+func goMessage(message *C.Message) Message {
+	msg := Message{
+		Topic:       goString(message.topic),
+		ReplyTo:     goString(message.reply_to),
+		ContentType: goString(message.content_type),
+		Data:        C.GoBytes(unsafe.Pointer(message.data.ptr), C.int(message.data.len)),
+	}
+	if message.metadata.len > 0 {
+		msg.Metadata = make(map[string]string, message.metadata.len)
+		for _, kv := range unsafe.Slice(message.metadata.ptr, message.metadata.len) {
+			msg.Metadata[goString(kv.key)] = goString(kv.value)
+		}
+	}
+	return msg
+}
+
+//export wadgeMessagingReplyHandler
+func wadgeMessagingReplyHandler(data unsafe.Pointer, request *C.Message, replies unsafe.Pointer) {
+	handlers := (*(*cgo.Handle)(data)).Value().(map[string]MessagingReplyHandler)
+	req := goMessage(request)
+	handler, ok := handlers[req.Topic]
+	if !ok {
+		return
+	}
+	for _, reply := range handler(req) {
+		var pinner runtime.Pinner
+		ok := C.messaging_replies_push(replies, pinMessage(&pinner, reply))
+		pinner.Unpin()
+		if !ok {
+			log.Printf("failed to reply to message on `%s`: %s", req.Topic, takeError())
+		}
+	}
+}
+
+//export wadgeMessageVisitor
+func wadgeMessageVisitor(data unsafe.Pointer, message *C.Message) {
+	messages := (*(*cgo.Handle)(data)).Value().(*[]Message)
+	*messages = append(*messages, goMessage(message))
+}
+
+// VirtualClock is the virtual guest clock configuration
+type VirtualClock struct {
+	// Start is the initial wall clock time.
+	Start time.Time
+	// AutoAdvance configures whether guest blocking on `monotonic-clock`
+	// pollables advances the clock to the earliest deadline waited on.
+	AutoAdvance bool
 }
 
 // Config is `wadge` runtime configuration
@@ -185,6 +578,345 @@ type Config struct {
 	// binary Wasm or WAT.
 	// In case a Wasm module is specified here, the runtime will componentize it.
 	Wasm []byte
+	// Env is the set of environment variables exposed to the guest,
+	// these take precedence over inherited host environment variables.
+	Env map[string]string
+	// InheritEnv is the host environment variable inheritance policy.
+	InheritEnv InheritEnv
+	// InheritEnvAllow is the list of host environment variable names to
+	// inherit if `InheritEnv` is `InheritEnvAllow`.
+	InheritEnvAllow []string
+	// Args is the list of arguments exposed to the guest.
+	Args []string
+	// Cwd is the initial working directory exposed to the guest, empty means none.
+	Cwd string
+	// Preopens is the list of host directories exposed to the guest.
+	Preopens []Preopen
+	// MemoryPreopens is the list of in-memory filesystems exposed to the guest.
+	// Contents of these can be inspected using `Instance.ReadFile`.
+	MemoryPreopens []MemoryPreopen
+	// Stdin is the guest stdin handling mode.
+	Stdin Input
+	// StdinBytes is the initial guest stdin contents.
+	StdinBytes []byte
+	// Stdout is the guest stdout handling mode.
+	Stdout Output
+	// Stderr is the guest stderr handling mode.
+	Stderr Output
+	// Clock is the virtual guest clock configuration, nil means host clocks are used.
+	// Virtual clock is frozen unless advanced via `Instance.AdvanceClock` or auto-advance.
+	Clock *VirtualClock
+	// RandomSeed is the seed making guest `wasi:random` interfaces deterministic,
+	// nil means OS-backed randomness is used.
+	RandomSeed *uint64
+	// Network is the guest `wasi:sockets` network policy.
+	Network NetworkPolicy
+	// NetworkAllow is the list of network rules used if `Network` is `NetworkAllow`.
+	NetworkAllow []NetworkRule
+	// Hosts maps hostnames to IP addresses, it is used by `wasi:sockets/ip-name-lookup`
+	// and outgoing `wasi:http` requests.
+	Hosts map[string][]netip.Addr
+	// ResolveFallthrough configures whether `wasi:sockets/ip-name-lookup` resolves
+	// hostnames not present in `Hosts` using the system resolver, disabled by default.
+	ResolveFallthrough bool
+	// OutgoingHTTPHandler intercepts outgoing guest `wasi:http` requests, nil means none.
+	OutgoingHTTPHandler OutgoingHTTPHandler
+	// Cassette is the outgoing `wasi:http` record/replay cassette, nil means none.
+	Cassette *Cassette
+	// HTTPRoutes maps authorities (`host` or `host:port`) to instances handling
+	// matching outgoing guest `wasi:http` requests via their `wasi:http/incoming-handler` export.
+	// Requests routed to an instance, which is busy handling a call, fail with `loop-detected`.
+	HTTPRoutes map[string]*Instance
+	// HTTPBetweenBytesTimeout is the timeout between frames of HTTP bodies constructed
+	// via `wasiext:http/ext`, e.g. by `wadgehttp`, zero means the default of 1s.
+	HTTPBetweenBytesTimeout time.Duration
+	// HTTPFieldSizeLimit is the maximum size of HTTP request fields constructed
+	// via `wasiext:http/ext`, zero means the default of 2GiB.
+	HTTPFieldSizeLimit uint
+	// HTTPDefaultAuthority is the authority of HTTP requests constructed via
+	// `wasiext:http/ext`, which do not specify one, empty means the default of `wadge`.
+	HTTPDefaultAuthority string
+	// HTTPPolicy is the policy applied to outgoing guest `wasi:http` requests leaving the process.
+	HTTPPolicy HTTPPolicy
+	// HTTPAllow is the list of authorities (`host` or `host:port`) or URL prefixes
+	// (e.g. `https://example.com/api/`) used if `HTTPPolicy` is `HTTPAllow`.
+	// URL prefixes match scheme, host and port exactly and the path on `/` boundaries.
+	HTTPAllow []string
+	// KeyValue is the initial contents of guest `wasi:keyvalue` buckets, indexed by
+	// bucket identifier and key. Contents can be inspected using `Instance.KeyValueGet`.
+	KeyValue map[string]map[string][]byte
+	// KeyValueBackend is the guest `wasi:keyvalue` store backend, buckets persisted by
+	// `KeyValueDir` and `KeyValueFile` backends outlive the instance.
+	KeyValueBackend KeyValueBackend
+	// KeyValuePath is the path used by `KeyValueBackend`.
+	KeyValuePath string
+	// RuntimeConfig is the set of configuration values exposed to the guest via `wasi:config/store`.
+	RuntimeConfig map[string]string
+	// BlobContainers is the set of guest `wasi:blobstore` containers. Contents can be
+	// inspected using `Instance.BlobRead`.
+	BlobContainers []BlobContainer
+	// MessagingReplyHandlers is the set of handlers responding to guest `wasi:messaging`
+	// requests, indexed by topic. Messages published by the guest can be inspected
+	// using `Instance.PublishedMessages`.
+	MessagingReplyHandlers map[string]MessagingReplyHandler
+}
+
+func takeError() error {
+	n := C.error_len()
+	buf := make([]C.char, n)
+	if n = C.error_take(unsafe.SliceData(buf), n); n > 0 {
+		return errors.New(C.GoStringN(unsafe.SliceData(buf), C.int(n)))
+	}
+	return nil
+}
+
+func pinBytes(pinner *runtime.Pinner, b []byte) C.List_u8 {
+	ptr := unsafe.SliceData(b)
+	pinner.Pin(ptr)
+	return C.List_u8{
+		ptr: (*C.uchar)(ptr),
+		len: C.uintptr_t(len(b)),
+	}
+}
+
+func pinString(pinner *runtime.Pinner, s string) C.List_u8 {
+	ptr := unsafe.StringData(s)
+	pinner.Pin(ptr)
+	return C.List_u8{
+		ptr: (*C.uchar)(ptr),
+		len: C.uintptr_t(len(s)),
+	}
+}
+
+func pinStrings(pinner *runtime.Pinner, ss []string) C.List_List_u8 {
+	if len(ss) == 0 {
+		return C.List_List_u8{}
+	}
+	list := make([]C.List_u8, len(ss))
+	for i, s := range ss {
+		list[i] = pinString(pinner, s)
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_List_u8{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinPreopens(pinner *runtime.Pinner, preopens []Preopen) C.List_Preopen {
+	if len(preopens) == 0 {
+		return C.List_Preopen{}
+	}
+	list := make([]C.Preopen, len(preopens))
+	for i, p := range preopens {
+		list[i] = C.Preopen{
+			host_path:  pinString(pinner, p.HostPath),
+			guest_path: pinString(pinner, p.GuestPath),
+			dir_perms:  C.uintptr_t(p.DirPerms),
+			file_perms: C.uintptr_t(p.FilePerms),
+		}
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_Preopen{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinNetworkRules(pinner *runtime.Pinner, rules []NetworkRule) C.List_NetworkRule {
+	if len(rules) == 0 {
+		return C.List_NetworkRule{}
+	}
+	list := make([]C.NetworkRule, len(rules))
+	for i, r := range rules {
+		list[i] = C.NetworkRule{
+			cidr:     pinString(pinner, r.CIDR),
+			port_min: C.uint16_t(r.PortMin),
+			port_max: C.uint16_t(r.PortMax),
+		}
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_NetworkRule{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinHosts(pinner *runtime.Pinner, hosts map[string][]netip.Addr) C.List_HostOverride {
+	if len(hosts) == 0 {
+		return C.List_HostOverride{}
+	}
+	list := make([]C.HostOverride, 0, len(hosts))
+	for name, addrs := range hosts {
+		ss := make([]string, len(addrs))
+		for i, addr := range addrs {
+			ss[i] = addr.String()
+		}
+		list = append(list, C.HostOverride{
+			name:  pinString(pinner, name),
+			addrs: pinStrings(pinner, ss),
+		})
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_HostOverride{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinHTTPRoutes(pinner *runtime.Pinner, routes map[string]*Instance) C.List_HttpRoute {
+	if len(routes) == 0 {
+		return C.List_HttpRoute{}
+	}
+	list := make([]C.HttpRoute, 0, len(routes))
+	for authority, instance := range routes {
+		list = append(list, C.HttpRoute{
+			authority: pinString(pinner, authority),
+			instance:  instance.ptr,
+		})
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_HttpRoute{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinMemoryPreopens(pinner *runtime.Pinner, preopens []MemoryPreopen) C.List_MemoryPreopen {
+	if len(preopens) == 0 {
+		return C.List_MemoryPreopen{}
+	}
+	list := make([]C.MemoryPreopen, len(preopens))
+	for i, p := range preopens {
+		list[i] = C.MemoryPreopen{
+			guest_path: pinString(pinner, p.GuestPath),
+		}
+		if len(p.Tar) > 0 {
+			list[i].tar = pinBytes(pinner, p.Tar)
+		}
+		if len(p.Files) == 0 {
+			continue
+		}
+		files := make([]C.MemoryFile, 0, len(p.Files))
+		for path, contents := range p.Files {
+			files = append(files, C.MemoryFile{
+				path:     pinString(pinner, path),
+				contents: pinBytes(pinner, contents),
+			})
+		}
+		filesPtr := unsafe.SliceData(files)
+		pinner.Pin(filesPtr)
+		list[i].files = C.List_MemoryFile{
+			ptr: filesPtr,
+			len: C.uintptr_t(len(files)),
+		}
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_MemoryPreopen{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinKeyValues(pinner *runtime.Pinner, m map[string]string) C.List_KeyValue {
+	if len(m) == 0 {
+		return C.List_KeyValue{}
+	}
+	list := make([]C.KeyValue, 0, len(m))
+	for k, v := range m {
+		list = append(list, C.KeyValue{
+			key:   pinString(pinner, k),
+			value: pinString(pinner, v),
+		})
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_KeyValue{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinKeyValueEntries(pinner *runtime.Pinner, buckets map[string]map[string][]byte) C.List_KeyValueEntry {
+	list := make([]C.KeyValueEntry, 0, len(buckets))
+	for bucket, entries := range buckets {
+		for key, value := range entries {
+			list = append(list, C.KeyValueEntry{
+				bucket: pinString(pinner, bucket),
+				key:    pinString(pinner, key),
+				value:  pinBytes(pinner, value),
+			})
+		}
+	}
+	if len(list) == 0 {
+		return C.List_KeyValueEntry{}
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_KeyValueEntry{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinBlobContainers(pinner *runtime.Pinner, containers []BlobContainer) C.List_BlobContainer {
+	if len(containers) == 0 {
+		return C.List_BlobContainer{}
+	}
+	list := make([]C.BlobContainer, len(containers))
+	for i, c := range containers {
+		list[i] = C.BlobContainer{
+			name: pinString(pinner, c.Name),
+		}
+		if c.Dir != "" {
+			list[i].dir = pinString(pinner, c.Dir)
+		}
+		if len(c.Objects) == 0 {
+			continue
+		}
+		objects := make([]C.BlobObject, 0, len(c.Objects))
+		for name, data := range c.Objects {
+			objects = append(objects, C.BlobObject{
+				name: pinString(pinner, name),
+				data: pinBytes(pinner, data),
+			})
+		}
+		objectsPtr := unsafe.SliceData(objects)
+		pinner.Pin(objectsPtr)
+		list[i].objects = C.List_BlobObject{
+			ptr: objectsPtr,
+			len: C.uintptr_t(len(objects)),
+		}
+	}
+	ptr := unsafe.SliceData(list)
+	pinner.Pin(ptr)
+	return C.List_BlobContainer{
+		ptr: ptr,
+		len: C.uintptr_t(len(list)),
+	}
+}
+
+func pinMessage(pinner *runtime.Pinner, msg Message) C.Message {
+	message := C.Message{
+		data:     pinBytes(pinner, msg.Data),
+		metadata: pinKeyValues(pinner, msg.Metadata),
+	}
+	if msg.Topic != "" {
+		message.topic = pinString(pinner, msg.Topic)
+	}
+	if msg.ReplyTo != "" {
+		message.reply_to = pinString(pinner, msg.ReplyTo)
+	}
+	if msg.ContentType != "" {
+		message.content_type = pinString(pinner, msg.ContentType)
+	}
+	return message
 }
 
 // NewInstance instantiates a new Wasm component in `wadge` runtime given a `Config`.
@@ -197,16 +929,93 @@ func NewInstance(conf *Config) (*Instance, error) {
 		if len(conf.Wasm) > 0 {
 			wasm = conf.Wasm
 		}
+	} else {
+		conf = &Config{}
 	}
 	wasmPtr := unsafe.SliceData(wasm)
 	pinner.Pin(wasmPtr)
-	ptr := C.instance_new(C.Config{
+	config := C.Config{
 		wasm: C.List_u8{
 			ptr: (*C.uchar)(wasmPtr),
 			len: C.uintptr_t(len(wasm)),
 		},
-	})
+		env:                 pinKeyValues(&pinner, conf.Env),
+		inherit_env:         C.uint32_t(conf.InheritEnv),
+		inherit_env_allow:   pinStrings(&pinner, conf.InheritEnvAllow),
+		args:                pinStrings(&pinner, conf.Args),
+		preopens:            pinPreopens(&pinner, conf.Preopens),
+		memory_preopens:     pinMemoryPreopens(&pinner, conf.MemoryPreopens),
+		stdin:               C.uint32_t(conf.Stdin),
+		stdout:              C.uint32_t(conf.Stdout),
+		stderr:              C.uint32_t(conf.Stderr),
+		network:             C.uint32_t(conf.Network),
+		network_allow:       pinNetworkRules(&pinner, conf.NetworkAllow),
+		hosts:               pinHosts(&pinner, conf.Hosts),
+		resolve_fallthrough: C.bool(conf.ResolveFallthrough),
+		http_routes:         pinHTTPRoutes(&pinner, conf.HTTPRoutes),
+		http_policy:         C.uint32_t(conf.HTTPPolicy),
+		http_allow:          pinStrings(&pinner, conf.HTTPAllow),
+		keyvalue:            pinKeyValueEntries(&pinner, conf.KeyValue),
+		keyvalue_backend:    C.uint32_t(conf.KeyValueBackend),
+		runtime_config:      pinKeyValues(&pinner, conf.RuntimeConfig),
+		blobstore:           pinBlobContainers(&pinner, conf.BlobContainers),
+
+		http_between_bytes_timeout_ns: C.uint64_t(conf.HTTPBetweenBytesTimeout.Nanoseconds()),
+		http_field_size_limit:         C.uintptr_t(conf.HTTPFieldSizeLimit),
+	}
+	if conf.Cwd != "" {
+		config.cwd = pinString(&pinner, conf.Cwd)
+	}
+	if len(conf.StdinBytes) > 0 {
+		config.stdin_bytes = pinBytes(&pinner, conf.StdinBytes)
+	}
+	if conf.Clock != nil {
+		config.clock = C.Clock_Virtual
+		config.clock_wall_ns = C.uint64_t(conf.Clock.Start.UnixNano())
+		config.clock_auto_advance = C.bool(conf.Clock.AutoAdvance)
+	}
+	if conf.RandomSeed != nil {
+		config.random_seeded = true
+		config.random_seed = C.uint64_t(*conf.RandomSeed)
+	}
+	if conf.KeyValuePath != "" {
+		config.keyvalue_path = pinString(&pinner, conf.KeyValuePath)
+	}
+	if conf.HTTPDefaultAuthority != "" {
+		config.http_default_authority = pinString(&pinner, conf.HTTPDefaultAuthority)
+	}
+	if conf.Cassette != nil {
+		config.cassette = pinString(&pinner, conf.Cassette.Path)
+		config.cassette_mode = C.uint32_t(conf.Cassette.Mode)
+		config.cassette_match_headers = pinStrings(&pinner, conf.Cassette.MatchHeaders)
+	}
+	instance := &Instance{}
+	if conf.OutgoingHTTPHandler != nil {
+		// handle is stored in C memory, since Go pointers cannot be retained by C
+		instance.outgoingHTTPHandler = cgo.NewHandle(conf.OutgoingHTTPHandler)
+		instance.outgoingHTTPHandlerData = C.malloc(C.size_t(unsafe.Sizeof(instance.outgoingHTTPHandler)))
+		*(*cgo.Handle)(instance.outgoingHTTPHandlerData) = instance.outgoingHTTPHandler
+		config.outgoing_http_handler = C.OutgoingHttpHandler(C.wadgeOutgoingHTTPHandler)
+		config.outgoing_http_handler_data = instance.outgoingHTTPHandlerData
+	}
+	if len(conf.MessagingReplyHandlers) > 0 {
+		topics := make([]string, 0, len(conf.MessagingReplyHandlers))
+		for topic := range conf.MessagingReplyHandlers {
+			topics = append(topics, topic)
+		}
+		// handle is stored in C memory, since Go pointers cannot be retained by C
+		instance.messagingReplyHandler = cgo.NewHandle(conf.MessagingReplyHandlers)
+		instance.messagingReplyHandlerData = C.malloc(C.size_t(unsafe.Sizeof(instance.messagingReplyHandler)))
+		*(*cgo.Handle)(instance.messagingReplyHandlerData) = instance.messagingReplyHandler
+		config.messaging_reply_topics = pinStrings(&pinner, topics)
+		config.messaging_reply_handler = C.MessagingReplyHandler(C.wadgeMessagingReplyHandler)
+		config.messaging_reply_handler_data = instance.messagingReplyHandlerData
+	}
+	ptr := C.instance_new(config)
+	runtime.KeepAlive(conf.HTTPRoutes)
 	if ptr == nil {
+		instance.freeOutgoingHTTPHandler()
+		instance.freeMessagingReplyHandler()
 		n := C.error_len()
 		buf := make([]C.char, n)
 		if n = C.error_take(unsafe.SliceData(buf), n); n > 0 {
@@ -216,30 +1025,443 @@ func NewInstance(conf *Config) (*Instance, error) {
 			return nil, errors.New("failed to create an instance")
 		}
 	}
-	instance := &Instance{ptr: ptr}
+	instance.ptr = ptr
 	runtime.SetFinalizer(instance, func(instance *Instance) {
 		C.instance_free(instance.ptr)
+		instance.freeOutgoingHTTPHandler()
+		instance.freeMessagingReplyHandler()
 	})
 	return instance, nil
 }
 
+// ExitError is returned by `Instance.Call` if the guest exits via `wasi:cli/exit`
+type ExitError struct {
+	// Code is the guest exit code, 0 on success
+	Code int32
+}
+
+func (e *ExitError) Error() string {
+	return fmt.Sprintf("guest exited with code %d", e.Code)
+}
+
 // Call calls function `name` within `instance` with arguments passed according to
-// `cabish` specification
+// `cabish` specification.
+// If the guest exits via `wasi:cli/exit`, the returned error is `*ExitError`.
 func (i Instance) Call(instance string, name string, args ...unsafe.Pointer) error {
 	instanceC := C.CString(instance)
 	defer C.free(unsafe.Pointer(instanceC))
 	nameC := C.CString(name)
 	defer C.free(unsafe.Pointer(nameC))
 
-	if !C.instance_call(i.ptr, instanceC, nameC, unsafe.SliceData(args)) {
-		n := C.error_len()
-		buf := make([]C.char, n)
-		if n = C.error_take(unsafe.SliceData(buf), n); n > 0 {
-			err := errors.New(C.GoStringN(unsafe.SliceData(buf), C.int(n)))
-			return fmt.Errorf("failed to call function on an instance: %w", err)
-		} else {
-			return errors.New("failed to call function on an instance")
+	var code C.int32_t
+	switch C.instance_call(i.ptr, instanceC, nameC, unsafe.SliceData(args), &code) {
+	case C.CallStatus_Ok:
+		return nil
+	case C.CallStatus_Exit:
+		return &ExitError{Code: int32(code)}
+	}
+	n := C.error_len()
+	buf := make([]C.char, n)
+	if n = C.error_take(unsafe.SliceData(buf), n); n > 0 {
+		err := errors.New(C.GoStringN(unsafe.SliceData(buf), C.int(n)))
+		return fmt.Errorf("failed to call function on an instance: %w", err)
+	} else {
+		return errors.New("failed to call function on an instance")
+	}
+}
+
+// ReadFile reads file at `path` from the in-memory filesystem preopened at `guestPath`
+func (i Instance) ReadFile(guestPath string, path string) ([]byte, error) {
+	guestPathC := C.CString(guestPath)
+	defer C.free(unsafe.Pointer(guestPathC))
+	pathC := C.CString(path)
+	defer C.free(unsafe.Pointer(pathC))
+
+	var n C.uintptr_t
+	if !C.instance_fs_read(i.ptr, guestPathC, pathC, nil, 0, &n) {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to read file: %w", err)
+		}
+		return nil, errors.New("failed to read file")
+	}
+	buf := make([]byte, n)
+	if n == 0 {
+		return buf, nil
+	}
+	if !C.instance_fs_read(i.ptr, guestPathC, pathC, (*C.uchar)(unsafe.SliceData(buf)), n, &n) {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to read file: %w", err)
+		}
+		return nil, errors.New("failed to read file")
+	}
+	return buf[:min(int(n), len(buf))], nil
+}
+
+// WriteFile writes `contents` to file at `path` in the in-memory filesystem preopened at `guestPath`
+func (i Instance) WriteFile(guestPath string, path string, contents []byte) error {
+	var pinner runtime.Pinner
+	defer pinner.Unpin()
+
+	guestPathC := C.CString(guestPath)
+	defer C.free(unsafe.Pointer(guestPathC))
+	pathC := C.CString(path)
+	defer C.free(unsafe.Pointer(pathC))
+
+	if !C.instance_fs_write(i.ptr, guestPathC, pathC, pinBytes(&pinner, contents)) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to write file: %w", err)
+		}
+		return errors.New("failed to write file")
+	}
+	return nil
+}
+
+// readBuf calls `f` with a buffer large enough to hold all `n` bytes it reports
+func readBuf(f func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool) ([]byte, bool) {
+	var buf []byte
+	for {
+		var n C.uintptr_t
+		if !f((*C.uchar)(unsafe.SliceData(buf)), C.uintptr_t(len(buf)), &n) {
+			return nil, false
+		}
+		if int(n) <= len(buf) {
+			return buf[:n], true
 		}
+		buf = make([]byte, n)
+	}
+}
+
+// splitNul splits NUL-terminated strings in `buf`
+func splitNul(buf []byte) []string {
+	ss := strings.Split(string(buf), "\x00")
+	return ss[:len(ss)-1]
+}
+
+// KeyValueBuckets returns identifiers of all guest `wasi:keyvalue` buckets.
+// KeyValueBuckets is safe to call concurrently with guest function calls.
+func (i Instance) KeyValueBuckets() ([]string, error) {
+	buf, ok := readBuf(func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool {
+		return C.instance_keyvalue_buckets(i.ptr, buf, len, n)
+	})
+	if !ok {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to list buckets: %w", err)
+		}
+		return nil, errors.New("failed to list buckets")
+	}
+	return splitNul(buf), nil
+}
+
+// KeyValueKeys returns all keys in guest `wasi:keyvalue` bucket `bucket`.
+// KeyValueKeys is safe to call concurrently with guest function calls.
+func (i Instance) KeyValueKeys(bucket string) ([]string, error) {
+	bucketC := C.CString(bucket)
+	defer C.free(unsafe.Pointer(bucketC))
+
+	buf, ok := readBuf(func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool {
+		return C.instance_keyvalue_keys(i.ptr, bucketC, buf, len, n)
+	})
+	if !ok {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to list keys: %w", err)
+		}
+		return nil, errors.New("failed to list keys")
+	}
+	return splitNul(buf), nil
+}
+
+// KeyValueGet returns value of `key` in guest `wasi:keyvalue` bucket `bucket`,
+// it fails if the key does not exist.
+// KeyValueGet is safe to call concurrently with guest function calls.
+func (i Instance) KeyValueGet(bucket string, key string) ([]byte, error) {
+	bucketC := C.CString(bucket)
+	defer C.free(unsafe.Pointer(bucketC))
+	keyC := C.CString(key)
+	defer C.free(unsafe.Pointer(keyC))
+
+	buf, ok := readBuf(func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool {
+		return C.instance_keyvalue_get(i.ptr, bucketC, keyC, buf, len, n)
+	})
+	if !ok {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to get value: %w", err)
+		}
+		return nil, errors.New("failed to get value")
+	}
+	return buf, nil
+}
+
+// KeyValueSet sets value of `key` in guest `wasi:keyvalue` bucket `bucket` to `value`.
+// KeyValueSet is safe to call concurrently with guest function calls.
+func (i Instance) KeyValueSet(bucket string, key string, value []byte) error {
+	var pinner runtime.Pinner
+	defer pinner.Unpin()
+
+	bucketC := C.CString(bucket)
+	defer C.free(unsafe.Pointer(bucketC))
+	keyC := C.CString(key)
+	defer C.free(unsafe.Pointer(keyC))
+
+	if !C.instance_keyvalue_set(i.ptr, bucketC, keyC, pinBytes(&pinner, value)) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to set value: %w", err)
+		}
+		return errors.New("failed to set value")
+	}
+	return nil
+}
+
+// KeyValueDelete deletes `key` from guest `wasi:keyvalue` bucket `bucket`.
+// KeyValueDelete is safe to call concurrently with guest function calls.
+func (i Instance) KeyValueDelete(bucket string, key string) error {
+	bucketC := C.CString(bucket)
+	defer C.free(unsafe.Pointer(bucketC))
+	keyC := C.CString(key)
+	defer C.free(unsafe.Pointer(keyC))
+
+	if !C.instance_keyvalue_delete(i.ptr, bucketC, keyC) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to delete key: %w", err)
+		}
+		return errors.New("failed to delete key")
 	}
 	return nil
 }
+
+// BlobContainers returns names of all guest `wasi:blobstore` containers.
+// BlobContainers is safe to call concurrently with guest function calls.
+func (i Instance) BlobContainers() ([]string, error) {
+	buf, ok := readBuf(func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool {
+		return C.instance_blobstore_containers(i.ptr, buf, len, n)
+	})
+	if !ok {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to list containers: %w", err)
+		}
+		return nil, errors.New("failed to list containers")
+	}
+	return splitNul(buf), nil
+}
+
+// BlobObjects returns names of all objects in guest `wasi:blobstore` container `container`.
+// BlobObjects is safe to call concurrently with guest function calls.
+func (i Instance) BlobObjects(container string) ([]string, error) {
+	containerC := C.CString(container)
+	defer C.free(unsafe.Pointer(containerC))
+
+	buf, ok := readBuf(func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool {
+		return C.instance_blobstore_objects(i.ptr, containerC, buf, len, n)
+	})
+	if !ok {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to list objects: %w", err)
+		}
+		return nil, errors.New("failed to list objects")
+	}
+	return splitNul(buf), nil
+}
+
+// BlobRead returns contents of object `name` in guest `wasi:blobstore` container `container`,
+// it fails if the object does not exist.
+// BlobRead is safe to call concurrently with guest function calls.
+func (i Instance) BlobRead(container string, name string) ([]byte, error) {
+	containerC := C.CString(container)
+	defer C.free(unsafe.Pointer(containerC))
+	nameC := C.CString(name)
+	defer C.free(unsafe.Pointer(nameC))
+
+	buf, ok := readBuf(func(buf *C.uchar, len C.uintptr_t, n *C.uintptr_t) C.bool {
+		return C.instance_blobstore_read(i.ptr, containerC, nameC, buf, len, n)
+	})
+	if !ok {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to read object: %w", err)
+		}
+		return nil, errors.New("failed to read object")
+	}
+	return buf, nil
+}
+
+// BlobWrite creates or replaces object `name` in guest `wasi:blobstore` container `container`.
+// BlobWrite is safe to call concurrently with guest function calls.
+func (i Instance) BlobWrite(container string, name string, data []byte) error {
+	var pinner runtime.Pinner
+	defer pinner.Unpin()
+
+	containerC := C.CString(container)
+	defer C.free(unsafe.Pointer(containerC))
+	nameC := C.CString(name)
+	defer C.free(unsafe.Pointer(nameC))
+
+	if !C.instance_blobstore_write(i.ptr, containerC, nameC, pinBytes(&pinner, data)) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to write object: %w", err)
+		}
+		return errors.New("failed to write object")
+	}
+	return nil
+}
+
+// BlobDelete deletes object `name` from guest `wasi:blobstore` container `container`.
+// BlobDelete is safe to call concurrently with guest function calls.
+func (i Instance) BlobDelete(container string, name string) error {
+	containerC := C.CString(container)
+	defer C.free(unsafe.Pointer(containerC))
+	nameC := C.CString(name)
+	defer C.free(unsafe.Pointer(nameC))
+
+	if !C.instance_blobstore_delete(i.ptr, containerC, nameC) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to delete object: %w", err)
+		}
+		return errors.New("failed to delete object")
+	}
+	return nil
+}
+
+// PublishedMessages returns all messages published by the guest via `wasi:messaging`
+// in order of publication, including requests and replies.
+// PublishedMessages is safe to call concurrently with guest function calls.
+func (i Instance) PublishedMessages() ([]Message, error) {
+	var messages []Message
+	h := cgo.NewHandle(&messages)
+	defer h.Delete()
+
+	if !C.instance_messaging_published(i.ptr, C.MessageVisitor(C.wadgeMessageVisitor), unsafe.Pointer(&h)) {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to list published messages: %w", err)
+		}
+		return nil, errors.New("failed to list published messages")
+	}
+	return messages, nil
+}
+
+// TakePublishedMessages returns all messages published by the guest via `wasi:messaging`
+// in order of publication and removes them, so that subsequent calls only return new messages.
+// TakePublishedMessages is safe to call concurrently with guest function calls.
+func (i Instance) TakePublishedMessages() ([]Message, error) {
+	var messages []Message
+	h := cgo.NewHandle(&messages)
+	defer h.Delete()
+
+	if !C.instance_messaging_take_published(i.ptr, C.MessageVisitor(C.wadgeMessageVisitor), unsafe.Pointer(&h)) {
+		if err := takeError(); err != nil {
+			return nil, fmt.Errorf("failed to take published messages: %w", err)
+		}
+		return nil, errors.New("failed to take published messages")
+	}
+	return messages, nil
+}
+
+// HandleMessage delivers `msg` to the `wasi:messaging/incoming-handler` export of the guest
+func (i Instance) HandleMessage(msg Message) error {
+	var pinner runtime.Pinner
+	defer pinner.Unpin()
+
+	if !C.instance_messaging_handle(i.ptr, pinMessage(&pinner, msg)) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to handle message: %w", err)
+		}
+		return errors.New("failed to handle message")
+	}
+	return nil
+}
+
+// PushStdin appends `buf` to guest stdin, which must be configured with `InputPipe`.
+// PushStdin is safe to call concurrently with guest function calls.
+func (i Instance) PushStdin(buf []byte) error {
+	var pinner runtime.Pinner
+	defer pinner.Unpin()
+
+	if !C.instance_stdin_push(i.ptr, pinBytes(&pinner, buf)) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to push stdin: %w", err)
+		}
+		return errors.New("failed to push stdin")
+	}
+	return nil
+}
+
+// CloseStdin closes guest stdin, which must be configured with `InputPipe`.
+// CloseStdin is safe to call concurrently with guest function calls.
+func (i Instance) CloseStdin() error {
+	if !C.instance_stdin_close(i.ptr) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to close stdin: %w", err)
+		}
+		return errors.New("failed to close stdin")
+	}
+	return nil
+}
+
+// Flush writes outgoing `wasi:http` exchanges recorded since the last flush to
+// `Config.Cassette`, if any, and syncs the HAR log set in `WADGE_HAR`, if any, to disk.
+// Recorded exchanges are also written once the instance is freed.
+func (i Instance) Flush() error {
+	if !C.instance_flush(i.ptr) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to flush instance: %w", err)
+		}
+		return errors.New("failed to flush instance")
+	}
+	return nil
+}
+
+// SetClock sets virtual wall clock time to `t`, monotonic clock is not affected.
+// Instance must be configured with `Config.Clock`.
+// SetClock is safe to call concurrently with guest function calls.
+func (i Instance) SetClock(t time.Time) error {
+	if !C.instance_clock_set(i.ptr, C.uint64_t(t.UnixNano())) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to set clock: %w", err)
+		}
+		return errors.New("failed to set clock")
+	}
+	return nil
+}
+
+// AdvanceClock advances virtual wall and monotonic clocks by `d`.
+// Instance must be configured with `Config.Clock`.
+// AdvanceClock is safe to call concurrently with guest function calls.
+func (i Instance) AdvanceClock(d time.Duration) error {
+	if !C.instance_clock_advance(i.ptr, C.uint64_t(d.Nanoseconds())) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to advance clock: %w", err)
+		}
+		return errors.New("failed to advance clock")
+	}
+	return nil
+}
+
+// SetClockAutoAdvance configures whether guest waiting on `monotonic-clock`
+// pollables advances the virtual clock.
+// Instance must be configured with `Config.Clock`.
+func (i Instance) SetClockAutoAdvance(autoAdvance bool) error {
+	if !C.instance_clock_set_auto_advance(i.ptr, C.bool(autoAdvance)) {
+		if err := takeError(); err != nil {
+			return fmt.Errorf("failed to configure clock auto-advance: %w", err)
+		}
+		return errors.New("failed to configure clock auto-advance")
+	}
+	return nil
+}
+
+// TakeStdout takes all bytes captured from guest stdout
+func (i Instance) TakeStdout() []byte {
+	buf := make([]byte, C.instance_stdout_len(i.ptr))
+	if len(buf) == 0 {
+		return buf
+	}
+	n := C.instance_stdout_take(i.ptr, (*C.uchar)(unsafe.SliceData(buf)), C.uintptr_t(len(buf)))
+	return buf[:n]
+}
+
+// TakeStderr takes all bytes captured from guest stderr
+func (i Instance) TakeStderr() []byte {
+	buf := make([]byte, C.instance_stderr_len(i.ptr))
+	if len(buf) == 0 {
+		return buf
+	}
+	n := C.instance_stderr_take(i.ptr, (*C.uchar)(unsafe.SliceData(buf)), C.uintptr_t(len(buf)))
+	return buf[:n]
+}